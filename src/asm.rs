@@ -0,0 +1,771 @@
+//! A two-pass assembler for the mnemonics `Instruction`'s `Debug` impl
+//! prints, so the output of `chip8-disasm --plain` can be fed back in.
+//!
+//! ```text
+//! ; comments run to the end of the line
+//! SPEED = 2                   ; a constant, `SPEED equ 2` works too
+//! include "sprites.asm"       ; relative to the including file
+//!
+//! start:
+//!     LD V[0x00], SPEED
+//!     LD I, ball
+//!     DRW V[0x00], V[0x01], 4
+//!     JP start
+//!
+//! ball:
+//!     db 0x60, 0xF0, 0xF0, 0x60
+//!     dw 0x1234, start
+//! ```
+//!
+//! Mnemonics and register names are case-insensitive, labels and constants
+//! are not. Registers are written `V[expression]` or `V0` to `VF`, and the
+//! ranges of `LD [I], V[0...x]` the same way. A range written from a plain
+//! `0` is the classic `FX55`/`FX65`, any other range is the XO-CHIP `5XY2`
+//! or `5XY3`, which is how the two are told apart in `Debug` output.
+//!
+//! Numbers are decimal, hexadecimal with `0x`, `#` or `$`, or binary with
+//! `0b`, and can be combined with `+ - * / % << >> & | ^ ~` and parentheses.
+//! `db` takes bytes and strings, `dw` big-endian words and `org ADDRESS`
+//! continues at another address.
+//!
+//! The first pass splits the lines into statements, lays out their
+//! addresses and collects the symbols. The second evaluates the operands,
+//! now that every label is known, and encodes the statements.
+
+use disasm::ORIGIN;
+use instruction::*;
+use symbols::{Location, SymbolMap};
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+const MEMORY_END: u32 = 0x10000;
+
+/// How deep includes can nest, which stops files including themselves.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// How deep constants can refer to other constants, which stops constants
+/// defined in terms of themselves.
+const MAX_CONSTANT_DEPTH: usize = 32;
+
+/// The words that are operands of their own rather than symbols.
+const KEYWORDS: [&str; 8] = ["I", "DT", "ST", "K", "F", "HF", "B", "R"];
+
+const MNEMONICS: [&str; 29] = [
+    "CLS", "RET", "SCD", "SCU", "SCR", "SCL", "EXIT", "LOW", "HIGH", "JP", "CALL", "SE", "SNE",
+    "LD", "ADD", "OR", "AND", "XOR", "SUB", "SHR", "SUBN", "SHL", "RND", "DRW", "SKP", "SKNP",
+    "PLANE", "AUDIO", "PITCH",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub location: Location,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+impl error::Error for AsmError {}
+
+pub struct Program {
+    /// Where the ROM is loaded.
+    pub origin: u16,
+    pub rom: Vec<u8>,
+    pub symbols: SymbolMap,
+}
+
+/// Assembles `source`. Its includes are relative to the working directory.
+pub fn assemble(source: &str) -> Result<Program, Vec<AsmError>> {
+    assemble_source(source, Path::new("<input>"))
+}
+
+/// Assembles `source`, read from `path`, which names it in errors and which
+/// its includes are relative to.
+pub fn assemble_source(source: &str, path: &Path) -> Result<Program, Vec<AsmError>> {
+    let mut assembler = Assembler::new();
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    assembler.source(&path.display().to_string(), source, dir, 0);
+    assembler.finish()
+}
+
+enum Operand {
+    Register(String),
+    Range(String, String),
+    /// `[I]`, the memory I points at.
+    Indirect,
+    Keyword(&'static str),
+    /// `LONG address` of `LD I, LONG address`.
+    Long(String),
+    Value(String),
+}
+
+impl Operand {
+    fn parse(text: &str) -> Operand {
+        let upper = text.to_uppercase();
+        if upper == "[I]" {
+            return Operand::Indirect;
+        }
+        if let Some(&keyword) = KEYWORDS.iter().find(|&&keyword| keyword == upper) {
+            return Operand::Keyword(keyword);
+        }
+        if upper.starts_with("LONG ") {
+            return Operand::Long(text[5..].trim().to_string());
+        }
+        if upper.starts_with("V[") && upper.ends_with(']') {
+            let inner = &text[2..text.len() - 1];
+            return match inner.find("...") {
+                Some(dots) => Operand::Range(
+                    inner[..dots].trim().to_string(),
+                    inner[dots + 3..].trim().to_string(),
+                ),
+                None => Operand::Register(inner.trim().to_string()),
+            };
+        }
+        let chars: Vec<char> = upper.chars().collect();
+        if let ['V', digit] = chars.as_slice() {
+            if digit.is_ascii_hexdigit() {
+                return Operand::Register(format!("0x{}", digit));
+            }
+        }
+        Operand::Value(text.to_string())
+    }
+}
+
+enum Datum {
+    Value(String),
+    Text(Vec<u8>),
+}
+
+enum Body {
+    Instruction(String, Vec<Operand>),
+    Bytes(Vec<Datum>),
+    Words(Vec<String>),
+}
+
+struct Statement {
+    address: u16,
+    location: Location,
+    body: Body,
+}
+
+enum Symbol {
+    Label(u16, Location),
+    Constant(String, Location),
+}
+
+impl Symbol {
+    fn location(&self) -> &Location {
+        match self {
+            Symbol::Label(_, location) | Symbol::Constant(_, location) => location,
+        }
+    }
+}
+
+struct Assembler {
+    statements: Vec<Statement>,
+    symbols: HashMap<String, Symbol>,
+    errors: Vec<AsmError>,
+    address: u32,
+}
+
+impl Assembler {
+    fn new() -> Assembler {
+        Assembler {
+            statements: Vec::new(),
+            symbols: HashMap::new(),
+            errors: Vec::new(),
+            address: ORIGIN as u32,
+        }
+    }
+
+    fn error(&mut self, location: &Location, message: String) {
+        self.errors.push(AsmError {
+            location: location.clone(),
+            message,
+        });
+    }
+
+    /// The first pass over the lines of a file.
+    fn source(&mut self, file: &str, text: &str, dir: &Path, depth: usize) {
+        for (n, line) in text.lines().enumerate() {
+            let location = Location {
+                file: file.to_string(),
+                line: n + 1,
+            };
+            if let Err(message) = self.line(&location, line, dir, depth) {
+                self.error(&location, message);
+            }
+        }
+    }
+
+    fn line(&mut self, location: &Location, line: &str, dir: &Path, depth: usize) -> Result<(), String> {
+        let mut line = strip_comment(line).trim();
+
+        if let Some(colon) = line.find(':') {
+            if is_name(&line[..colon]) {
+                let label = Symbol::Label(self.address as u16, location.clone());
+                self.define(&line[..colon], label)?;
+                line = line[colon + 1..].trim();
+            }
+        }
+        if line.is_empty() {
+            return Ok(());
+        }
+
+        let (word, rest) = match line.find(char::is_whitespace) {
+            Some(space) => (&line[..space], line[space..].trim()),
+            None => (line, ""),
+        };
+        // Only a line that starts with a name and an `=` defines a constant,
+        // an `=` further on can be part of a string.
+        let name_end = line
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+            .unwrap_or(line.len());
+        let constant = if let Some(value) = line[name_end..].trim_start().strip_prefix('=') {
+            Some((&line[..name_end], value))
+        } else if first_word(rest).eq_ignore_ascii_case("equ") {
+            Some((word, &rest[3..]))
+        } else {
+            None
+        };
+        if let Some((name, value)) = constant {
+            if !is_name(name) {
+                return Err(format!("invalid constant name {:?}", name));
+            }
+            let constant = Symbol::Constant(value.trim().to_string(), location.clone());
+            return self.define(name, constant);
+        }
+
+        let body = match word.to_lowercase().as_str() {
+            "include" => {
+                let path = dir.join(String::from_utf8_lossy(&string(rest)?).as_ref());
+                if depth >= MAX_INCLUDE_DEPTH {
+                    return Err("includes nest too deeply".to_string());
+                }
+                let source = fs::read_to_string(&path)
+                    .map_err(|err| format!("could not include {:?}: {}", path, err))?;
+                let dir = path.parent().unwrap_or_else(|| Path::new(""));
+                self.source(&path.display().to_string(), &source, dir, depth + 1);
+                return Ok(());
+            }
+            "org" => {
+                let address = self.evaluate(rest, 0)?;
+                if address < ORIGIN as i64 || address >= MEMORY_END as i64 {
+                    return Err(format!(
+                        "org {:#06X} is outside the program, which starts at {:#06X}",
+                        address, ORIGIN
+                    ));
+                }
+                self.address = address as u32;
+                return Ok(());
+            }
+            "db" => Body::Bytes(
+                operands(rest)
+                    .into_iter()
+                    .map(|item| {
+                        if item.starts_with('"') {
+                            string(&item).map(Datum::Text)
+                        } else {
+                            Ok(Datum::Value(item))
+                        }
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            "dw" => Body::Words(operands(rest)),
+            _ => Body::Instruction(
+                word.to_uppercase(),
+                operands(rest).iter().map(|operand| Operand::parse(operand)).collect(),
+            ),
+        };
+
+        let size = match body {
+            Body::Instruction(_, ref operands) => {
+                if operands.iter().any(|operand| matches!(operand, Operand::Long(_))) {
+                    4
+                } else {
+                    2
+                }
+            }
+            Body::Bytes(ref data) if data.is_empty() => return Err("db needs a value".to_string()),
+            Body::Bytes(ref data) => data
+                .iter()
+                .map(|datum| match datum {
+                    Datum::Value(_) => 1,
+                    Datum::Text(text) => text.len() as u32,
+                })
+                .sum(),
+            Body::Words(ref words) if words.is_empty() => return Err("dw needs a value".to_string()),
+            Body::Words(ref words) => 2 * words.len() as u32,
+        };
+        if self.address + size > MEMORY_END {
+            return Err("the program does not fit in memory".to_string());
+        }
+
+        self.statements.push(Statement {
+            address: self.address as u16,
+            location: location.clone(),
+            body,
+        });
+        self.address += size;
+        Ok(())
+    }
+
+    fn define(&mut self, name: &str, symbol: Symbol) -> Result<(), String> {
+        if let Some(existing) = self.symbols.get(name) {
+            return Err(format!("{} is already defined at {}", name, existing.location()));
+        }
+        self.symbols.insert(name.to_string(), symbol);
+        Ok(())
+    }
+
+    fn evaluate(&self, text: &str, depth: usize) -> Result<i64, String> {
+        evaluate(text, &|name| match self.symbols.get(name) {
+            Some(Symbol::Label(address, _)) => Ok(*address as i64),
+            Some(Symbol::Constant(_, _)) if depth >= MAX_CONSTANT_DEPTH => {
+                Err(format!("the constant {} is defined in terms of itself", name))
+            }
+            Some(Symbol::Constant(value, _)) => self.evaluate(value, depth + 1),
+            None => Err(format!("unknown symbol {}", name)),
+        })
+    }
+
+    fn value(&self, text: &str, min: i64, max: i64, what: &str) -> Result<i64, String> {
+        let value = self.evaluate(text, 0)?;
+        if value < min || value > max {
+            return Err(format!("{} does not fit in {}", value, what));
+        }
+        Ok(value)
+    }
+
+    fn register(&self, text: &str) -> Result<u8, String> {
+        self.value(text, 0, 0xF, "a register number").map(|value| value as u8)
+    }
+
+    fn nibble(&self, text: &str) -> Result<u8, String> {
+        self.value(text, 0, 0xF, "a nibble").map(|value| value as u8)
+    }
+
+    fn byte(&self, text: &str) -> Result<u8, String> {
+        self.value(text, -0x80, 0xFF, "a byte").map(|value| value as u8)
+    }
+
+    fn address(&self, text: &str) -> Result<u16, String> {
+        self.value(text, 0, 0xFFF, "a 12-bit address").map(|value| value as u16)
+    }
+
+    fn word(&self, text: &str) -> Result<u16, String> {
+        self.value(text, -0x8000, 0xFFFF, "a word").map(|value| value as u16)
+    }
+
+    /// The first register of a range that has to start at `V0`.
+    fn starts_at_v0(&self, text: &str) -> Result<(), String> {
+        match self.register(text)? {
+            0 => Ok(()),
+            _ => Err("the range has to start at V0".to_string()),
+        }
+    }
+
+    fn encode(&self, mnemonic: &str, operands: &[Operand]) -> Result<Instruction, String> {
+        use self::Operand::*;
+
+        let r = |text: &String| self.register(text);
+        let from_zero = |text: &String| text == "0";
+
+        Ok(match (mnemonic, operands) {
+            ("CLS", []) => Clear,
+            ("RET", []) => Return,
+            ("SCD", [Value(n)]) => ScrollDown(self.nibble(n)?),
+            ("SCU", [Value(n)]) => ScrollUp(self.nibble(n)?),
+            ("SCR", []) => ScrollRight,
+            ("SCL", []) => ScrollLeft,
+            ("EXIT", []) => Exit,
+            ("LOW", []) => LowRes,
+            ("HIGH", []) => HighRes,
+            ("JP", [Value(address)]) => Jump(self.address(address)?),
+            ("JP", [Register(x), Value(address)]) => {
+                if r(x)? != 0 {
+                    return Err("jumps can only be offset by V0".to_string());
+                }
+                JumpV0Address(self.address(address)?)
+            }
+            ("CALL", [Value(address)]) => Call(self.address(address)?),
+            ("SE", [Register(x), Value(kk)]) => SkipIfConstantEqual(r(x)?, self.byte(kk)?),
+            ("SE", [Register(x), Register(y)]) => SkipIfEqual(r(x)?, r(y)?),
+            ("SNE", [Register(x), Value(kk)]) => SkipIfConstantNotEqual(r(x)?, self.byte(kk)?),
+            ("SNE", [Register(x), Register(y)]) => SkipIfNotEqual(r(x)?, r(y)?),
+            ("LD", [Register(x), Value(kk)]) => LoadConstant(r(x)?, self.byte(kk)?),
+            ("LD", [Register(x), Register(y)]) => Load(r(x)?, r(y)?),
+            ("LD", [Keyword("I"), Value(address)]) => SetAddress(self.address(address)?),
+            ("LD", [Keyword("I"), Long(address)]) => {
+                SetLongAddress(self.value(address, 0, 0xFFFF, "a 16-bit address")? as u16)
+            }
+            ("LD", [Register(x), Keyword("DT")]) => LoadDelay(r(x)?),
+            ("LD", [Register(x), Keyword("K")]) => WaitForKey(r(x)?),
+            ("LD", [Keyword("DT"), Register(x)]) => SetDelay(r(x)?),
+            ("LD", [Keyword("ST"), Register(x)]) => SetSound(r(x)?),
+            ("LD", [Keyword("F"), Register(x)]) => SetFontLocation(r(x)?),
+            ("LD", [Keyword("HF"), Register(x)]) => SetBigFontLocation(r(x)?),
+            ("LD", [Keyword("B"), Register(x)]) => SetBCD(r(x)?),
+            ("LD", [Indirect, Register(x)]) => DumpRegisters(r(x)?),
+            ("LD", [Indirect, Range(x, y)]) if from_zero(x) => DumpRegisters(r(y)?),
+            ("LD", [Indirect, Range(x, y)]) => SaveRange(r(x)?, r(y)?),
+            ("LD", [Register(x), Indirect]) => LoadRegisters(r(x)?),
+            ("LD", [Range(x, y), Indirect]) if from_zero(x) => LoadRegisters(r(y)?),
+            ("LD", [Range(x, y), Indirect]) => LoadRange(r(x)?, r(y)?),
+            ("LD", [Keyword("R"), Register(x)]) => SaveFlags(r(x)?),
+            ("LD", [Keyword("R"), Range(x, y)]) => {
+                self.starts_at_v0(x)?;
+                SaveFlags(r(y)?)
+            }
+            ("LD", [Register(x), Keyword("R")]) => LoadFlags(r(x)?),
+            ("LD", [Range(x, y), Keyword("R")]) => {
+                self.starts_at_v0(x)?;
+                LoadFlags(r(y)?)
+            }
+            ("ADD", [Register(x), Value(kk)]) => AddConstant(r(x)?, self.byte(kk)?),
+            ("ADD", [Register(x), Register(y)]) => Add(r(x)?, r(y)?),
+            ("ADD", [Keyword("I"), Register(x)]) => AddAddress(r(x)?),
+            ("OR", [Register(x), Register(y)]) => Or(r(x)?, r(y)?),
+            ("AND", [Register(x), Register(y)]) => And(r(x)?, r(y)?),
+            ("XOR", [Register(x), Register(y)]) => Xor(r(x)?, r(y)?),
+            ("SUB", [Register(x), Register(y)]) => Sub(r(x)?, r(y)?),
+            ("SUBN", [Register(x), Register(y)]) => SubReverse(r(x)?, r(y)?),
+            ("SHR", [Register(x)]) => ShiftRight(r(x)?, r(x)?),
+            ("SHR", [Register(x), Register(y)]) => ShiftRight(r(x)?, r(y)?),
+            ("SHL", [Register(x)]) => ShiftLeft(r(x)?, r(x)?),
+            ("SHL", [Register(x), Register(y)]) => ShiftLeft(r(x)?, r(y)?),
+            ("RND", [Register(x), Value(kk)]) => RandomAnd(r(x)?, self.byte(kk)?),
+            ("DRW", [Register(x), Register(y), Value(n)]) => Draw(r(x)?, r(y)?, self.nibble(n)?),
+            ("SKP", [Register(x)]) => SkipIfPressed(r(x)?),
+            ("SKNP", [Register(x)]) => SkipIfNotPressed(r(x)?),
+            ("PLANE", [Value(n)]) => SelectPlanes(self.nibble(n)?),
+            ("AUDIO", []) => LoadAudio,
+            ("PITCH", [Register(x)]) => SetPitch(r(x)?),
+            _ if MNEMONICS.contains(&mnemonic) => {
+                return Err(format!("invalid operands for {}", mnemonic))
+            }
+            _ => return Err(format!("unknown instruction {}", mnemonic)),
+        })
+    }
+
+    fn bytes(&self, body: &Body) -> Result<Vec<u8>, String> {
+        let mut out = Vec::new();
+        match body {
+            Body::Instruction(mnemonic, operands) => {
+                out = self.encode(mnemonic, operands)?.encode();
+            }
+            Body::Bytes(data) => {
+                for datum in data {
+                    match datum {
+                        Datum::Value(value) => out.push(self.byte(value)?),
+                        Datum::Text(text) => out.extend_from_slice(text),
+                    }
+                }
+            }
+            Body::Words(words) => {
+                for word in words {
+                    let word = self.word(word)?;
+                    out.extend_from_slice(&[(word >> 8) as u8, word as u8]);
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// The second pass, which encodes the statements.
+    fn finish(mut self) -> Result<Program, Vec<AsmError>> {
+        let mut memory = vec![0u8; MEMORY_END as usize];
+        let mut written = vec![false; MEMORY_END as usize];
+        let mut end = ORIGIN as usize;
+        let mut symbols = SymbolMap::default();
+        let mut errors = Vec::new();
+
+        for statement in &self.statements {
+            let bytes = match self.bytes(&statement.body) {
+                Ok(bytes) => bytes,
+                Err(message) => {
+                    errors.push((statement.location.clone(), message));
+                    continue;
+                }
+            };
+
+            let start = statement.address as usize;
+            if let Some(overlap) = (start..start + bytes.len()).find(|&address| written[address]) {
+                let message = format!("this overwrites the code or data at {:#06X}", overlap);
+                errors.push((statement.location.clone(), message));
+                continue;
+            }
+            memory[start..start + bytes.len()].copy_from_slice(&bytes);
+            for flag in &mut written[start..start + bytes.len()] {
+                *flag = true;
+            }
+            end = end.max(start + bytes.len());
+
+            if let Body::Instruction(..) = statement.body {
+                symbols.lines.insert(statement.address, statement.location.clone());
+            }
+        }
+
+        for (location, message) in errors {
+            self.error(&location, message);
+        }
+        if !self.errors.is_empty() {
+            self.errors.sort_by(|a, b| a.location.cmp(&b.location));
+            return Err(self.errors);
+        }
+
+        for (name, symbol) in &self.symbols {
+            if let Symbol::Label(address, _) = symbol {
+                symbols.labels.insert(name.clone(), *address);
+            }
+        }
+        Ok(Program {
+            origin: ORIGIN,
+            rom: memory[ORIGIN as usize..end].to_vec(),
+            symbols,
+        })
+    }
+}
+
+/// Cuts off a `;` comment that is not inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (pos, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..pos],
+            _ => (),
+        }
+    }
+    line
+}
+
+fn first_word(text: &str) -> &str {
+    text.split_whitespace().next().unwrap_or("")
+}
+
+fn is_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        }
+        _ => false,
+    }
+}
+
+/// Splits operands at the commas outside of brackets, parentheses and
+/// strings.
+fn operands(text: &str) -> Vec<String> {
+    let mut operands = Vec::new();
+    let mut current = String::new();
+    let mut nesting = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in text.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '[' | '(' if !in_string => nesting += 1,
+            ']' | ')' if !in_string => nesting -= 1,
+            ',' if !in_string && nesting == 0 => {
+                operands.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => (),
+        }
+        current.push(c);
+    }
+    if !current.trim().is_empty() || !operands.is_empty() {
+        operands.push(current.trim().to_string());
+    }
+    operands
+}
+
+/// The bytes of a string literal with its `\"`, `\\`, `\n` and `\0`
+/// escapes resolved.
+fn string(text: &str) -> Result<Vec<u8>, String> {
+    let text = text.trim();
+    if text.len() < 2 || !text.starts_with('"') || !text.ends_with('"') {
+        return Err(format!("expected a string, found {:?}", text));
+    }
+    let mut out = Vec::new();
+    let mut chars = text[1..text.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' => match chars.next() {
+                Some('n') => '\n',
+                Some('0') => '\0',
+                Some(c @ '"') | Some(c @ '\\') => c,
+                _ => return Err(format!("invalid escape in {}", text)),
+            },
+            c => c,
+        };
+        let mut buffer = [0; 4];
+        out.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+    }
+    Ok(out)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Operator(&'static str),
+    Open,
+    Close,
+}
+
+const OPERATORS: [&str; 11] = ["<<", ">>", "+", "-", "*", "/", "%", "&", "|", "^", "~"];
+
+fn precedence(operator: &str) -> Option<u8> {
+    match operator {
+        "|" => Some(1),
+        "^" => Some(2),
+        "&" => Some(3),
+        "<<" | ">>" => Some(4),
+        "+" | "-" => Some(5),
+        "*" | "/" | "%" => Some(6),
+        _ => None,
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        let length = |rest: &str| {
+            rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+                .unwrap_or(rest.len())
+        };
+
+        let c = rest.chars().next().unwrap_or(' ');
+        let size = if c.is_ascii_digit() || c == '#' || c == '$' {
+            let size = if c == '#' || c == '$' { 1 + length(&rest[1..]) } else { length(rest) };
+            tokens.push(Token::Number(number(&rest[..size])?));
+            size
+        } else if is_name(&rest[..length(rest)]) {
+            let size = length(rest);
+            tokens.push(Token::Name(rest[..size].to_string()));
+            size
+        } else if c == '(' {
+            tokens.push(Token::Open);
+            1
+        } else if c == ')' {
+            tokens.push(Token::Close);
+            1
+        } else if let Some(&operator) = OPERATORS.iter().find(|&&op| rest.starts_with(op)) {
+            tokens.push(Token::Operator(operator));
+            operator.len()
+        } else {
+            return Err(format!("unexpected {:?} in {:?}", c, text));
+        };
+        rest = rest[size..].trim_start();
+    }
+    Ok(tokens)
+}
+
+fn number(text: &str) -> Result<i64, String> {
+    let lower = text.to_lowercase();
+    let (digits, radix) = if let Some(digits) = lower.strip_prefix("0x") {
+        (digits, 16)
+    } else if let Some(digits) = lower.strip_prefix("0b") {
+        (digits, 2)
+    } else if let Some(digits) = lower.strip_prefix(|c| c == '#' || c == '$') {
+        (digits, 16)
+    } else {
+        (&lower[..], 10)
+    };
+    i64::from_str_radix(&digits.replace('_', ""), radix)
+        .map_err(|_| format!("invalid number {}", text))
+}
+
+/// Evaluates the expression `text`, looking names up with `lookup`.
+fn evaluate(text: &str, lookup: &dyn Fn(&str) -> Result<i64, String>) -> Result<i64, String> {
+    let tokens = tokenize(text)?;
+    if tokens.is_empty() {
+        return Err("expected a value".to_string());
+    }
+    let mut parser = Parser {
+        tokens: &tokens,
+        pos: 0,
+        lookup,
+    };
+    let value = parser.binary(0)?;
+    match tokens.get(parser.pos) {
+        Some(_) => Err(format!("invalid expression {:?}", text)),
+        None => Ok(value),
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    lookup: &'a dyn Fn(&str) -> Result<i64, String>,
+}
+
+impl<'a> Parser<'a> {
+    fn binary(&mut self, min: u8) -> Result<i64, String> {
+        let mut left = self.unary()?;
+        while let Some(&Token::Operator(operator)) = self.tokens.get(self.pos) {
+            let precedence = match precedence(operator) {
+                Some(precedence) if precedence >= min => precedence,
+                _ => break,
+            };
+            self.pos += 1;
+            let right = self.binary(precedence + 1)?;
+            left = match operator {
+                "|" => left | right,
+                "^" => left ^ right,
+                "&" => left & right,
+                "<<" | ">>" if !(0..64).contains(&right) => {
+                    return Err(format!("cannot shift by {}", right))
+                }
+                "<<" => left << right,
+                ">>" => left >> right,
+                "+" => left.wrapping_add(right),
+                "-" => left.wrapping_sub(right),
+                "*" => left.wrapping_mul(right),
+                "/" | "%" if right == 0 => return Err("division by zero".to_string()),
+                "/" => left.wrapping_div(right),
+                _ => left.wrapping_rem(right),
+            };
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Number(value)) => Ok(value),
+            Some(Token::Name(name)) => (self.lookup)(&name),
+            Some(Token::Operator("-")) => Ok(self.unary()?.wrapping_neg()),
+            Some(Token::Operator("+")) => self.unary(),
+            Some(Token::Operator("~")) => Ok(!self.unary()?),
+            Some(Token::Open) => {
+                let value = self.binary(0)?;
+                match self.tokens.get(self.pos) {
+                    Some(Token::Close) => {
+                        self.pos += 1;
+                        Ok(value)
+                    }
+                    _ => Err("missing )".to_string()),
+                }
+            }
+            _ => Err("expected a value".to_string()),
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! Assembles CHIP-8 mnemonics into a ROM, see `chip8::asm` for the syntax.
+
+extern crate chip8;
+
+use chip8::asm;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+const USAGE: &str = "usage: chip8-asm [-o ROM] [--symbols FILE] SOURCE
+  the ROM defaults to SOURCE with the extension .ch8
+  --symbols writes the labels and the source line of every instruction";
+
+struct Options {
+    source_path: PathBuf,
+    output: PathBuf,
+    symbols: Option<String>,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut source_path = None;
+    let mut output = None;
+    let mut symbols = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(args.next().ok_or("-o needs a file")?),
+            "--symbols" => symbols = Some(args.next().ok_or("--symbols needs a file")?),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => source_path = Some(PathBuf::from(arg)),
+        }
+    }
+
+    let source_path = source_path.ok_or("no source file given")?;
+    let output = output
+        .map(PathBuf::from)
+        .unwrap_or_else(|| source_path.with_extension("ch8"));
+    Ok(Options {
+        source_path,
+        output,
+        symbols,
+    })
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("chip8-asm: {}", message);
+    process::exit(1);
+}
+
+fn write(path: &Path, data: &[u8]) {
+    if let Err(err) = fs::write(path, data) {
+        fail(format!("could not write {:?}: {}", path, err));
+    }
+}
+
+fn main() {
+    let options = parse_args().unwrap_or_else(|err| {
+        eprintln!("chip8-asm: {}\n{}", err, USAGE);
+        process::exit(2);
+    });
+
+    let source = fs::read_to_string(&options.source_path).unwrap_or_else(|err| {
+        fail(format!("could not read {:?}: {}", options.source_path, err))
+    });
+
+    let program = asm::assemble_source(&source, &options.source_path).unwrap_or_else(|errors| {
+        for error in &errors {
+            eprintln!("{}", error);
+        }
+        process::exit(1)
+    });
+
+    write(&options.output, &program.rom);
+    if let Some(path) = options.symbols {
+        write(Path::new(&path), program.symbols.to_string().as_bytes());
+    }
+}
@@ -0,0 +1,73 @@
+//! Disassembles a ROM, separating code from data by following control flow.
+
+extern crate chip8;
+
+use chip8::disasm::{Disassembly, ORIGIN};
+
+use std::env;
+use std::fs;
+use std::process;
+
+const USAGE: &str = "usage: chip8-disasm [--origin ADDRESS] [--entry ADDRESS]... [--plain] ROM
+  ADDRESS is hexadecimal, the origin defaults to 0x200
+  every --entry is followed in addition to the origin, e.g. jump table targets
+  --plain leaves out addresses and opcodes, so the output assembles with chip8-asm";
+
+fn parse_address(value: &str) -> Result<u16, String> {
+    let digits = value.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid address {:?}", value))
+}
+
+struct Options {
+    rom_path: String,
+    origin: u16,
+    entries: Vec<u16>,
+    plain: bool,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut rom_path = None;
+    let mut origin = ORIGIN;
+    let mut entries = Vec::new();
+    let mut plain = false;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--origin" => origin = parse_address(&args.next().ok_or("--origin needs an address")?)?,
+            "--entry" => {
+                entries.push(parse_address(&args.next().ok_or("--entry needs an address")?)?)
+            }
+            "--plain" => plain = true,
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => rom_path = Some(arg),
+        }
+    }
+
+    entries.insert(0, origin);
+    Ok(Options {
+        rom_path: rom_path.ok_or("no ROM given")?,
+        origin,
+        entries,
+        plain,
+    })
+}
+
+fn main() {
+    let options = parse_args().unwrap_or_else(|err| {
+        eprintln!("chip8-disasm: {}\n{}", err, USAGE);
+        process::exit(2);
+    });
+
+    let rom = fs::read(&options.rom_path).unwrap_or_else(|err| {
+        eprintln!("chip8-disasm: could not read {:?}: {}", options.rom_path, err);
+        process::exit(1);
+    });
+
+    let disassembly = Disassembly::with_entries(&rom, options.origin, &options.entries);
+    if options.plain {
+        print!("{}", disassembly.plain());
+    } else {
+        print!("{}", disassembly);
+    }
+}
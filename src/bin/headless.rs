@@ -0,0 +1,337 @@
+//! Runs a ROM without a window and writes out the final screen and
+//! registers, for golden image tests on machines without a display.
+
+extern crate chip8;
+
+use chip8::config::{self, Config};
+use chip8::coverage::Coverage;
+use chip8::movie::Movie;
+use chip8::profile::Profiler;
+use chip8::rom;
+use chip8::screen::Format;
+use chip8::trace::Tracer;
+use chip8::{Cpu, Error};
+
+use std::env;
+use std::fs;
+use std::process;
+use std::time::Instant;
+
+const DEFAULT_FRAMES: u64 = 600;
+
+/// How long `--benchmark` runs without `--frames` or `--instructions`, long
+/// enough for the timings to mean something.
+const BENCHMARK_INSTRUCTIONS: u64 = 50_000_000;
+
+/// The random seed without `--seed`, so that runs are reproducible.
+const DEFAULT_SEED: u64 = 0;
+
+/// How long to run for.
+enum Limit {
+    Frames(u64),
+    Instructions(u64),
+}
+
+/// Keys held during a range of frames, from `--keys`.
+struct KeyPress {
+    first: u64,
+    last: u64,
+    mask: u16,
+}
+
+struct Options {
+    game_path: String,
+    config: Config,
+    limit: Limit,
+    keys: Vec<KeyPress>,
+    movie: Option<String>,
+    output: Option<String>,
+    trace: Option<String>,
+    profile: Option<String>,
+    coverage: Option<String>,
+    benchmark: bool,
+}
+
+fn usage() -> String {
+    format!(
+        "usage: chip8-headless {} [--frames N | --instructions N] [--keys SCRIPT] \
+         [--play MOVIE] [--output FILE] [--trace TRACE] [--profile REPORT] [--coverage COVERAGE] [--benchmark] ROM\n{}\n  \
+         SCRIPT is a comma separated list of FRAME:KEYS or FIRST-LAST:KEYS,\n  \
+         KEYS are hex digits, e.g. 10-20:5,60:AB\n  \
+         FILE is written as PNG or PBM by its extension, ASCII art otherwise\n  \
+         TRACE is written as JSON lines for .jsonl, in the binary format otherwise\n  \
+         COVERAGE is an lcov tracefile for .info and .lcov, an annotated disassembly otherwise\n  \
+         --benchmark runs a second time without the decode cache and compares the speeds,\n  \
+         by default for {} instructions; it cannot be combined with --trace, --profile or --coverage\n  \
+         the random generator is seeded with {} unless --seed is given\n  \
+         ROM can also be an Octo (.8o) or assembly (.asm) source",
+        config::USAGE,
+        config::option_values(),
+        BENCHMARK_INSTRUCTIONS,
+        DEFAULT_SEED
+    )
+}
+
+fn parse_keys(script: &str) -> Result<Vec<KeyPress>, String> {
+    let mut presses = Vec::new();
+    for entry in script.split(',').filter(|entry| !entry.is_empty()) {
+        let mut parts = entry.splitn(2, ':');
+        let frames = parts.next().unwrap_or("");
+        let keys = parts
+            .next()
+            .ok_or_else(|| format!("missing keys in {:?}", entry))?;
+
+        let parse_frame = |frame: &str| {
+            frame
+                .parse::<u64>()
+                .map_err(|_| format!("invalid frame {:?} in {:?}", frame, entry))
+        };
+        let mut range = frames.splitn(2, '-');
+        let first = parse_frame(range.next().unwrap_or(""))?;
+        let last = match range.next() {
+            Some(last) => parse_frame(last)?,
+            None => first,
+        };
+
+        let mut mask = 0;
+        for key in keys.chars() {
+            let key = key
+                .to_digit(16)
+                .ok_or_else(|| format!("invalid key {:?} in {:?}", key, entry))?;
+            mask |= 1 << key;
+        }
+        presses.push(KeyPress { first, last, mask });
+    }
+    Ok(presses)
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut game_path = None;
+    let mut config = Config::default();
+    let mut limit = None;
+    let mut keys = Vec::new();
+    let mut movie = None;
+    let mut output = None;
+    let mut trace = None;
+    let mut profile = None;
+    let mut coverage = None;
+    let mut benchmark = false;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if config.parse_option(&arg, &mut args)? {
+            continue;
+        }
+        let mut number = |name: &str| -> Result<u64, String> {
+            let value = args.next().ok_or(format!("{} needs a number", name))?;
+            value
+                .parse()
+                .map_err(|_| format!("invalid number {:?} for {}", value, name))
+        };
+        match arg.as_str() {
+            "--frames" => limit = Some(Limit::Frames(number("--frames")?)),
+            "--instructions" => limit = Some(Limit::Instructions(number("--instructions")?)),
+            "--keys" => keys = parse_keys(&args.next().ok_or("--keys needs a script")?)?,
+            "--play" => movie = Some(args.next().ok_or("--play needs a movie file")?),
+            "--output" | "-o" => output = Some(args.next().ok_or("--output needs a file")?),
+            "--trace" => trace = Some(args.next().ok_or("--trace needs a file")?),
+            "--profile" => profile = Some(args.next().ok_or("--profile needs a file")?),
+            "--coverage" => coverage = Some(args.next().ok_or("--coverage needs a file")?),
+            "--benchmark" => benchmark = true,
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => game_path = Some(arg),
+        }
+    }
+
+    // Only the timed run would be instrumented, which makes the comparison
+    // meaningless.
+    if benchmark && (trace.is_some() || profile.is_some() || coverage.is_some()) {
+        return Err("--benchmark cannot be combined with --trace, --profile or --coverage".to_string());
+    }
+    let limit = limit.unwrap_or(if benchmark {
+        Limit::Instructions(BENCHMARK_INSTRUCTIONS)
+    } else {
+        Limit::Frames(DEFAULT_FRAMES)
+    });
+
+    if config.seed.is_none() {
+        config.seed = Some(DEFAULT_SEED);
+    }
+
+    Ok(Options {
+        game_path: game_path.ok_or("no ROM given")?,
+        config,
+        limit,
+        keys,
+        movie,
+        output,
+        trace,
+        profile,
+        coverage,
+        benchmark,
+    })
+}
+
+fn dump_registers(cpu: &Cpu) {
+    println!(
+        "PC={:#06X} I={:#06X} SP={} DT={} ST={}",
+        cpu.pc(),
+        cpu.i(),
+        cpu.sp(),
+        cpu.delay_timer(),
+        cpu.sound_timer()
+    );
+    let registers: Vec<String> = cpu
+        .registers()
+        .iter()
+        .enumerate()
+        .map(|(n, value)| format!("V{:X}={:#04X}", n, value))
+        .collect();
+    println!("{}", registers.join(" "));
+    let stack: Vec<String> = cpu
+        .stack()
+        .iter()
+        .map(|address| format!("{:#06X}", address))
+        .collect();
+    println!("stack=[{}]", stack.join(", "));
+}
+
+/// Runs `cpu` until `limit` is reached or the program exits, holding the
+/// keys `keys_for` returns for each frame. Returns how many instructions
+/// were executed.
+fn run<F>(cpu: &mut Cpu, limit: &Limit, keys_for: F) -> (u64, Result<(), Error>)
+where
+    F: Fn(u64) -> u16,
+{
+    let (frames, instructions) = match *limit {
+        Limit::Frames(frames) => (frames, u64::MAX),
+        Limit::Instructions(instructions) => (u64::MAX, instructions),
+    };
+
+    let mut executed = 0;
+    let mut frame = 0;
+    while frame < frames && executed < instructions && !cpu.exited() {
+        cpu.set_key_mask(keys_for(frame));
+        let left = (instructions - executed).min(u32::MAX as u64) as u32;
+        match cpu.run_frame_limited(left) {
+            Ok(count) => executed += count as u64,
+            Err(err) => return (executed, Err(err)),
+        }
+        frame += 1;
+    }
+    (executed, Ok(()))
+}
+
+/// Millions of instructions per second.
+fn mips(instructions: u64, start: Instant) -> f64 {
+    instructions as f64 / start.elapsed().as_secs_f64() / 1e6
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("chip8-headless: {}", message);
+    process::exit(1);
+}
+
+fn main() {
+    let options = parse_args().unwrap_or_else(|err| {
+        eprintln!("chip8-headless: {}\n{}", err, usage());
+        process::exit(2);
+    });
+
+    let rom = rom::read(&options.game_path)
+        .unwrap_or_else(|err| fail(format!("could not load {:?}: {}", options.game_path, err)));
+
+    let movie = options.movie.as_ref().map(|path| {
+        let text = fs::read_to_string(path)
+            .unwrap_or_else(|err| fail(format!("could not read {:?}: {}", path, err)));
+        Movie::parse(&text).unwrap_or_else(|err| fail(format!("could not parse {:?}: {}", path, err)))
+    });
+
+    let new_cpu = || {
+        let mut cpu = Cpu::new();
+        cpu.load_rom(&rom.data);
+        options.config.apply(&mut cpu);
+        if let Some(ref movie) = movie {
+            movie.configure(&mut cpu);
+        }
+        cpu
+    };
+    let mut cpu = new_cpu();
+
+    let keys_for = |frame: u64| match movie {
+        Some(ref movie) => movie.frames.get(frame as usize).cloned().unwrap_or(0),
+        None => options
+            .keys
+            .iter()
+            .filter(|press| press.first <= frame && frame <= press.last)
+            .fold(0, |mask, press| mask | press.mask),
+    };
+
+    if let Some(ref path) = options.trace {
+        match Tracer::create(path) {
+            Ok(tracer) => cpu.set_tracer(tracer),
+            Err(err) => fail(format!("could not create {:?}: {}", path, err)),
+        }
+    }
+
+    if options.profile.is_some() {
+        cpu.set_profiler(Profiler::new());
+    }
+    if options.coverage.is_some() {
+        cpu.set_coverage(Coverage::new());
+    }
+
+    // The run without the cache goes first and uses the same random seed,
+    // so both runs execute the same instructions.
+    let uncached = if options.benchmark {
+        let mut uncached = new_cpu();
+        if let Some((generator, seed)) = cpu.rng_seed() {
+            uncached.seed_rng(generator, seed);
+        }
+        uncached.set_decode_cache(false);
+        let start = Instant::now();
+        let (executed, _) = run(&mut uncached, &options.limit, keys_for);
+        Some(mips(executed, start))
+    } else {
+        None
+    };
+
+    let start = Instant::now();
+    let (executed, result) = run(&mut cpu, &options.limit, keys_for);
+    if let Some(uncached) = uncached {
+        println!(
+            "{} instructions: {:.1} MIPS with the decode cache, {:.1} MIPS without",
+            executed,
+            mips(executed, start),
+            uncached
+        );
+    }
+
+    if let Some(tracer) = cpu.take_tracer() {
+        if let Err(err) = tracer.finish() {
+            fail(format!("could not write the trace: {}", err));
+        }
+    }
+
+    if let Some(ref path) = options.output {
+        let image = Format::from_path(path).encode(&cpu);
+        if let Err(err) = fs::write(path, image) {
+            fail(format!("could not write {:?}: {}", path, err));
+        }
+    }
+    if let (Some(path), Some(profiler)) = (&options.profile, cpu.profiler()) {
+        if let Err(err) = fs::write(path, profiler.report(cpu.memory(), rom.symbols.as_ref())) {
+            fail(format!("could not write {:?}: {}", path, err));
+        }
+    }
+    if let (Some(path), Some(coverage)) = (&options.coverage, cpu.coverage()) {
+        if let Err(err) = coverage.save(path, &rom.data, rom.symbols.as_ref()) {
+            fail(format!("could not write {:?}: {}", path, err));
+        }
+    }
+    dump_registers(&cpu);
+
+    if let Err(err) = result {
+        fail(format!("the CPU stopped: {}", err));
+    }
+}
@@ -0,0 +1,71 @@
+//! Checks a ROM for common CHIP-8 bugs without running it, see
+//! `chip8::lint` for what it looks for.
+
+extern crate chip8;
+
+use chip8::lint::{self, Severity};
+use chip8::rom;
+
+use std::env;
+use std::process;
+
+const USAGE: &str = "usage: chip8-lint [--no-notes] ROM
+  ROM can also be an Octo (.8o) or assembly (.asm) source, then the
+  diagnostics point at its lines
+  --no-notes leaves out what only matters on some interpreters
+  exits with 1 when there are warnings";
+
+struct Options {
+    rom_path: String,
+    notes: bool,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut rom_path = None;
+    let mut notes = true;
+
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--no-notes" => notes = false,
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => rom_path = Some(arg),
+        }
+    }
+
+    Ok(Options {
+        rom_path: rom_path.ok_or("no ROM given")?,
+        notes,
+    })
+}
+
+fn main() {
+    let options = parse_args().unwrap_or_else(|err| {
+        eprintln!("chip8-lint: {}\n{}", err, USAGE);
+        process::exit(2);
+    });
+
+    let rom = rom::read(&options.rom_path).unwrap_or_else(|err| {
+        eprintln!("chip8-lint: could not load {:?}: {}", options.rom_path, err);
+        process::exit(2);
+    });
+
+    let diagnostics: Vec<_> = lint::lint(&rom.data)
+        .into_iter()
+        .filter(|diagnostic| options.notes || diagnostic.severity != Severity::Note)
+        .collect();
+    for diagnostic in &diagnostics {
+        println!("{}", diagnostic.format(&options.rom_path, rom.symbols.as_ref(), &rom.data));
+    }
+
+    let warnings = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Warning)
+        .count();
+    let notes = diagnostics.len() - warnings;
+    if !diagnostics.is_empty() {
+        eprintln!("chip8-lint: {} warnings, {} notes", warnings, notes);
+    }
+    if warnings > 0 {
+        process::exit(1);
+    }
+}
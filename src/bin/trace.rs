@@ -0,0 +1,88 @@
+//! Reads the traces `--trace` writes: prints them, or compares two of them
+//! and reports the first step where they disagree.
+
+extern crate chip8;
+
+use chip8::trace::{self, Record};
+
+use std::env;
+use std::fs;
+use std::process;
+
+const USAGE: &str = "usage: chip8-trace diff TRACE TRACE
+       chip8-trace show [--json] TRACE
+  TRACE is a JSON lines or binary trace from --trace
+  diff exits with 0 when the traces are the same, 1 when they diverge";
+
+enum Command {
+    Diff(String, String),
+    Show { path: String, json: bool },
+}
+
+fn parse_args() -> Result<Command, String> {
+    let mut args = env::args().skip(1);
+    let command = args.next().ok_or("no command given")?;
+    let mut paths = Vec::new();
+    let mut json = false;
+    for arg in args {
+        match arg.as_str() {
+            "--json" if command == "show" => json = true,
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => paths.push(arg),
+        }
+    }
+
+    match (command.as_str(), paths.len()) {
+        ("diff", 2) => {
+            let right = paths.pop().unwrap();
+            Ok(Command::Diff(paths.pop().unwrap(), right))
+        }
+        ("show", 1) => Ok(Command::Show {
+            path: paths.pop().unwrap(),
+            json,
+        }),
+        ("diff", _) => Err("diff needs two traces".to_string()),
+        ("show", _) => Err("show needs one trace".to_string()),
+        _ => Err(format!("unknown command {}", command)),
+    }
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("chip8-trace: {}", message);
+    process::exit(2);
+}
+
+fn read(path: &str) -> Vec<Record> {
+    let data = fs::read(path).unwrap_or_else(|err| fail(format!("could not read {:?}: {}", path, err)));
+    trace::read(&data).unwrap_or_else(|err| fail(format!("could not parse {:?}: {}", path, err)))
+}
+
+fn main() {
+    let command = parse_args().unwrap_or_else(|err| {
+        eprintln!("chip8-trace: {}\n{}", err, USAGE);
+        process::exit(2);
+    });
+
+    match command {
+        Command::Diff(left, right) => {
+            let (left_records, right_records) = (read(&left), read(&right));
+            match trace::diff(left_records, right_records) {
+                Some(divergence) => {
+                    println!("< {}\n> {}", left, right);
+                    println!("{}", divergence);
+                    process::exit(1);
+                }
+                None => println!("the traces are the same"),
+            }
+        }
+        Command::Show { path, json } => {
+            for record in read(&path) {
+                if json {
+                    println!("{}", record.to_json());
+                } else {
+                    println!("{}", record);
+                }
+            }
+        }
+    }
+}
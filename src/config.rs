@@ -0,0 +1,102 @@
+//! The machine settings shared by the command-line frontends.
+
+use cpu::{Cpu, DEFAULT_INSTRUCTIONS_PER_FRAME};
+use quirks::{Quirks, PROFILE_NAMES, QUIRK_NAMES};
+use rng::{Generator, GENERATOR_NAMES};
+
+use rand;
+
+/// The usage line for the options `Config::parse_option` understands.
+pub const USAGE: &str =
+    "[--quirks PROFILE] [--quirk NAME=on|off]... [--ipf N] [--rng GENERATOR] [--seed N]";
+
+/// Lists the values the options accept, one line each.
+pub fn option_values() -> String {
+    format!(
+        "  profiles:   {}\n  quirks:     {}\n  generators: {}",
+        PROFILE_NAMES.join(", "),
+        QUIRK_NAMES.join(", "),
+        GENERATOR_NAMES.join(", ")
+    )
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    profile: Quirks,
+    settings: Vec<String>,
+    pub instructions_per_frame: u32,
+    pub generator: Generator,
+    pub seed: Option<u64>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            profile: Quirks::default(),
+            settings: Vec::new(),
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            generator: Generator::Xorshift,
+            seed: None,
+        }
+    }
+}
+
+impl Config {
+    /// Handles `option` if it is one of the machine options, taking its value
+    /// from `args`. Returns `false` for options that are not.
+    pub fn parse_option<I>(&mut self, option: &str, args: &mut I) -> Result<bool, String>
+    where
+        I: Iterator<Item = String>,
+    {
+        match option {
+            "--quirks" => {
+                let name = args.next().ok_or("--quirks needs a profile name")?;
+                self.profile = name.parse().map_err(|err| format!("{}", err))?;
+            }
+            "--quirk" => {
+                let setting = args.next().ok_or("--quirk needs a name=on|off setting")?;
+                Quirks::default().apply(&setting)?;
+                self.settings.push(setting);
+            }
+            "--ipf" => {
+                let value = args.next().ok_or("--ipf needs a number of instructions")?;
+                self.instructions_per_frame = value
+                    .parse()
+                    .map_err(|_| format!("invalid instructions per frame {:?}", value))?;
+            }
+            "--rng" => {
+                let name = args.next().ok_or("--rng needs a generator name")?;
+                self.generator = name.parse().map_err(|err| format!("{}", err))?;
+            }
+            "--seed" => {
+                let value = args.next().ok_or("--seed needs a number")?;
+                self.seed = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid seed {:?}", value))?,
+                );
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// The profile refined by the individual settings, whatever their order
+    /// on the command line.
+    pub fn quirks(&self) -> Quirks {
+        let mut quirks = self.profile;
+        for setting in &self.settings {
+            // Every setting was checked when it was parsed.
+            let _ = quirks.apply(setting);
+        }
+        quirks
+    }
+
+    /// Configures `cpu`. Without a seed the generator gets a random one.
+    pub fn apply(&self, cpu: &mut Cpu) {
+        cpu.set_quirks(self.quirks());
+        cpu.set_instructions_per_frame(self.instructions_per_frame);
+        let seed = self.seed.unwrap_or_else(rand::random);
+        cpu.seed_rng(self.generator, seed);
+    }
+}
@@ -0,0 +1,285 @@
+//! Code coverage. Attached to a `Cpu` with `Cpu::set_coverage` it notes
+//! which bytes of memory were fetched as instructions, read as data by
+//! `Draw`, `LoadRegisters` and the like, or written, and which way every
+//! skip went. The result is exported as an annotated disassembly or, through
+//! the symbols of an assembled program, as an lcov tracefile that coverage
+//! tools such as `genhtml` read.
+
+use disasm::{Disassembly, Flow, ORIGIN};
+use instruction::Instruction;
+use symbols::SymbolMap;
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The byte was fetched as part of an instruction.
+pub const EXECUTED: u8 = 1;
+/// The byte was read as data.
+pub const READ: u8 = 2;
+/// The byte was written.
+pub const WRITTEN: u8 = 4;
+
+/// How often a skip skipped and how often it fell through.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Branch {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+pub struct Coverage {
+    flags: Vec<u8>,
+    executions: Vec<u64>,
+    branches: BTreeMap<u16, Branch>,
+}
+
+impl Default for Coverage {
+    fn default() -> Coverage {
+        Coverage::new()
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage {
+            flags: vec![0; 0x10000],
+            executions: vec![0; 0x10000],
+            branches: BTreeMap::new(),
+        }
+    }
+
+    /// Notes that `instruction` at `address` is executed.
+    pub fn fetch(&mut self, address: u16, instruction: &Instruction) {
+        self.executions[address as usize] += 1;
+        for offset in 0..instruction.size() {
+            self.flags[address.wrapping_add(offset) as usize] |= EXECUTED;
+        }
+    }
+
+    /// Notes which way the skip at `address` went.
+    pub fn branch(&mut self, address: u16, taken: bool) {
+        let branch = self.branches.entry(address).or_default();
+        if taken {
+            branch.taken += 1;
+        } else {
+            branch.not_taken += 1;
+        }
+    }
+
+    pub fn read(&mut self, address: u16) {
+        self.flags[address as usize] |= READ;
+    }
+
+    pub fn write(&mut self, address: u16) {
+        self.flags[address as usize] |= WRITTEN;
+    }
+
+    /// How the byte at `address` was used, a combination of `EXECUTED`,
+    /// `READ` and `WRITTEN`.
+    pub fn flags(&self, address: u16) -> u8 {
+        self.flags[address as usize]
+    }
+
+    /// How often the instruction at `address` executed.
+    pub fn executions(&self, address: u16) -> u64 {
+        self.executions[address as usize]
+    }
+
+    /// Which way the skip at `address` went, if it executed.
+    pub fn branch_at(&self, address: u16) -> Option<Branch> {
+        self.branches.get(&address).cloned()
+    }
+
+    /// Lists `rom` as loaded at `0x200` with how every byte was used. Code
+    /// is what the disassembler finds from the entry point and from the
+    /// instructions that executed, so code only reached through `JP V0, NNN`
+    /// shows up too. Labels come from `symbols` when they are given.
+    pub fn disassembly(&self, rom: &[u8], symbols: Option<&SymbolMap>) -> String {
+        let end = (ORIGIN as usize + rom.len()).min(self.flags.len());
+        // Code the static walk misses is entered where execution continued
+        // from somewhere other than the instruction before it.
+        let reachable = Disassembly::new(rom, ORIGIN);
+        let mut entries = vec![ORIGIN];
+        entries.extend(
+            (ORIGIN as usize + 2..end)
+                .filter(|&address| self.executions[address] > 0 && self.executions[address - 2] == 0)
+                .map(|address| address as u16)
+                .filter(|&address| !reachable.is_code(address)),
+        );
+        let disassembly = Disassembly::with_entries(rom, ORIGIN, &entries);
+
+        let mut out = String::new();
+        let bytes = end - ORIGIN as usize;
+        let used = |flag: u8| (ORIGIN as usize..end).filter(|&address| self.flags[address] & flag != 0).count();
+        writeln!(
+            out,
+            "; {} bytes at {:#06X}: {} executed, {} read, {} written",
+            bytes,
+            ORIGIN,
+            used(EXECUTED),
+            used(READ),
+            used(WRITTEN)
+        )
+        .unwrap();
+        let (branches, covered) = disassembly
+            .code
+            .iter()
+            .filter(|&(_, instruction)| Flow::of(instruction) == Flow::Skip)
+            .fold((0, 0), |(branches, covered), (&address, _)| {
+                let branch = self.branch_at(address).unwrap_or_default();
+                (branches + 2, covered + (branch.taken > 0) as usize + (branch.not_taken > 0) as usize)
+            });
+        writeln!(out, "; {} of {} skip outcomes taken", covered, branches).unwrap();
+        writeln!(out, ";\n; x executed, r read, w written, - unused; the count is how often it executed").unwrap();
+
+        let label = |address: usize| {
+            symbols
+                .and_then(|symbols| symbols.label_at(address as u16))
+                .map(str::to_string)
+                .or_else(|| disassembly.label_name(address as u16))
+        };
+        let mut address = ORIGIN as usize;
+        while address < end {
+            if let Some(label) = label(address) {
+                writeln!(out, "\n{}:", label).unwrap();
+            }
+
+            let (size, text) = match disassembly.code.get(&(address as u16)) {
+                Some(instruction) => {
+                    let mut text = disassembly.mnemonic(instruction);
+                    if let Some(branch) = self.branch_at(address as u16) {
+                        text = format!("{:<24}  ; skipped {}, fell through {}", text, branch.taken, branch.not_taken);
+                    } else if Flow::of(instruction) == Flow::Skip {
+                        text = format!("{:<24}  ; never ran", text);
+                    }
+                    (instruction.size() as usize, text)
+                }
+                None => {
+                    let byte = rom[address - ORIGIN as usize];
+                    let art = (0..8).map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' }).collect();
+                    (1, art)
+                }
+            };
+            let size = size.min(end - address);
+            let flags = self.flags[address..address + size].iter().fold(0, |flags, &flag| flags | flag);
+            let marks: String = [(EXECUTED, 'x'), (READ, 'r'), (WRITTEN, 'w')]
+                .iter()
+                .map(|&(flag, mark)| if flags & flag != 0 { mark } else { '-' })
+                .collect();
+            let count = match self.executions[address] {
+                0 => String::new(),
+                count => count.to_string(),
+            };
+            let hex: String = rom[address - ORIGIN as usize..address - ORIGIN as usize + size]
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect();
+            writeln!(out, "{:>10} {}  {:#06X}  {:<8}  {}", count, marks, address, hex, text).unwrap();
+            // Labels on the operand bytes of an instruction have no line of
+            // their own.
+            for inside in address + 1..address + size {
+                if let Some(label) = label(inside) {
+                    writeln!(out, "{} = {:#06X}", label, inside).unwrap();
+                }
+            }
+            address += size;
+        }
+        out
+    }
+
+    /// An lcov tracefile for the source files in `symbols`: every line that
+    /// assembled to an instruction, with the most often any of its
+    /// instructions executed, every label on such a line as a function and
+    /// the two ways of every skip as a branch. `rom` is the program the
+    /// symbols belong to, loaded at `0x200`.
+    pub fn lcov(&self, rom: &[u8], symbols: &SymbolMap) -> String {
+        let mut files: BTreeMap<&str, BTreeMap<usize, Vec<u16>>> = BTreeMap::new();
+        for (&address, location) in &symbols.lines {
+            files
+                .entry(location.file.as_str())
+                .or_default()
+                .entry(location.line)
+                .or_default()
+                .push(address);
+        }
+
+        let mut out = String::new();
+        for (file, lines) in &files {
+            writeln!(out, "TN:\nSF:{}", file).unwrap();
+
+            let mut functions: Vec<(usize, &str, u64)> = symbols
+                .labels
+                .iter()
+                .filter_map(|(name, &address)| {
+                    symbols
+                        .location(address)
+                        .filter(|location| location.file == *file)
+                        .map(|location| (location.line, name.as_str(), self.executions(address)))
+                })
+                .collect();
+            functions.sort();
+            for &(line, name, _) in &functions {
+                writeln!(out, "FN:{},{}", line, name).unwrap();
+            }
+            for &(_, name, count) in &functions {
+                writeln!(out, "FNDA:{},{}", count, name).unwrap();
+            }
+            let hit = functions.iter().filter(|&&(_, _, count)| count > 0).count();
+            writeln!(out, "FNF:{}\nFNH:{}", functions.len(), hit).unwrap();
+
+            let (mut branches, mut branches_hit) = (0, 0);
+            for (&line, addresses) in lines {
+                for (block, &address) in addresses.iter().enumerate() {
+                    let offset = address.wrapping_sub(ORIGIN) as usize;
+                    if offset >= rom.len() || Flow::of(&Instruction::decode_at(rom, offset as u16)) != Flow::Skip {
+                        continue;
+                    }
+                    let outcomes = match self.branch_at(address) {
+                        Some(branch) => [branch.taken.to_string(), branch.not_taken.to_string()],
+                        None => ["-".to_string(), "-".to_string()],
+                    };
+                    for (n, outcome) in outcomes.iter().enumerate() {
+                        writeln!(out, "BRDA:{},{},{},{}", line, block, n, outcome).unwrap();
+                        branches += 1;
+                        if outcome != "-" && outcome != "0" {
+                            branches_hit += 1;
+                        }
+                    }
+                }
+            }
+            writeln!(out, "BRF:{}\nBRH:{}", branches, branches_hit).unwrap();
+
+            let mut lines_hit = 0;
+            for (&line, addresses) in lines {
+                let count = addresses.iter().map(|&address| self.executions(address)).max().unwrap_or(0);
+                if count > 0 {
+                    lines_hit += 1;
+                }
+                writeln!(out, "DA:{},{}", line, count).unwrap();
+            }
+            writeln!(out, "LF:{}\nLH:{}\nend_of_record", lines.len(), lines_hit).unwrap();
+        }
+        out
+    }
+
+    /// Writes an lcov tracefile to `path` if it ends in `.info` or `.lcov`
+    /// and the annotated disassembly otherwise. `rom` and `symbols` are the
+    /// program that ran, an lcov tracefile needs the symbols.
+    pub fn save<P: AsRef<Path>>(&self, path: P, rom: &[u8], symbols: Option<&SymbolMap>) -> io::Result<()> {
+        let text = match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+            Some("info") | Some("lcov") => match symbols {
+                Some(symbols) => self.lcov(rom, symbols),
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "an lcov tracefile needs a program assembled from source",
+                    ))
+                }
+            },
+            _ => self.disassembly(rom, symbols),
+        };
+        fs::write(path, text)
+    }
+}
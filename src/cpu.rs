@@ -1,29 +1,111 @@
+use coverage::Coverage;
+use disasm::Flow;
+use font::{BIG_FONT, BIG_FONT_ADDRESS, FONT};
 use instruction::*;
+use profile::Profiler;
+use quirks::Quirks;
+use rng::{Generator, RandomSource};
+use state::{Reader, StateError, Writer};
+use trace::Tracer;
 
 use rand;
 
+use std::error;
 use std::fmt;
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, Read};
+use std::path::Path;
 
 #[derive(Debug)]
 pub enum Error {
     IllegalOpcode(Opcode),
+    /// A `Call` with all 16 levels of the stack in use.
+    StackOverflow,
+    /// A `Return` outside of any subroutine.
+    StackUnderflow,
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::IllegalOpcode(opcode) => write!(f, "illegal opcode {:?}", opcode),
+            Error::StackOverflow => write!(f, "call stack overflow"),
+            Error::StackUnderflow => write!(f, "return without a call"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// The size of the display in SUPER-CHIP high resolution mode. In low
+/// resolution mode only the first 64x32 pixels of `vram` are used.
+pub const MAX_WIDTH: usize = 128;
+pub const MAX_HEIGHT: usize = 64;
+
+/// How many instructions `run_frame` executes by default, which is about the
+/// 500 instructions per second this interpreter has always run at.
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: u32 = 8;
+
+/// XO-CHIP extends the address space to the full 16 bits of I.
+pub const MEMORY_SIZE: usize = 0x10000;
+
 pub struct Cpu {
     registers: [u8; 16],
     stack: [u16; 16],
     i: u16,
     pc: u16,
     sp: u16,
-    memory: [u8; 4096],
-    pub vram: [u8; 2048],
+    memory: Vec<u8>,
+    /// The display, one byte per pixel, `screen_width()` pixels per row.
+    /// Bit 0 of a pixel is the first XO-CHIP bitplane and bit 1 the second.
+    pub vram: [u8; MAX_WIDTH * MAX_HEIGHT],
     pub keys: [u8; 16],
     delay_timer: u8,
     sound_timer: u8,
+    hires: bool,
+    planes: u8,
+    flags: [u8; 16],
+    audio_pattern: [u8; 16],
+    pitch: u8,
+    exited: bool,
+    quirks: Quirks,
+    vblank: VBlank,
+    key_wait: KeyWait,
+    instructions_per_frame: u32,
+    rng: Box<dyn RandomSource>,
+    rng_seed: Option<(Generator, u64)>,
+    /// What the last store instruction wrote, see `last_store`.
+    last_store: Option<(u16, u16)>,
+    tracer: Option<Tracer>,
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
+    /// The instruction decoded at each address, filled in as the program
+    /// runs and cleared when the memory under it changes.
+    decoded: Vec<Option<Instruction>>,
+    decode_cache: bool,
 }
 
+/// The progress of an `FX0A` key wait.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyWait {
+    Idle,
+    /// Waiting for a key to go down. Keys in the mask were already held when
+    /// the wait started and only count once they have been released.
+    Press(u16),
+    /// The key went down, waiting for it to come back up.
+    Release(u8),
+}
+
+/// Where a `Draw` is in waiting for the vertical blank when the
+/// `display_wait` quirk is enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VBlank {
+    Idle,
+    Waiting,
+    Ready,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub struct Opcode(pub u8, pub u8);
 
 impl fmt::Debug for Opcode {
@@ -48,30 +130,70 @@ impl fmt::Debug for Cpu {
     }
 }
 
+impl Default for Cpu {
+    fn default() -> Cpu {
+        Cpu::new()
+    }
+}
+
 impl Cpu {
+    /// Creates a CPU in its power-on state with the built-in font loaded.
     pub fn new() -> Cpu {
-        Cpu {
+        let mut cpu = Cpu {
             registers: [0u8; 16],
             stack: [0u16; 16],
             i: 0,
             pc: 0x0200,
             sp: 0,
-            memory: [0u8; 4096],
-            vram: [0u8; 2048],
+            memory: vec![0u8; MEMORY_SIZE],
+            vram: [0u8; MAX_WIDTH * MAX_HEIGHT],
             keys: [0u8; 16],
             delay_timer: 0,
             sound_timer: 0,
-        }
+            hires: false,
+            planes: 1,
+            flags: [0u8; 16],
+            audio_pattern: [0u8; 16],
+            pitch: 64,
+            exited: false,
+            quirks: Quirks::default(),
+            vblank: VBlank::Idle,
+            key_wait: KeyWait::Idle,
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            rng: Generator::Xorshift.create(0),
+            rng_seed: None,
+            last_store: None,
+            tracer: None,
+            profiler: None,
+            coverage: None,
+            decoded: vec![None; MEMORY_SIZE],
+            decode_cache: true,
+        };
+        cpu.seed_rng(Generator::Xorshift, rand::random());
+        cpu.load_font(FONT);
+        let big_font = BIG_FONT_ADDRESS as usize;
+        cpu.memory[big_font..big_font + BIG_FONT.len()].copy_from_slice(&BIG_FONT);
+        cpu
     }
 
-    pub fn load(&mut self, game_path: String) {
-        info!("Loading {:?}", game_path);
+    /// Reads the game at `game_path` into memory at `0x200`.
+    pub fn load<P: AsRef<Path>>(&mut self, game_path: P) -> io::Result<usize> {
+        info!("Loading {:?}", game_path.as_ref());
 
-        let mut f = File::open(game_path).expect("File not found");
-        let n = f.read(&mut self.memory[0x0200..0x0FFF])
-            .expect("Could not read game");
+        let mut rom = Vec::new();
+        File::open(game_path)?.read_to_end(&mut rom)?;
+        Ok(self.load_rom(&rom))
+    }
+
+    /// Copies `rom` into memory at `0x200`, truncating it if it does not fit.
+    /// Returns the number of bytes copied.
+    pub fn load_rom(&mut self, rom: &[u8]) -> usize {
+        let n = rom.len().min(self.memory.len() - 0x0200);
+        self.memory[0x0200..0x0200 + n].copy_from_slice(&rom[..n]);
+        self.clear_decoded();
 
         debug!("Read {:?} bytes into memory", n);
+        n
     }
 
     pub fn load_font(&mut self, font: [u8; 80]) {
@@ -79,21 +201,519 @@ impl Cpu {
         for (i, byte) in font.iter().enumerate() {
             self.memory[i] = *byte;
         }
+        self.clear_decoded();
+    }
+
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.registers
+    }
+
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp as usize]
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn sp(&self) -> u16 {
+        self.sp
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn set_register(&mut self, x: usize, value: u8) {
+        self.registers[x] = value;
+    }
+
+    pub fn set_i(&mut self, address: u16) {
+        self.i = address;
+    }
+
+    pub fn set_pc(&mut self, address: u16) {
+        self.pc = address;
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
+    }
+
+    /// Writes `data` to memory from `address` on, wrapping around at the
+    /// end. This is for debuggers and editors poking at a running program.
+    pub fn write_memory(&mut self, address: u16, data: &[u8]) {
+        for (offset, &byte) in data.iter().enumerate() {
+            let address = (address as usize + offset) % MEMORY_SIZE;
+            self.memory[address] = byte;
+            self.invalidate_decoded(address);
+        }
+    }
+
+    /// The start and length of the memory written by the last `SetBCD`,
+    /// `DumpRegisters` or XO-CHIP `SaveRange`, if any.
+    pub fn last_store(&self) -> Option<(u16, u16)> {
+        self.last_store
+    }
+
+    /// Starts writing a trace record before every instruction `step`
+    /// executes.
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Detaches the tracer, so it can be finished.
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    /// Starts counting the instructions `step` executes.
+    pub fn set_profiler(&mut self, profiler: Profiler) {
+        self.profiler = Some(profiler);
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Detaches the profiler.
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+
+    /// Starts noting which memory the program executes, reads and writes.
+    pub fn set_coverage(&mut self, coverage: Coverage) {
+        self.coverage = Some(coverage);
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Detaches the coverage.
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
+    }
+
+    /// Whether instructions are decoded once and kept until the memory
+    /// under them is written, rather than decoded every step. It is on by
+    /// default, turning it off is for comparing the two.
+    pub fn decode_cache(&self) -> bool {
+        self.decode_cache
+    }
+
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decode_cache = enabled;
+        self.clear_decoded();
+    }
+
+    pub fn screen_width(&self) -> usize {
+        if self.hires {
+            MAX_WIDTH
+        } else {
+            64
+        }
+    }
+
+    pub fn screen_height(&self) -> usize {
+        if self.hires {
+            MAX_HEIGHT
+        } else {
+            32
+        }
+    }
+
+    /// The bitplanes lit at `x`, `y`, 0 for an unlit pixel.
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.vram[y * self.screen_width() + x]
+    }
+
+    /// The XO-CHIP audio pattern buffer, 128 one-bit samples.
+    pub fn audio_pattern(&self) -> &[u8; 16] {
+        &self.audio_pattern
+    }
+
+    /// The XO-CHIP pitch register. The pattern plays at
+    /// `4000 * 2^((pitch - 64) / 48)` samples per second.
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    /// Whether the program has stopped itself with `EXIT`.
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+
+    /// The held keys as a bit mask, bit N for key N.
+    pub fn key_mask(&self) -> u16 {
+        self.keys
+            .iter()
+            .enumerate()
+            .filter(|&(_, &state)| state == 1)
+            .fold(0, |mask, (key, _)| mask | 1 << key)
+    }
+
+    /// Sets the held keys from a bit mask as returned by `key_mask`.
+    pub fn set_key_mask(&mut self, mask: u16) {
+        for (key, state) in self.keys.iter_mut().enumerate() {
+            *state = (mask >> key & 1) as u8;
+        }
+    }
+
+    /// Whether the CPU is stalled on `FX0A` waiting for a key.
+    pub fn waiting_for_key(&self) -> bool {
+        self.key_wait != KeyWait::Idle
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn instructions_per_frame(&self) -> u32 {
+        self.instructions_per_frame
+    }
+
+    pub fn set_instructions_per_frame(&mut self, instructions: u32) {
+        self.instructions_per_frame = instructions;
+    }
+
+    /// Replaces the random number generator with `generator` seeded with
+    /// `seed`, so that `CXNN` produces the same numbers on every run.
+    pub fn seed_rng(&mut self, generator: Generator, seed: u64) {
+        info!("Seeding the {:?} random generator with {}", generator, seed);
+        self.rng = generator.create(seed);
+        self.rng_seed = Some((generator, seed));
+    }
+
+    /// Replaces the random number generator with a custom one.
+    pub fn set_random_source(&mut self, source: Box<dyn RandomSource>) {
+        self.rng = source;
+        self.rng_seed = None;
+    }
+
+    /// The generator and seed set by `seed_rng`, `None` for a custom source.
+    pub fn rng_seed(&self) -> Option<(Generator, u64)> {
+        self.rng_seed
+    }
+
+    /// Captures the complete machine, including the quirks, the instruction
+    /// rate and the random generator, in the format described in `state`.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = Writer::new();
+        w.bytes(&self.registers);
+        for address in self.stack.iter() {
+            w.u16(*address);
+        }
+        w.u16(self.i);
+        w.u16(self.pc);
+        w.u16(self.sp);
+        w.u8(self.delay_timer);
+        w.u8(self.sound_timer);
+        w.bytes(&self.keys);
+        w.bool(self.hires);
+        w.u8(self.planes);
+        w.bytes(&self.flags);
+        w.bytes(&self.audio_pattern);
+        w.u8(self.pitch);
+        w.bool(self.exited);
+        w.u8(match self.vblank {
+            VBlank::Idle => 0,
+            VBlank::Waiting => 1,
+            VBlank::Ready => 2,
+        });
+        match self.key_wait {
+            KeyWait::Idle => {
+                w.u8(0);
+                w.u16(0);
+            }
+            KeyWait::Press(ignored) => {
+                w.u8(1);
+                w.u16(ignored);
+            }
+            KeyWait::Release(key) => {
+                w.u8(2);
+                w.u16(key as u16);
+            }
+        }
+        w.u32(self.instructions_per_frame);
+
+        let q = self.quirks;
+        for quirk in &[
+            q.shift_uses_vy,
+            q.load_store_increments_i,
+            q.logic_resets_vf,
+            q.clip_sprites,
+            q.jump_uses_vx,
+            q.display_wait,
+            q.key_wait_release,
+        ] {
+            w.bool(*quirk);
+        }
+
+        let (generator, seed) = match self.rng_seed {
+            None => (0, 0),
+            Some((Generator::Xorshift, seed)) => (1, seed),
+            Some((Generator::Vip, seed)) => (2, seed),
+        };
+        w.u8(generator);
+        w.u64(seed);
+        w.blob(&self.rng.save());
+
+        w.blob(&self.memory);
+        w.blob(&self.vram);
+        w.finish()
+    }
+
+    /// Restores a machine captured by `save_state`. Nothing is changed if the
+    /// state is invalid.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut r = Reader::new(data)?;
+
+        let mut registers = [0u8; 16];
+        r.fill(&mut registers)?;
+        let mut stack = [0u16; 16];
+        for address in stack.iter_mut() {
+            *address = r.u16()?;
+        }
+        let i = r.u16()?;
+        let pc = r.u16()?;
+        let sp = r.u16()?;
+        if sp as usize > stack.len() {
+            return Err(StateError::Invalid("stack pointer"));
+        }
+        let delay_timer = r.u8()?;
+        let sound_timer = r.u8()?;
+        let mut keys = [0u8; 16];
+        r.fill(&mut keys)?;
+        let hires = r.bool()?;
+        let planes = r.u8()?;
+        if planes > 0b11 {
+            return Err(StateError::Invalid("plane selection"));
+        }
+        let mut flags = [0u8; 16];
+        r.fill(&mut flags)?;
+        let mut audio_pattern = [0u8; 16];
+        r.fill(&mut audio_pattern)?;
+        let pitch = r.u8()?;
+        let exited = r.bool()?;
+        let vblank = match r.u8()? {
+            0 => VBlank::Idle,
+            1 => VBlank::Waiting,
+            2 => VBlank::Ready,
+            _ => return Err(StateError::Invalid("vertical blank state")),
+        };
+        let key_wait = match (r.u8()?, r.u16()?) {
+            (0, _) => KeyWait::Idle,
+            (1, ignored) => KeyWait::Press(ignored),
+            (2, key) if key < 16 => KeyWait::Release(key as u8),
+            _ => return Err(StateError::Invalid("key wait state")),
+        };
+        let instructions_per_frame = r.u32()?;
+
+        let quirks = Quirks {
+            shift_uses_vy: r.bool()?,
+            load_store_increments_i: r.bool()?,
+            logic_resets_vf: r.bool()?,
+            clip_sprites: r.bool()?,
+            jump_uses_vx: r.bool()?,
+            display_wait: r.bool()?,
+            key_wait_release: r.bool()?,
+        };
+
+        let generator = match r.u8()? {
+            0 => None,
+            1 => Some(Generator::Xorshift),
+            2 => Some(Generator::Vip),
+            _ => return Err(StateError::Invalid("random generator")),
+        };
+        let seed = r.u64()?;
+        let rng_state = r.blob()?;
+
+        let memory = r.blob()?;
+        if memory.len() != MEMORY_SIZE {
+            return Err(StateError::Invalid("memory size"));
+        }
+        let display = r.blob()?;
+        if display.len() != self.vram.len() {
+            return Err(StateError::Invalid("display size"));
+        }
+        r.finish()?;
+
+        // A custom source can only be restored in place, so this comes last.
+        let mut rng = generator.map(|generator| generator.create(seed));
+        let restored = match rng {
+            Some(ref mut rng) => rng.restore(rng_state),
+            None => self.rng.restore(rng_state),
+        };
+        if !restored {
+            return Err(StateError::Invalid("random generator state"));
+        }
+
+        self.registers = registers;
+        self.stack = stack;
+        self.i = i;
+        self.pc = pc;
+        self.sp = sp;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.keys = keys;
+        self.hires = hires;
+        self.planes = planes;
+        self.flags = flags;
+        self.audio_pattern = audio_pattern;
+        self.pitch = pitch;
+        self.exited = exited;
+        self.vblank = vblank;
+        self.key_wait = key_wait;
+        self.instructions_per_frame = instructions_per_frame;
+        self.quirks = quirks;
+        if let Some(rng) = rng {
+            self.rng = rng;
+            self.rng_seed = generator.map(|generator| (generator, seed));
+        }
+        self.memory.copy_from_slice(memory);
+        self.clear_decoded();
+        self.vram.copy_from_slice(display);
+        Ok(())
+    }
+
+    /// Signals the vertical blank to a `Draw` held back by the
+    /// `display_wait` quirk.
+    fn vblank(&mut self) {
+        if self.vblank == VBlank::Waiting {
+            self.vblank = VBlank::Ready;
+        }
+    }
+
+    /// Whether the CPU is stalled on a `Draw` until the next `vblank`.
+    pub fn waiting_for_vblank(&self) -> bool {
+        self.vblank == VBlank::Waiting
+    }
+
+    /// Runs one 60 Hz frame: up to `instructions_per_frame` instructions,
+    /// then the timers tick once. The frame ends early when the program exits
+    /// or a `Draw` waits for the vertical blank. Returns how many
+    /// instructions ran.
+    pub fn run_frame(&mut self) -> Result<u32, Error> {
+        let limit = self.instructions_per_frame;
+        self.run_frame_limited(limit)
     }
 
+    /// Like `run_frame`, but runs at most `limit` instructions, for callers
+    /// that stop after a number of instructions rather than of frames. The
+    /// timers still tick.
+    pub fn run_frame_limited(&mut self, limit: u32) -> Result<u32, Error> {
+        let limit = limit.min(self.instructions_per_frame);
+        let mut executed = 0;
+        while executed < limit && !self.exited && !self.waiting_for_vblank() {
+            self.step()?;
+            executed += 1;
+        }
+        self.end_frame();
+        Ok(executed)
+    }
+
+    /// Does what happens at the 60 Hz vertical blank: the timers tick and
+    /// a `Draw` waiting for the blank is released. `run_frame` calls this,
+    /// frontends that call `step` themselves have to do it once per frame.
+    pub fn end_frame(&mut self) {
+        self.update_timers();
+        self.rng.frame();
+        self.vblank();
+    }
+
+    /// Executes a single instruction. The timers are left alone, they only
+    /// tick at the end of a `run_frame`.
     pub fn step(&mut self) -> Result<(), Error> {
-        let opcode = self.fetch();
-        let instruction = Instruction::decode(opcode);
+        if self.exited {
+            return Ok(());
+        }
+        if let Some(mut tracer) = self.tracer.take() {
+            match tracer.record(self) {
+                Ok(()) => self.tracer = Some(tracer),
+                Err(err) => error!("Tracing stopped: {}", err),
+            }
+        }
+        let instruction = self.fetch();
+        if let Some(mut profiler) = self.profiler.take() {
+            profiler.record(self, instruction);
+            self.profiler = Some(profiler);
+        }
+        if let Some(ref mut coverage) = self.coverage {
+            coverage.fetch(self.pc, &instruction);
+        }
+
+        let pc = self.pc;
         self.execute(instruction)?;
-        self.update_timers();
+        if let Some(ref mut coverage) = self.coverage {
+            if Flow::of(&instruction) == Flow::Skip {
+                coverage.branch(pc, self.pc != pc.wrapping_add(instruction.size()));
+            }
+        }
         Ok(())
     }
 
-    fn fetch(&mut self) -> Opcode {
-        Opcode(
-            self.memory[self.pc as usize],
-            self.memory[(self.pc + 1) as usize],
-        )
+    fn fetch(&mut self) -> Instruction {
+        if !self.decode_cache {
+            return Instruction::decode_at(&self.memory, self.pc);
+        }
+        match self.decoded[self.pc as usize] {
+            Some(instruction) => instruction,
+            None => {
+                let instruction = Instruction::decode_at(&self.memory, self.pc);
+                self.decoded[self.pc as usize] = Some(instruction);
+                instruction
+            }
+        }
+    }
+
+    /// Forgets the instructions that include the byte at `address`, the
+    /// four byte long load can start up to three bytes before it.
+    fn invalidate_decoded(&mut self, address: usize) {
+        for offset in 0..4 {
+            self.decoded[(address + MEMORY_SIZE - offset) % MEMORY_SIZE] = None;
+        }
+    }
+
+    fn clear_decoded(&mut self) {
+        for instruction in self.decoded.iter_mut() {
+            *instruction = None;
+        }
+    }
+
+    fn read(&mut self, address: usize) -> u8 {
+        if let Some(ref mut coverage) = self.coverage {
+            coverage.read((address % MEMORY_SIZE) as u16);
+        }
+        self.memory[address % MEMORY_SIZE]
+    }
+
+    fn write(&mut self, address: usize, value: u8) {
+        if let Some(ref mut coverage) = self.coverage {
+            coverage.write((address % MEMORY_SIZE) as u16);
+        }
+        self.memory[address % MEMORY_SIZE] = value;
+        self.invalidate_decoded(address % MEMORY_SIZE);
     }
 
     fn update_timers(&mut self) {
@@ -109,21 +729,55 @@ impl Cpu {
     fn execute(&mut self, instruction: Instruction) -> Result<(), Error> {
         trace!("{:?}", instruction);
 
+        let size = instruction.size();
         let mut increment_pc = true;
 
         match instruction {
-            Clear => self.vram = [0; 2048],
+            Clear => {
+                for pixel in self.vram.iter_mut() {
+                    *pixel &= !self.planes;
+                }
+            }
             Return => {
+                if self.sp == 0 {
+                    return Err(Error::StackUnderflow);
+                }
                 self.sp -= 1;
                 self.pc = self.stack[self.sp as usize];
             }
 
+            ScrollDown(n) => self.scroll(0, n as isize),
+
+            ScrollUp(n) => self.scroll(0, -(n as isize)),
+
+            ScrollRight => self.scroll(4, 0),
+
+            ScrollLeft => self.scroll(-4, 0),
+
+            Exit => {
+                self.exited = true;
+                increment_pc = false;
+            }
+
+            LowRes => {
+                self.hires = false;
+                self.vram = [0; MAX_WIDTH * MAX_HEIGHT];
+            }
+
+            HighRes => {
+                self.hires = true;
+                self.vram = [0; MAX_WIDTH * MAX_HEIGHT];
+            }
+
             Jump(address) => {
                 self.pc = address;
                 increment_pc = false;
             }
 
             Call(address) => {
+                if self.sp as usize == self.stack.len() {
+                    return Err(Error::StackOverflow);
+                }
                 self.stack[self.sp as usize] = self.pc;
                 self.sp += 1;
                 self.pc = address;
@@ -132,19 +786,33 @@ impl Cpu {
 
             SkipIfConstantEqual(x, kk) => {
                 if self.registers[x as usize] == kk {
-                    self.pc += 2
+                    self.skip();
                 }
             }
 
             SkipIfConstantNotEqual(x, kk) => {
                 if self.registers[x as usize] != kk {
-                    self.pc += 2
+                    self.skip();
                 }
             }
 
             SkipIfEqual(x, y) => {
                 if self.registers[x as usize] == self.registers[y as usize] {
-                    self.pc += 2
+                    self.skip();
+                }
+            }
+
+            SaveRange(x, y) => {
+                for (offset, register) in register_range(x, y).enumerate() {
+                    let value = self.registers[register];
+                    self.write(self.i as usize + offset, value);
+                }
+                self.last_store = Some((self.i, (x.max(y) - x.min(y)) as u16 + 1));
+            }
+
+            LoadRange(x, y) => {
+                for (offset, register) in register_range(x, y).enumerate() {
+                    self.registers[register] = self.read(self.i as usize + offset);
                 }
             }
 
@@ -156,127 +824,170 @@ impl Cpu {
 
             Load(x, y) => self.registers[x as usize] = self.registers[y as usize],
 
-            Or(x, y) => self.registers[x as usize] |= self.registers[y as usize],
+            Or(x, y) => {
+                self.registers[x as usize] |= self.registers[y as usize];
+                self.reset_vf_after_logic();
+            }
 
-            And(x, y) => self.registers[x as usize] &= self.registers[y as usize],
+            And(x, y) => {
+                self.registers[x as usize] &= self.registers[y as usize];
+                self.reset_vf_after_logic();
+            }
 
             Add(x, y) => {
                 let vx = self.registers[x as usize];
                 let vy = self.registers[y as usize];
-                self.registers[0xF] = ((vx as u16 + vy as u16) > 255) as u8;
                 self.registers[x as usize] = vx.wrapping_add(vy);
+                self.registers[0xF] = ((vx as u16 + vy as u16) > 255) as u8;
             }
 
-            Xor(x, y) => self.registers[x as usize] ^= self.registers[y as usize],
+            Xor(x, y) => {
+                self.registers[x as usize] ^= self.registers[y as usize];
+                self.reset_vf_after_logic();
+            }
 
             Sub(x, y) => {
                 let vx = self.registers[x as usize];
                 let vy = self.registers[y as usize];
-                self.registers[0xF] = (vx > vy) as u8;
                 self.registers[x as usize] = vx.wrapping_sub(vy);
+                self.registers[0xF] = (vx >= vy) as u8;
             }
 
             ShiftRight(x, y) => {
-                let vy = self.registers[y as usize];
-                self.registers[0xF] = vy & 1;
-                self.registers[x as usize] = vy >> 1;
+                let value = self.shift_source(x, y);
+                self.registers[x as usize] = value >> 1;
+                self.registers[0xF] = value & 1;
             }
 
             SubReverse(x, y) => {
                 let vx = self.registers[x as usize];
                 let vy = self.registers[y as usize];
-                self.registers[0xF] = (vy > vx) as u8;
                 self.registers[x as usize] = vy.wrapping_sub(vx);
+                self.registers[0xF] = (vy >= vx) as u8;
             }
 
             ShiftLeft(x, y) => {
-                let vy = self.registers[y as usize];
-                self.registers[0xF] = vy >> 7;
-                self.registers[x as usize] = vy << 1;
+                let value = self.shift_source(x, y);
+                self.registers[x as usize] = value << 1;
+                self.registers[0xF] = value >> 7;
             }
 
             SkipIfNotEqual(x, y) => {
                 if self.registers[x as usize] != self.registers[y as usize] {
-                    self.pc += 2;
+                    self.skip();
                 }
             }
 
             SetAddress(address) => self.i = address,
 
-            JumpV0Address(address) => self.i = address.wrapping_add(self.registers[0] as u16),
+            SetLongAddress(address) => self.i = address,
+
+            JumpV0Address(address) => {
+                let offset = if self.quirks.jump_uses_vx {
+                    self.registers[(address >> 8) as usize & 0xF]
+                } else {
+                    self.registers[0]
+                };
+                self.pc = address.wrapping_add(offset as u16);
+                increment_pc = false;
+            }
 
-            RandomAnd(x, kk) => self.registers[x as usize] = rand::random::<u8>() & kk,
+            RandomAnd(x, kk) => self.registers[x as usize] = self.rng.next_byte() & kk,
 
             Draw(x, y, height) => {
-                let vx = self.registers[x as usize] as usize;
-                let vy = self.registers[y as usize] as usize;
-                self.registers[0xF] = 0;
-                for (i, byte) in self.memory[self.i as usize..(self.i as usize + height as usize)]
-                    .iter()
-                    .enumerate()
-                {
-                    for j in 0..8 {
-                        let pos_y = (vy + i) % 32;
-                        let pos_x = (vx + j) % 64;
-
-                        let vram_addr = pos_y * 64 + pos_x;
-                        let old = self.vram[vram_addr];
-                        let new = self.vram[vram_addr] ^ (byte >> (7 - j as u8));
-                        self.vram[vram_addr] = new;
-                        if old & 1 == 1 && new & 1 == 0 {
-                            self.registers[0xF] = 1;
-                        }
-                    }
+                if self.quirks.display_wait && self.vblank != VBlank::Ready {
+                    self.vblank = VBlank::Waiting;
+                    return Ok(());
                 }
+                self.vblank = VBlank::Idle;
+
+                let vx = self.registers[x as usize];
+                let vy = self.registers[y as usize];
+                let collision = self.draw_sprite(vx, vy, height);
+                self.registers[0xF] = collision as u8;
             }
 
+            SelectPlanes(n) => self.planes = n & 0b11,
+
+            LoadAudio => {
+                for offset in 0..self.audio_pattern.len() {
+                    self.audio_pattern[offset] = self.read(self.i as usize + offset);
+                }
+            }
+
+            SetPitch(x) => self.pitch = self.registers[x as usize],
+
             SkipIfNotPressed(x) => {
                 let key = self.registers[x as usize];
                 if self.keys[key as usize] != 1 {
-                    self.pc += 2;
+                    self.skip();
                 }
             }
 
             SkipIfPressed(x) => {
                 let key = self.registers[x as usize];
                 if self.keys[key as usize] == 1 {
-                    self.pc += 2;
+                    self.skip();
                 }
             }
 
             LoadDelay(x) => self.registers[x as usize] = self.delay_timer,
 
-            WaitForKey(x) => {
-                let key = self.registers[x as usize];
-                if self.keys[key as usize] != 1 {
-                    increment_pc = false;
-                }
-            }
+            WaitForKey(x) => match self.wait_for_key() {
+                Some(key) => self.registers[x as usize] = key,
+                None => increment_pc = false,
+            },
 
             SetDelay(x) => self.delay_timer = self.registers[x as usize],
 
             SetSound(x) => self.sound_timer = self.registers[x as usize],
 
-            AddAddress(x) => self.i += self.registers[x as usize] as u16,
+            AddAddress(x) => self.i = self.i.wrapping_add(self.registers[x as usize] as u16),
 
             SetFontLocation(x) => self.i = self.registers[x as usize] as u16 * 5,
 
+            SetBigFontLocation(x) => {
+                self.i = BIG_FONT_ADDRESS + (self.registers[x as usize] & 0xF) as u16 * 10
+            }
+
             SetBCD(x) => {
                 let value = self.registers[x as usize];
-                self.memory[self.i as usize] = value / 100;
-                self.memory[self.i as usize + 1] = (value % 100) / 10;
-                self.memory[self.i as usize + 2] = (value % 100) % 10;
+                let i = self.i as usize;
+                self.write(i, value / 100);
+                self.write(i + 1, (value % 100) / 10);
+                self.write(i + 2, (value % 100) % 10);
+                self.last_store = Some((self.i, 3));
             }
 
-            DumpRegisters(x) => for i in 0..=x {
-                self.memory[self.i as usize] = self.registers[i as usize];
-                self.i += 1;
-            },
+            DumpRegisters(x) => {
+                for i in 0..=x as usize {
+                    let value = self.registers[i];
+                    self.write(self.i as usize + i, value);
+                }
+                self.last_store = Some((self.i, x as u16 + 1));
+                if self.quirks.load_store_increments_i {
+                    self.i = self.i.wrapping_add(x as u16 + 1);
+                }
+            }
 
-            LoadRegisters(x) => for i in 0..=x {
-                self.registers[i as usize] = self.memory[self.i as usize];
-                self.i += 1;
-            },
+            LoadRegisters(x) => {
+                for i in 0..=x as usize {
+                    self.registers[i] = self.read(self.i as usize + i);
+                }
+                if self.quirks.load_store_increments_i {
+                    self.i = self.i.wrapping_add(x as u16 + 1);
+                }
+            }
+
+            SaveFlags(x) => {
+                let n = x as usize + 1;
+                self.flags[..n].copy_from_slice(&self.registers[..n]);
+            }
+
+            LoadFlags(x) => {
+                let n = x as usize + 1;
+                self.registers[..n].copy_from_slice(&self.flags[..n]);
+            }
 
             Illegal(opcode) => {
                 return Err(Error::IllegalOpcode(opcode));
@@ -284,9 +995,148 @@ impl Cpu {
         }
 
         if increment_pc {
-            self.pc += 2;
+            self.pc = self.pc.wrapping_add(size);
         }
 
         Ok(())
     }
+
+    /// XORs the sprite at I onto the selected bitplanes at `vx`, `vy` and
+    /// returns whether any lit pixel was turned off. A height of 0 draws a
+    /// 16x16 sprite. With both planes selected the sprite data for the second
+    /// plane follows the data for the first.
+    fn draw_sprite(&mut self, vx: u8, vy: u8, height: u8) -> bool {
+        let (width, screen_height) = (self.screen_width(), self.screen_height());
+        let (sprite_width, rows) = if height == 0 { (16, 16) } else { (8, height as usize) };
+        let bytes_per_row = sprite_width / 8;
+
+        let x0 = vx as usize % width;
+        let y0 = vy as usize % screen_height;
+        let clip = self.quirks.clip_sprites;
+
+        let mut collision = false;
+        let mut address = self.i as usize;
+        for plane in &[0b01u8, 0b10] {
+            if self.planes & plane == 0 {
+                continue;
+            }
+
+            for row in 0..rows {
+                let bits = if bytes_per_row == 2 {
+                    (self.read(address) as u16) << 8 | self.read(address + 1) as u16
+                } else {
+                    (self.read(address) as u16) << 8
+                };
+                address += bytes_per_row;
+
+                if clip && y0 + row >= screen_height {
+                    continue;
+                }
+                let pos_y = (y0 + row) % screen_height;
+
+                for column in 0..sprite_width {
+                    if bits & (0x8000 >> column) == 0 {
+                        continue;
+                    }
+                    if clip && x0 + column >= width {
+                        break;
+                    }
+                    let pos_x = (x0 + column) % width;
+
+                    let vram_addr = pos_y * width + pos_x;
+                    collision |= self.vram[vram_addr] & plane != 0;
+                    self.vram[vram_addr] ^= plane;
+                }
+            }
+        }
+        collision
+    }
+
+    /// Moves the selected bitplanes by `dx`, `dy` pixels, filling the
+    /// uncovered area with unlit pixels.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.screen_width() as isize, self.screen_height() as isize);
+        let old = self.vram;
+        for y in 0..height {
+            for x in 0..width {
+                let (from_x, from_y) = (x - dx, y - dy);
+                let moved = if from_x >= 0 && from_x < width && from_y >= 0 && from_y < height {
+                    old[(from_y * width + from_x) as usize] & self.planes
+                } else {
+                    0
+                };
+                let pixel = &mut self.vram[(y * width + x) as usize];
+                *pixel = (*pixel & !self.planes) | moved;
+            }
+        }
+    }
+
+    /// Skips the next instruction, which is four bytes long if it is the
+    /// XO-CHIP long load.
+    fn skip(&mut self) {
+        let next = Instruction::decode_at(&self.memory, self.pc.wrapping_add(2));
+        self.pc = self.pc.wrapping_add(next.size());
+    }
+
+    /// Advances the `FX0A` state machine and returns the key once the wait
+    /// is complete.
+    fn wait_for_key(&mut self) -> Option<u8> {
+        let held = self.key_mask();
+        match self.key_wait {
+            KeyWait::Idle => {
+                // On the VIP a key that is already down counts as a press,
+                // press-only interpreters want a fresh one.
+                let ignored = if self.quirks.key_wait_release { 0 } else { held };
+                self.key_wait = KeyWait::Press(ignored);
+                self.wait_for_key()
+            }
+            KeyWait::Press(ignored) => {
+                let ignored = ignored & held;
+                let pressed = held & !ignored;
+                if pressed == 0 {
+                    self.key_wait = KeyWait::Press(ignored);
+                    return None;
+                }
+                let key = pressed.trailing_zeros() as u8;
+                if self.quirks.key_wait_release {
+                    self.key_wait = KeyWait::Release(key);
+                    None
+                } else {
+                    self.key_wait = KeyWait::Idle;
+                    Some(key)
+                }
+            }
+            KeyWait::Release(key) => {
+                if held & 1 << key != 0 {
+                    return None;
+                }
+                self.key_wait = KeyWait::Idle;
+                Some(key)
+            }
+        }
+    }
+
+    fn shift_source(&self, x: u8, y: u8) -> u8 {
+        if self.quirks.shift_uses_vy {
+            self.registers[y as usize]
+        } else {
+            self.registers[x as usize]
+        }
+    }
+
+    fn reset_vf_after_logic(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.registers[0xF] = 0;
+        }
+    }
+}
+
+/// The registers `5XY2`/`5XY3` transfer, which run backwards if `y < x`.
+fn register_range(x: u8, y: u8) -> Box<dyn Iterator<Item = usize>> {
+    let (x, y) = (x as usize, y as usize);
+    if x <= y {
+        Box::new(x..=y)
+    } else {
+        Box::new((y..=x).rev())
+    }
 }
@@ -0,0 +1,709 @@
+//! A Debug Adapter Protocol server, so VS Code and other IDEs can debug
+//! CHIP-8 programs, at source level when they were compiled from source.
+//!
+//! The IDE connects over TCP to a port of the loopback interface, which in
+//! VS Code is the `debugServer` of a launch configuration. Like the GDB stub
+//! the server never blocks: a frontend calls `poll` to handle the requests
+//! and `run_frame` in place of `Cpu::run_frame`.
+//!
+//! The program is a single thread. Its stack frames come from the call
+//! stack and every frame has the registers and timers as variables. Line
+//! breakpoints are mapped to addresses through the program's symbols and
+//! function breakpoints take labels.
+
+use cpu::{Cpu, MEMORY_SIZE};
+use debugger::{parse_number, Breakpoint, Connection, Engine, Stop};
+use instruction::*;
+use json::{object, Value};
+use symbols::{Location, SymbolMap};
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::net::{Ipv4Addr, TcpListener};
+use std::path::Path;
+
+const THREAD: u8 = 1;
+
+/// The `variablesReference` of the scopes.
+const REGISTERS: u64 = 1;
+const TIMERS: u64 = 2;
+
+struct Client {
+    connection: Connection,
+    /// The sequence number of the next message we send.
+    seq: u64,
+}
+
+impl Client {
+    /// Numbers and sends a message.
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        if let Value::Object(ref mut members) = message {
+            members.insert(0, ("seq".to_string(), self.seq.into()));
+            self.seq += 1;
+        }
+        let body = message.to_string();
+        trace!("dap: <- {}", body);
+        let data = format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        self.connection.send(data.as_bytes())
+    }
+
+    /// Takes the next complete message out of the input.
+    fn next_message(&mut self) -> Option<io::Result<Value>> {
+        let end = self.connection.input.windows(4).position(|window| window == b"\r\n\r\n")?;
+        let header = String::from_utf8_lossy(&self.connection.input[..end]).into_owned();
+        let length = header
+            .lines()
+            .filter_map(|line| line.strip_prefix("Content-Length:"))
+            .filter_map(|length| length.trim().parse::<usize>().ok())
+            .next();
+        let length = match length {
+            Some(length) => length,
+            None => {
+                return Some(Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "a message without a Content-Length",
+                )))
+            }
+        };
+
+        let start = end + 4;
+        if self.connection.input.len() < start + length {
+            return None;
+        }
+        let body: Vec<u8> = self.connection.input.drain(..start + length).skip(start).collect();
+        let body = String::from_utf8_lossy(&body);
+        trace!("dap: -> {}", body);
+        Some(Value::parse(&body).map_err(|err| io::Error::new(ErrorKind::InvalidData, err)))
+    }
+}
+
+pub struct DapServer {
+    listener: TcpListener,
+    client: Option<Client>,
+    symbols: Option<SymbolMap>,
+    /// The addresses of the line breakpoints, by source path.
+    line_breakpoints: BTreeMap<String, Vec<u16>>,
+    function_breakpoints: Vec<u16>,
+    engine: Engine,
+    stop_on_entry: bool,
+}
+
+impl DapServer {
+    /// Listens on `port` of the loopback interface. Port 0 picks a free one,
+    /// see `port`. The symbols of a program compiled from source give the
+    /// IDE its source lines and labels.
+    pub fn bind(port: u16, symbols: Option<SymbolMap>) -> io::Result<DapServer> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        listener.set_nonblocking(true)?;
+        Ok(DapServer {
+            listener,
+            client: None,
+            symbols,
+            line_breakpoints: BTreeMap::new(),
+            function_breakpoints: Vec::new(),
+            engine: Engine::new(),
+            stop_on_entry: false,
+        })
+    }
+
+    pub fn port(&self) -> io::Result<u16> {
+        Ok(self.listener.local_addr()?.port())
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.client.is_some()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.engine.is_paused()
+    }
+
+    /// Accepts an IDE and handles the requests it sent since the last call.
+    /// An IDE that goes away is disconnected, only errors of the listening
+    /// socket are returned.
+    pub fn poll(&mut self, cpu: &mut Cpu) -> io::Result<()> {
+        if self.client.is_none() {
+            let connection = match Connection::accept(&self.listener)? {
+                Some(connection) => connection,
+                None => return Ok(()),
+            };
+            info!("An IDE connected");
+            self.client = Some(Client { connection, seq: 1 });
+            self.engine.pause();
+        }
+
+        if let Err(err) = self.receive(cpu) {
+            warn!("Lost the IDE: {}", err);
+            self.disconnect();
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self, cpu: &mut Cpu) -> io::Result<()> {
+        let mut client = match self.client.take() {
+            Some(client) => client,
+            None => return Ok(()),
+        };
+        if !client.connection.receive()? {
+            self.disconnect();
+            return Ok(());
+        }
+
+        while let Some(message) = client.next_message() {
+            let request = message?;
+            if request["type"].as_str() != Some("request") {
+                continue;
+            }
+            for reply in self.handle(&request, cpu) {
+                client.send(reply)?;
+            }
+            if request["command"].as_str() == Some("disconnect") {
+                self.disconnect();
+                return Ok(());
+            }
+        }
+        self.client = Some(client);
+        Ok(())
+    }
+
+    /// Lets the program run on its own.
+    fn disconnect(&mut self) {
+        info!("The IDE disconnected");
+        self.client = None;
+        self.line_breakpoints.clear();
+        self.function_breakpoints.clear();
+        self.engine.clear_breakpoints();
+        self.engine.resume();
+    }
+
+    /// Runs a frame through the engine and sends the events when the
+    /// program stopped.
+    pub fn run_frame(&mut self, cpu: &mut Cpu) {
+        let events = match self.engine.run_frame(cpu) {
+            Some(Stop::Breakpoint(..)) => vec![self.stopped("breakpoint", None)],
+            Some(Stop::Returned) => vec![self.stopped("step", None)],
+            Some(Stop::Error(err)) => vec![self.stopped("exception", Some(err.to_string()))],
+            Some(Stop::Exited) => self.exited(),
+            None => return,
+        };
+        let mut sent = Ok(());
+        if let Some(ref mut client) = self.client {
+            sent = events.into_iter().try_for_each(|event| client.send(event));
+        }
+        if let Err(err) = sent {
+            warn!("Lost the IDE: {}", err);
+            self.disconnect();
+        }
+    }
+
+    /// Gives the engine a breakpoint at the address of every line and
+    /// function breakpoint.
+    fn update_breakpoints(&mut self) {
+        self.engine.clear_breakpoints();
+        let addresses = self.line_breakpoints.values().flatten().chain(&self.function_breakpoints);
+        for &address in addresses {
+            self.engine.add_breakpoint(Breakpoint::Address(address));
+        }
+    }
+
+    fn event(&self, event: &str, body: Value) -> Value {
+        object(vec![
+            ("type", "event".into()),
+            ("event", event.into()),
+            ("body", body),
+        ])
+    }
+
+    fn stopped(&self, reason: &str, text: Option<String>) -> Value {
+        let mut body = vec![
+            ("reason", reason.into()),
+            ("threadId", THREAD.into()),
+            ("allThreadsStopped", true.into()),
+        ];
+        if let Some(text) = text {
+            body.push(("text", text.into()));
+        }
+        self.event("stopped", object(body))
+    }
+
+    fn exited(&self) -> Vec<Value> {
+        vec![
+            self.event("exited", object(vec![("exitCode", 0.into())])),
+            self.event("terminated", object(vec![])),
+        ]
+    }
+
+    /// Answers a request, followed by the events it caused.
+    fn handle(&mut self, request: &Value, cpu: &mut Cpu) -> Vec<Value> {
+        let command = request["command"].as_str().unwrap_or("");
+        let args = &request["arguments"];
+        let mut events = Vec::new();
+
+        let body = match command {
+            "initialize" => {
+                events.push(self.event("initialized", object(vec![])));
+                Ok(object(vec![
+                    ("supportsConfigurationDoneRequest", true.into()),
+                    ("supportsFunctionBreakpoints", true.into()),
+                    ("supportsSetVariable", true.into()),
+                    ("supportsEvaluateForHovers", true.into()),
+                    ("supportsReadMemoryRequest", true.into()),
+                    ("supportsWriteMemoryRequest", true.into()),
+                    ("supportsDisassembleRequest", true.into()),
+                ]))
+            }
+            // The program is loaded already, there is nothing to launch.
+            "launch" | "attach" => {
+                self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+                Ok(object(vec![]))
+            }
+            "configurationDone" => {
+                if self.stop_on_entry {
+                    events.push(self.stopped("entry", None));
+                } else {
+                    self.engine.resume();
+                }
+                Ok(object(vec![]))
+            }
+            "setBreakpoints" => Ok(self.set_breakpoints(args)),
+            "setFunctionBreakpoints" => Ok(self.set_function_breakpoints(args)),
+            "setExceptionBreakpoints" => Ok(object(vec![("breakpoints", Value::Array(Vec::new()))])),
+            "threads" => Ok(object(vec![(
+                "threads",
+                vec![object(vec![("id", THREAD.into()), ("name", "CHIP-8".into())])].into(),
+            )])),
+            "stackTrace" => Ok(self.stack_trace(args, cpu)),
+            "scopes" => Ok(object(vec![(
+                "scopes",
+                vec![
+                    object(vec![
+                        ("name", "Registers".into()),
+                        ("presentationHint", "registers".into()),
+                        ("variablesReference", REGISTERS.into()),
+                        ("expensive", false.into()),
+                    ]),
+                    object(vec![
+                        ("name", "Timers".into()),
+                        ("variablesReference", TIMERS.into()),
+                        ("expensive", false.into()),
+                    ]),
+                ]
+                .into(),
+            )])),
+            "variables" => Ok(variables(args, cpu)),
+            "setVariable" => set_variable(args, cpu),
+            "evaluate" => self.evaluate(args, cpu),
+            "readMemory" => read_memory(args, cpu),
+            "writeMemory" => write_memory(args, cpu),
+            "disassemble" => Ok(self.disassemble(args, cpu)),
+            "continue" => {
+                self.engine.resume();
+                Ok(object(vec![("allThreadsContinued", true.into())]))
+            }
+            "next" => {
+                match self.engine.step_over(cpu) {
+                    Ok(true) => (),
+                    stepped => events = self.stepped(stepped.map(drop), cpu),
+                }
+                Ok(object(vec![]))
+            }
+            "stepIn" => {
+                let stepped = self.engine.step(cpu);
+                events = self.stepped(stepped, cpu);
+                Ok(object(vec![]))
+            }
+            "stepOut" => self.engine.step_out(cpu).map(|()| object(vec![])),
+            "pause" => {
+                if !self.engine.is_paused() {
+                    self.engine.pause();
+                    events.push(self.stopped("pause", None));
+                }
+                Ok(object(vec![]))
+            }
+            "disconnect" | "terminate" => Ok(object(vec![])),
+            _ => Err(format!("{} is not supported", command)),
+        };
+
+        let response = self.response(request, body);
+        let mut messages = vec![response];
+        messages.extend(events);
+        messages
+    }
+
+    fn response(&self, request: &Value, body: Result<Value, String>) -> Value {
+        let mut members = vec![
+            ("type", "response".into()),
+            ("request_seq", request["seq"].clone()),
+            ("command", request["command"].clone()),
+            ("success", body.is_ok().into()),
+        ];
+        match body {
+            Ok(body) => members.push(("body", body)),
+            Err(message) => members.push(("message", message.into())),
+        }
+        object(members)
+    }
+
+    /// The events to send after the engine executed one instruction.
+    fn stepped(&self, stepped: Result<(), String>, cpu: &Cpu) -> Vec<Value> {
+        match stepped {
+            Ok(()) if cpu.exited() => self.exited(),
+            Ok(()) => vec![self.stopped("step", None)],
+            Err(err) => vec![self.stopped("exception", Some(err))],
+        }
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Value {
+        let path = args["source"]["path"].as_str().unwrap_or("").to_string();
+        let lines: Vec<u64> = match args["breakpoints"].as_array() {
+            Some(breakpoints) => breakpoints.iter().filter_map(|breakpoint| breakpoint["line"].as_u64()).collect(),
+            None => args["lines"].as_array().unwrap_or(&[]).iter().filter_map(Value::as_u64).collect(),
+        };
+
+        let mut addresses = Vec::new();
+        let mut breakpoints = Vec::new();
+        for line in lines {
+            match self.line_addresses(&path, line as usize) {
+                Some((actual, found)) => {
+                    addresses.extend(found);
+                    breakpoints.push(object(vec![("verified", true.into()), ("line", actual.into())]));
+                }
+                None => breakpoints.push(object(vec![
+                    ("verified", false.into()),
+                    ("line", line.into()),
+                    ("message", "no code at or after this line".into()),
+                ])),
+            }
+        }
+        self.line_breakpoints.insert(path, addresses);
+        self.update_breakpoints();
+        object(vec![("breakpoints", breakpoints.into())])
+    }
+
+    /// The addresses of the first line with code at or after `line` in the
+    /// file at `path`, and that line.
+    fn line_addresses(&self, path: &str, line: usize) -> Option<(usize, Vec<u16>)> {
+        let symbols = self.symbols.as_ref()?;
+        let mut files: Vec<&str> = symbols.lines.values().map(|location| location.file.as_str()).collect();
+        files.sort();
+        files.dedup();
+        files.retain(|file| same_file(path, file));
+
+        let in_file = |location: &Location| files.contains(&location.file.as_str());
+        let actual = symbols
+            .lines
+            .values()
+            .filter(|location| in_file(location) && location.line >= line)
+            .map(|location| location.line)
+            .min()?;
+        let addresses = symbols
+            .lines
+            .iter()
+            .filter(|&(_, location)| in_file(location) && location.line == actual)
+            .map(|(&address, _)| address)
+            .collect();
+        Some((actual, addresses))
+    }
+
+    fn set_function_breakpoints(&mut self, args: &Value) -> Value {
+        self.function_breakpoints.clear();
+        let mut breakpoints = Vec::new();
+        for breakpoint in args["breakpoints"].as_array().unwrap_or(&[]) {
+            let name = breakpoint["name"].as_str().unwrap_or("");
+            match self.address(name) {
+                Some(address) => {
+                    self.function_breakpoints.push(address);
+                    breakpoints.push(object(vec![("verified", true.into())]));
+                }
+                None => breakpoints.push(object(vec![
+                    ("verified", false.into()),
+                    ("message", format!("there is no label {}", name).into()),
+                ])),
+            }
+        }
+        self.update_breakpoints();
+        object(vec![("breakpoints", breakpoints.into())])
+    }
+
+    /// A label or a number.
+    fn address(&self, text: &str) -> Option<u16> {
+        if let Some(&address) = self.symbols.as_ref().and_then(|symbols| symbols.labels.get(text)) {
+            return Some(address);
+        }
+        parse_number(text)
+            .ok()
+            .filter(|&address| (address as usize) < MEMORY_SIZE)
+            .map(|address| address as u16)
+    }
+
+    /// The frame at PC and one for every `Call` on the stack, which holds
+    /// the address of the `Call` itself.
+    fn stack_trace(&self, args: &Value, cpu: &Cpu) -> Value {
+        let mut addresses = vec![cpu.pc()];
+        addresses.extend(cpu.stack().iter().rev().cloned());
+        let total = addresses.len();
+
+        let start = args["startFrame"].as_u64().unwrap_or(0) as usize;
+        let levels = match args["levels"].as_u64().unwrap_or(0) as usize {
+            0 => total,
+            levels => levels,
+        };
+        let frames: Vec<Value> = addresses
+            .into_iter()
+            .enumerate()
+            .skip(start)
+            .take(levels)
+            .map(|(id, address)| self.frame(id, address))
+            .collect();
+        object(vec![("stackFrames", frames.into()), ("totalFrames", total.into())])
+    }
+
+    fn frame(&self, id: usize, address: u16) -> Value {
+        let routine = self.symbols.as_ref().and_then(|symbols| symbols.routine(address));
+        let name = match routine {
+            Some((label, start)) if start == address => label.to_string(),
+            Some((label, start)) => format!("{}+{}", label, address - start),
+            None => format!("{:#06X}", address),
+        };
+
+        let mut members = vec![
+            ("id", id.into()),
+            ("name", name.into()),
+            ("instructionPointerReference", format!("{:#06X}", address).into()),
+        ];
+        match self.symbols.as_ref().and_then(|symbols| symbols.location(address)) {
+            Some(location) => {
+                members.push(("source", source(&location.file)));
+                members.push(("line", location.line.into()));
+                members.push(("column", 1.into()));
+            }
+            None => {
+                members.push(("line", 0.into()));
+                members.push(("column", 0.into()));
+            }
+        }
+        object(members)
+    }
+
+    fn evaluate(&self, args: &Value, cpu: &Cpu) -> Result<Value, String> {
+        let expression = args["expression"].as_str().unwrap_or("").trim();
+        let value = match read_variable(cpu, expression) {
+            Some(value) => value,
+            None => self
+                .address(expression)
+                .ok_or_else(|| format!("cannot evaluate {}", expression))?,
+        };
+        Ok(object(vec![
+            ("result", format_value(value).into()),
+            ("variablesReference", 0.into()),
+        ]))
+    }
+
+    fn disassemble(&self, args: &Value, cpu: &Cpu) -> Value {
+        let reference = args["memoryReference"].as_str().unwrap_or("0");
+        let base = parse_number(reference).unwrap_or(0) as i64 + args["offset"].as_i64().unwrap_or(0);
+        let count = args["instructionCount"].as_u64().unwrap_or(0);
+        // Instructions before the reference are assumed to be two bytes.
+        let mut address = base + 2 * args["instructionOffset"].as_i64().unwrap_or(0);
+
+        let mut instructions = Vec::new();
+        for _ in 0..count {
+            if address < 0 || address >= MEMORY_SIZE as i64 {
+                instructions.push(object(vec![
+                    ("address", format!("{:#06X}", address.max(0)).into()),
+                    ("instruction", "".into()),
+                    ("presentationHint", "invalid".into()),
+                ]));
+                address += 2;
+                continue;
+            }
+
+            let instruction = Instruction::decode_at(cpu.memory(), address as u16);
+            let bytes: String = (0..instruction.size() as usize)
+                .map(|offset| format!("{:02X}", cpu.memory()[(address as usize + offset) % MEMORY_SIZE]))
+                .collect();
+            let mut members = vec![
+                ("address", format!("{:#06X}", address).into()),
+                ("instructionBytes", bytes.into()),
+                ("instruction", format!("{:?}", instruction).into()),
+            ];
+            if let Some(ref symbols) = self.symbols {
+                if let Some(label) = symbols.label_at(address as u16) {
+                    members.push(("symbol", label.into()));
+                }
+                if let Some(location) = symbols.location(address as u16) {
+                    members.push(("location", source(&location.file)));
+                    members.push(("line", location.line.into()));
+                }
+            }
+            instructions.push(object(members));
+            address += instruction.size() as i64;
+        }
+        object(vec![("instructions", instructions.into())])
+    }
+}
+
+/// Whether the IDE's `path` is the `file` a program was compiled from.
+fn same_file(path: &str, file: &str) -> bool {
+    match (fs::canonicalize(path), fs::canonicalize(file)) {
+        (Ok(path), Ok(file)) => path == file,
+        _ => Path::new(path).ends_with(file),
+    }
+}
+
+fn source(file: &str) -> Value {
+    let path = fs::canonicalize(file)
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|_| file.to_string());
+    let name = Path::new(file)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| file.to_string());
+    object(vec![("name", name.into()), ("path", path.into())])
+}
+
+fn format_value(value: u16) -> String {
+    format!("{:#04X} ({})", value, value)
+}
+
+fn variable(name: &str, value: u16) -> Value {
+    object(vec![
+        ("name", name.into()),
+        ("value", format_value(value).into()),
+        ("variablesReference", 0.into()),
+    ])
+}
+
+fn variables(args: &Value, cpu: &Cpu) -> Value {
+    let mut variables = Vec::new();
+    match args["variablesReference"].as_u64() {
+        Some(REGISTERS) => {
+            for (x, &value) in cpu.registers().iter().enumerate() {
+                variables.push(variable(&format!("V{:X}", x), value as u16));
+            }
+            let mut i = variable("I", cpu.i());
+            if let Value::Object(ref mut members) = i {
+                members.push(("memoryReference".to_string(), format!("{:#06X}", cpu.i()).into()));
+            }
+            variables.push(i);
+            variables.push(variable("PC", cpu.pc()));
+            variables.push(variable("SP", cpu.sp()));
+        }
+        Some(TIMERS) => {
+            variables.push(variable("DT", cpu.delay_timer() as u16));
+            variables.push(variable("ST", cpu.sound_timer() as u16));
+        }
+        _ => (),
+    }
+    object(vec![("variables", variables.into())])
+}
+
+/// V0-VF, I, PC, SP, DT or ST.
+fn read_variable(cpu: &Cpu, name: &str) -> Option<u16> {
+    let name = name.to_uppercase();
+    match name.as_str() {
+        "I" => Some(cpu.i()),
+        "PC" => Some(cpu.pc()),
+        "SP" => Some(cpu.sp()),
+        "DT" => Some(cpu.delay_timer() as u16),
+        "ST" => Some(cpu.sound_timer() as u16),
+        _ => register(&name).map(|x| cpu.registers()[x] as u16),
+    }
+}
+
+fn register(name: &str) -> Option<usize> {
+    match name.strip_prefix('V') {
+        Some(digit) if digit.len() == 1 => usize::from_str_radix(digit, 16).ok(),
+        _ => None,
+    }
+}
+
+fn set_variable(args: &Value, cpu: &mut Cpu) -> Result<Value, String> {
+    let name = args["name"].as_str().unwrap_or("").to_uppercase();
+    let text = args["value"].as_str().unwrap_or("");
+    // Accept a value the way it is shown, with the decimal in parentheses.
+    let value = parse_number(text.split_whitespace().next().unwrap_or(""))?;
+    let byte = || match value {
+        0..=0xFF => Ok(value as u8),
+        _ => Err(format!("{} needs a byte", name)),
+    };
+    match name.as_str() {
+        "I" => cpu.set_i(value as u16),
+        "PC" => cpu.set_pc(value as u16),
+        "DT" => cpu.set_delay_timer(byte()?),
+        "ST" => cpu.set_sound_timer(byte()?),
+        "SP" => return Err("the call stack cannot be changed".to_string()),
+        _ => {
+            let x = register(&name).ok_or_else(|| format!("unknown variable {}", name))?;
+            cpu.set_register(x, byte()?);
+        }
+    }
+    let value = read_variable(cpu, &name).unwrap_or(0);
+    Ok(object(vec![("value", format_value(value).into())]))
+}
+
+/// The address a `memoryReference` and `offset` point at.
+fn memory_address(args: &Value) -> Result<usize, String> {
+    let reference = args["memoryReference"].as_str().unwrap_or("");
+    let address = parse_number(reference)? as i64 + args["offset"].as_i64().unwrap_or(0);
+    if address < 0 || address >= MEMORY_SIZE as i64 {
+        return Err(format!("{:#X} is outside of memory", address));
+    }
+    Ok(address as usize)
+}
+
+fn read_memory(args: &Value, cpu: &Cpu) -> Result<Value, String> {
+    let address = memory_address(args)?;
+    let count = args["count"].as_u64().unwrap_or(0) as usize;
+    let end = (address + count).min(MEMORY_SIZE);
+    Ok(object(vec![
+        ("address", format!("{:#06X}", address).into()),
+        ("data", base64_encode(&cpu.memory()[address..end]).into()),
+        ("unreadableBytes", (address + count - end).into()),
+    ]))
+}
+
+fn write_memory(args: &Value, cpu: &mut Cpu) -> Result<Value, String> {
+    let address = memory_address(args)?;
+    let data = base64_decode(args["data"].as_str().unwrap_or(""))
+        .ok_or_else(|| "invalid base64 data".to_string())?;
+    let data = &data[..data.len().min(MEMORY_SIZE - address)];
+    cpu.write_memory(address as u16, data);
+    Ok(object(vec![("bytesWritten", data.len().into())]))
+}
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut text = String::new();
+    for chunk in data.chunks(3) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (n, &byte)| bits | (byte as u32) << (16 - 8 * n));
+        for n in 0..4 {
+            if n <= chunk.len() {
+                text.push(BASE64[(bits >> (18 - 6 * n) & 0x3F) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    let (mut bits, mut count) = (0u32, 0);
+    for byte in text.bytes().filter(|&byte| byte != b'=') {
+        let value = BASE64.iter().position(|&c| c == byte)? as u32;
+        bits = bits << 6 | value;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            data.push((bits >> count) as u8);
+        }
+    }
+    Some(data)
+}
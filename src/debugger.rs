@@ -0,0 +1,646 @@
+//! An interactive debugger with breakpoints, stepping and a small command
+//! language to inspect and change the machine.
+//!
+//! The debugger takes its input one line at a time and returns the text to
+//! show, so any frontend can host it. It drives the CPU through `run_frame`
+//! in place of `Cpu::run_frame` and stops before an instruction that hits a
+//! breakpoint.
+//!
+//! The stepping itself is done by an `Engine`, which the GDB stub and the
+//! Debug Adapter Protocol server drive as well, and `Connection` is the
+//! non-blocking socket those two talk over.
+
+use cpu::{self, Cpu, MEMORY_SIZE};
+use disasm;
+use instruction::*;
+use symbols::SymbolMap;
+
+use std::fmt;
+use std::fmt::Write;
+use std::io::{self, ErrorKind, Read, Write as IoWrite};
+use std::mem;
+use std::net::{TcpListener, TcpStream};
+
+pub const HELP: &str = "\
+break ADDRESS             stop before the instruction at ADDRESS, a number or label
+break op PATTERN          stop before opcodes matching PATTERN, e.g. DXYN or 8XY6
+                          (X, Y, N, K and ? match any digit)
+delete [N]                remove breakpoint N, or all of them
+breakpoints               list the breakpoints
+step [COUNT]              execute COUNT instructions, 1 by default
+next                      step, but run a CALL until it returns
+finish                    run until the current subroutine returns
+continue                  run until a breakpoint is hit
+pause                     stop the running program
+regs                      show the registers, timers and call stack
+print NAME                show V0-VF, I, PC, SP, DT or ST
+set NAME VALUE            change V0-VF, I, PC, DT or ST
+mem ADDRESS [LENGTH]      dump memory, 64 bytes by default
+poke ADDRESS BYTE...      write bytes to memory
+disasm [ADDRESS] [COUNT]  disassemble around PC, or from ADDRESS
+Numbers are decimal or hexadecimal with 0x, $ or #. An empty line repeats
+the last command.";
+
+/// What a command that lets the program run answers.
+const RUNNING: &str = "running";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    Address(u16),
+    /// Matches the opcodes whose bits under `mask` are `value`.
+    Opcode { value: u16, mask: u16 },
+}
+
+impl Breakpoint {
+    /// Parses an opcode pattern of four hex digits where X, Y, N, K and `?`
+    /// match any digit.
+    pub fn pattern(text: &str) -> Option<Breakpoint> {
+        if text.chars().count() != 4 {
+            return None;
+        }
+        let (mut value, mut mask) = (0, 0);
+        for c in text.chars() {
+            value <<= 4;
+            mask <<= 4;
+            match c.to_ascii_uppercase() {
+                'X' | 'Y' | 'N' | 'K' | '?' => (),
+                c => {
+                    value |= c.to_digit(16)? as u16;
+                    mask |= 0xF;
+                }
+            }
+        }
+        Some(Breakpoint::Opcode { value, mask })
+    }
+
+    pub fn hits(&self, cpu: &Cpu) -> bool {
+        let pc = cpu.pc();
+        match *self {
+            Breakpoint::Address(address) => pc == address,
+            Breakpoint::Opcode { value, mask } => {
+                let memory = cpu.memory();
+                let opcode = (memory[pc as usize] as u16) << 8
+                    | memory[(pc as usize + 1) % MEMORY_SIZE] as u16;
+                opcode & mask == value
+            }
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Breakpoint::Address(address) => write!(f, "at {:#06X}", address),
+            Breakpoint::Opcode { value, mask } => {
+                write!(f, "on opcode ")?;
+                for shift in (0..4).rev().map(|digit| digit * 4) {
+                    match mask >> shift & 0xF {
+                        0 => write!(f, "?")?,
+                        _ => write!(f, "{:X}", value >> shift & 0xF)?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Paused,
+    Running,
+    /// Running until the call stack is shallower than the depth.
+    RunningUntil(u16),
+}
+
+/// Why `Engine::run_frame` paused the program.
+#[derive(Debug)]
+pub enum Stop {
+    /// The instruction at PC hits the breakpoint with this id.
+    Breakpoint(usize, Breakpoint),
+    /// A call stepped over or a routine stepped out of returned.
+    Returned,
+    Error(cpu::Error),
+    Exited,
+}
+
+/// Runs the CPU for a debugger: pauses it, lets it run until a breakpoint or
+/// until a routine returns, and steps it one instruction at a time.
+pub struct Engine {
+    breakpoints: Vec<(usize, Breakpoint)>,
+    next_id: usize,
+    state: State,
+    /// Set when the program resumes, so the breakpoint it stopped on does
+    /// not stop it again before it has moved.
+    resuming: bool,
+}
+
+impl Default for Engine {
+    fn default() -> Engine {
+        Engine::new()
+    }
+}
+
+impl Engine {
+    /// Creates an engine with the program paused.
+    pub fn new() -> Engine {
+        Engine {
+            breakpoints: Vec::new(),
+            next_id: 1,
+            state: State::Paused,
+            resuming: false,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state == State::Paused
+    }
+
+    pub fn pause(&mut self) {
+        self.state = State::Paused;
+    }
+
+    /// Lets the program run until it hits a breakpoint.
+    pub fn resume(&mut self) {
+        self.run(State::Running);
+    }
+
+    fn run(&mut self, state: State) {
+        self.state = state;
+        self.resuming = true;
+    }
+
+    /// The breakpoints with their ids, in the order they were added.
+    pub fn breakpoints(&self) -> &[(usize, Breakpoint)] {
+        &self.breakpoints
+    }
+
+    /// Adds a breakpoint and returns its id.
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.breakpoints.push((id, breakpoint));
+        id
+    }
+
+    /// Removes the breakpoint with the id `id`, returns whether there was
+    /// one.
+    pub fn remove_breakpoint(&mut self, id: usize) -> bool {
+        let count = self.breakpoints.len();
+        self.breakpoints.retain(|&(other, _)| other != id);
+        count != self.breakpoints.len()
+    }
+
+    /// Removes every breakpoint equal to `breakpoint`.
+    pub fn remove_breakpoints_like(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.retain(|&(_, other)| other != breakpoint);
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Pauses the program and executes one instruction.
+    pub fn step(&mut self, cpu: &mut Cpu) -> Result<(), String> {
+        self.state = State::Paused;
+        step(cpu)
+    }
+
+    /// Steps over the instruction at PC. A `Call` is left running until it
+    /// returns, which is when `Ok(true)` is returned, anything else is
+    /// executed right away.
+    pub fn step_over(&mut self, cpu: &mut Cpu) -> Result<bool, String> {
+        match Instruction::decode_at(cpu.memory(), cpu.pc()) {
+            Call(_) => {
+                self.run(State::RunningUntil(cpu.sp() + 1));
+                Ok(true)
+            }
+            _ => self.step(cpu).map(|()| false),
+        }
+    }
+
+    /// Lets the program run until the current subroutine returns.
+    pub fn step_out(&mut self, cpu: &Cpu) -> Result<(), String> {
+        match cpu.sp() {
+            0 => Err("not in a subroutine".to_string()),
+            depth => {
+                self.run(State::RunningUntil(depth));
+                Ok(())
+            }
+        }
+    }
+
+    /// Runs a frame the way `Cpu::run_frame` does, unless the program is
+    /// paused. Pauses in the middle of the frame, without ending it, before
+    /// an instruction that hits a breakpoint, when a step over or out is
+    /// done, on an error or when the program has exited, and returns why.
+    pub fn run_frame(&mut self, cpu: &mut Cpu) -> Option<Stop> {
+        if self.state == State::Paused {
+            return None;
+        }
+
+        for _ in 0..cpu.instructions_per_frame() {
+            if cpu.exited() {
+                return Some(self.stop(Stop::Exited));
+            }
+            if cpu.waiting_for_vblank() {
+                break;
+            }
+            if !mem::replace(&mut self.resuming, false) {
+                if let Some(&(id, breakpoint)) = self
+                    .breakpoints
+                    .iter()
+                    .find(|(_, breakpoint)| breakpoint.hits(cpu))
+                {
+                    return Some(self.stop(Stop::Breakpoint(id, breakpoint)));
+                }
+            }
+            if let Err(err) = cpu.step() {
+                return Some(self.stop(Stop::Error(err)));
+            }
+            if let State::RunningUntil(depth) = self.state {
+                if cpu.sp() < depth {
+                    return Some(self.stop(Stop::Returned));
+                }
+            }
+        }
+        cpu.end_frame();
+        None
+    }
+
+    fn stop(&mut self, stop: Stop) -> Stop {
+        self.state = State::Paused;
+        stop
+    }
+}
+
+/// A debugger's TCP connection. Reading never blocks, so the emulator keeps
+/// running while the debugger is quiet.
+pub struct Connection {
+    stream: TcpStream,
+    /// What arrived and has not been taken out yet.
+    pub input: Vec<u8>,
+}
+
+impl Connection {
+    /// Accepts a debugger waiting on `listener`, which has to be
+    /// non-blocking. Returns `None` when there is none.
+    pub fn accept(listener: &TcpListener) -> io::Result<Option<Connection>> {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(ref err) if err.kind() == ErrorKind::WouldBlock => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Some(Connection {
+            stream,
+            input: Vec::new(),
+        }))
+    }
+
+    /// Writes `data` in one go, even if the socket is full.
+    pub fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        self.stream.set_nonblocking(false)?;
+        let result = self.stream.write_all(data);
+        self.stream.set_nonblocking(true)?;
+        result
+    }
+
+    /// Appends whatever arrived to `input`. Returns false once the debugger
+    /// hung up.
+    pub fn receive(&mut self) -> io::Result<bool> {
+        let mut buffer = [0; 4096];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Ok(false),
+                Ok(n) => self.input.extend_from_slice(&buffer[..n]),
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock => return Ok(true),
+                Err(ref err) if err.kind() == ErrorKind::Interrupted => (),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+pub struct Debugger {
+    engine: Engine,
+    symbols: Option<SymbolMap>,
+    last_command: String,
+}
+
+impl Debugger {
+    /// Creates a debugger that starts out paused. The symbols of a program
+    /// compiled from source let the commands use its labels.
+    pub fn new(symbols: Option<SymbolMap>) -> Debugger {
+        Debugger {
+            engine: Engine::new(),
+            symbols,
+            last_command: String::new(),
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.engine.is_paused()
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        self.engine.add_breakpoint(breakpoint)
+    }
+
+    /// Runs a frame through the engine and returns what to tell the user
+    /// when the program stopped.
+    pub fn run_frame(&mut self, cpu: &mut Cpu) -> Option<String> {
+        let stop = self.engine.run_frame(cpu)?;
+        let location = self.location(cpu);
+        Some(match stop {
+            Stop::Breakpoint(id, breakpoint) => format!("breakpoint {} {}\n{}", id, breakpoint, location),
+            Stop::Returned => location,
+            Stop::Error(err) => format!("{}\n{}", err, location),
+            Stop::Exited => "the program exited".to_string(),
+        })
+    }
+
+    /// Executes a line of input and returns the text to show.
+    pub fn command(&mut self, line: &str, cpu: &mut Cpu) -> String {
+        let line = match line.trim() {
+            "" => self.last_command.clone(),
+            line => line.to_string(),
+        };
+        self.last_command = line.clone();
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.split_first() {
+            Some((command, args)) => self.execute(command, args, cpu).unwrap_or_else(|err| err),
+            None => String::new(),
+        }
+    }
+
+    fn execute(&mut self, command: &str, args: &[&str], cpu: &mut Cpu) -> Result<String, String> {
+        match (command, args) {
+            ("help", _) | ("h", _) => Ok(HELP.to_string()),
+            ("break", ["op", pattern]) | ("b", ["op", pattern]) => {
+                let breakpoint = Breakpoint::pattern(pattern)
+                    .ok_or_else(|| format!("invalid opcode pattern {}", pattern))?;
+                let id = self.add_breakpoint(breakpoint);
+                Ok(format!("breakpoint {} {}", id, breakpoint))
+            }
+            ("break", [address]) | ("b", [address]) => {
+                let breakpoint = Breakpoint::Address(self.address(address)?);
+                let id = self.add_breakpoint(breakpoint);
+                Ok(format!("breakpoint {} {}", id, breakpoint))
+            }
+            ("delete", []) | ("d", []) => {
+                self.engine.clear_breakpoints();
+                Ok("deleted all breakpoints".to_string())
+            }
+            ("delete", [id]) | ("d", [id]) => {
+                let id = parse_number(id)? as usize;
+                if !self.engine.remove_breakpoint(id) {
+                    return Err(format!("there is no breakpoint {}", id));
+                }
+                Ok(format!("deleted breakpoint {}", id))
+            }
+            ("breakpoints", []) | ("info", ["breakpoints"]) => Ok(self
+                .engine
+                .breakpoints()
+                .iter()
+                .map(|(id, breakpoint)| format!("{}: {}", id, breakpoint))
+                .collect::<Vec<_>>()
+                .join("\n")),
+            ("step", _) | ("s", _) if args.len() <= 1 => {
+                let count = match args.first() {
+                    Some(count) => parse_number(count)?,
+                    None => 1,
+                };
+                self.pause_for_step()?;
+                for _ in 0..count {
+                    self.engine.step(cpu)?;
+                }
+                Ok(self.location(cpu))
+            }
+            ("next", []) | ("n", []) => {
+                self.pause_for_step()?;
+                if self.engine.step_over(cpu)? {
+                    Ok(RUNNING.to_string())
+                } else {
+                    Ok(self.location(cpu))
+                }
+            }
+            ("finish", []) | ("f", []) => {
+                self.engine.step_out(cpu)?;
+                Ok(RUNNING.to_string())
+            }
+            ("continue", []) | ("c", []) => {
+                self.engine.resume();
+                Ok(RUNNING.to_string())
+            }
+            ("pause", []) => {
+                self.engine.pause();
+                Ok(self.location(cpu))
+            }
+            ("regs", []) | ("r", []) => Ok(registers(cpu)),
+            ("print", [name]) | ("p", [name]) => {
+                let value = match name.to_uppercase().as_str() {
+                    "I" => cpu.i(),
+                    "PC" => cpu.pc(),
+                    "SP" => cpu.sp(),
+                    "DT" => cpu.delay_timer() as u16,
+                    "ST" => cpu.sound_timer() as u16,
+                    _ => cpu.registers()[register(name)?] as u16,
+                };
+                Ok(format!("{} = {:#X} ({})", name, value, value))
+            }
+            ("set", [name, value]) => {
+                let value = parse_number(value)?;
+                let name = name.to_uppercase();
+                let byte = || match value {
+                    0..=0xFF => Ok(value as u8),
+                    _ => Err(format!("{} needs a byte", name)),
+                };
+                match name.as_str() {
+                    "I" => cpu.set_i(value as u16),
+                    "PC" => cpu.set_pc(value as u16),
+                    "DT" => cpu.set_delay_timer(byte()?),
+                    "ST" => cpu.set_sound_timer(byte()?),
+                    _ => cpu.set_register(register(&name)?, byte()?),
+                }
+                Ok(format!("{} = {:#X}", name, value))
+            }
+            ("mem", _) | ("x", _) if !args.is_empty() && args.len() <= 2 => {
+                let address = self.address(args[0])?;
+                let length = match args.get(1) {
+                    Some(length) => parse_number(length)? as usize,
+                    None => 64,
+                };
+                Ok(dump(cpu.memory(), address as usize, length))
+            }
+            ("poke", _) if args.len() >= 2 => {
+                let address = self.address(args[0])?;
+                let bytes = args[1..]
+                    .iter()
+                    .map(|byte| match parse_number(byte)? {
+                        byte @ 0..=0xFF => Ok(byte as u8),
+                        _ => Err(format!("{} is not a byte", byte)),
+                    })
+                    .collect::<Result<Vec<u8>, String>>()?;
+                cpu.write_memory(address, &bytes);
+                Ok(format!("wrote {} bytes at {:#06X}", bytes.len(), address))
+            }
+            ("disasm", _) | ("l", _) if args.len() <= 2 => {
+                let count = match args.get(1) {
+                    Some(count) => parse_number(count)?,
+                    None => 10,
+                };
+                Ok(match args.first() {
+                    Some(address) => self.disassemble(cpu, self.address(address)?, count),
+                    // A few instructions before PC, assuming they are all
+                    // two bytes long.
+                    None => self.disassemble(cpu, cpu.pc().saturating_sub(8), count),
+                })
+            }
+            _ => Err(format!("invalid command {:?}, try help", [&[command], args].concat().join(" "))),
+        }
+    }
+
+    fn pause_for_step(&self) -> Result<(), String> {
+        if self.engine.is_paused() {
+            Ok(())
+        } else {
+            Err("the program is running, pause it first".to_string())
+        }
+    }
+
+    /// A number or the label of a program compiled from source.
+    fn address(&self, text: &str) -> Result<u16, String> {
+        if let Some(&address) = self.symbols.as_ref().and_then(|symbols| symbols.labels.get(text)) {
+            return Ok(address);
+        }
+        match parse_number(text)? as usize {
+            address if address < MEMORY_SIZE => Ok(address as u16),
+            _ => Err(format!("{} is not an address", text)),
+        }
+    }
+
+    /// The instruction at PC, where the program is.
+    fn location(&self, cpu: &Cpu) -> String {
+        self.line(cpu, cpu.pc())
+    }
+
+    fn line(&self, cpu: &Cpu, address: u16) -> String {
+        let instruction = Instruction::decode_at(cpu.memory(), address);
+        let bytes: String = (0..instruction.size())
+            .map(|offset| format!("{:02X}", cpu.memory()[address.wrapping_add(offset) as usize]))
+            .collect();
+
+        let mut mnemonic = format!("{:?}", instruction);
+        let mut source = String::new();
+        if let Some(ref symbols) = self.symbols {
+            if let Some(label) = disasm::target(&instruction).and_then(|target| {
+                symbols
+                    .label_at(target)
+                    .map(|label| (format!("{:#06X}", target), label))
+            }) {
+                mnemonic = mnemonic.replacen(&label.0, label.1, 1);
+            }
+            if let Some(location) = symbols.location(address) {
+                source = format!("  ; {}", location);
+            }
+        }
+
+        let marker = if address == cpu.pc() { "=>" } else { "  " };
+        format!("{} {:#06X}  {:<8}  {}{}", marker, address, bytes, mnemonic, source)
+    }
+
+    fn disassemble(&self, cpu: &Cpu, start: u16, count: u32) -> String {
+        let mut out = String::new();
+        let mut address = start;
+        for _ in 0..count {
+            if let Some(label) = self.symbols.as_ref().and_then(|symbols| symbols.label_at(address)) {
+                let _ = writeln!(out, "{}:", label);
+            }
+            let _ = writeln!(out, "{}", self.line(cpu, address));
+            // Walking up to PC two bytes at a time keeps PC in step even
+            // when the disassembly starts in the middle of something.
+            address = if address < cpu.pc() {
+                address + 2
+            } else {
+                address.wrapping_add(Instruction::decode_at(cpu.memory(), address).size())
+            };
+        }
+        out.pop();
+        out
+    }
+}
+
+/// A decimal number, or a hexadecimal one with a 0x, `$` or `#` prefix.
+pub fn parse_number(text: &str) -> Result<u32, String> {
+    let lower = text.to_lowercase();
+    let hex = lower
+        .strip_prefix("0x")
+        .or_else(|| lower.strip_prefix('$'))
+        .or_else(|| lower.strip_prefix('#'));
+    match hex {
+        Some(digits) => u32::from_str_radix(digits, 16),
+        None => lower.parse(),
+    }
+    .map_err(|_| format!("invalid number {}", text))
+}
+
+/// Executes one instruction. A `Draw` held back until the vertical blank
+/// gets its frame ended first, or stepping would never get past it.
+pub fn step(cpu: &mut Cpu) -> Result<(), String> {
+    if cpu.waiting_for_vblank() {
+        cpu.end_frame();
+    }
+    cpu.step().map_err(|err| err.to_string())
+}
+
+fn register(name: &str) -> Result<usize, String> {
+    let upper = name.to_uppercase();
+    match upper.strip_prefix('V').map(|digit| usize::from_str_radix(digit, 16)) {
+        Some(Ok(x)) if upper.len() == 2 => Ok(x),
+        _ => Err(format!("unknown register {}", name)),
+    }
+}
+
+fn registers(cpu: &Cpu) -> String {
+    let mut out = String::new();
+    for (x, value) in cpu.registers().iter().enumerate() {
+        let _ = write!(out, "V{:X}={:02X}{}", x, value, if x % 8 == 7 { "\n" } else { " " });
+    }
+    let _ = write!(
+        out,
+        "I={:04X} PC={:04X} SP={} DT={:02X} ST={:02X}\nstack: {}",
+        cpu.i(),
+        cpu.pc(),
+        cpu.sp(),
+        cpu.delay_timer(),
+        cpu.sound_timer(),
+        cpu.stack()
+            .iter()
+            .map(|address| format!("{:04X}", address))
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+    out
+}
+
+/// Sixteen bytes a line, in hex and as ASCII.
+fn dump(memory: &[u8], start: usize, length: usize) -> String {
+    let end = (start + length).min(memory.len());
+    let mut lines = Vec::new();
+    for line in (start..end).step_by(16) {
+        let bytes = &memory[line..(line + 16).min(end)];
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        let text: String = bytes
+            .iter()
+            .map(|&byte| match byte {
+                0x20..=0x7E => byte as char,
+                _ => '.',
+            })
+            .collect();
+        lines.push(format!("{:#06X}  {:<47}  {}", line, hex.join(" "), text));
+    }
+    lines.join("\n")
+}
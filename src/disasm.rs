@@ -0,0 +1,305 @@
+//! Control-flow based disassembly.
+//!
+//! Code is found by walking every path from the entry point, following
+//! jumps, calls, skips and returns. Bytes that no path reaches are data. The
+//! targets of `JP V0, NNN` depend on a register at run time, so those jumps
+//! are reported as unresolved and whatever they lead to stays data.
+
+use cpu::MEMORY_SIZE;
+use instruction::*;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Where CHIP-8 programs are loaded and start.
+pub const ORIGIN: u16 = 0x200;
+
+/// Where control can go after an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    /// Continues with the next instruction.
+    Next,
+    /// Continues with the next instruction or skips over it.
+    Skip,
+    Jump(u16),
+    /// Continues at the subroutine, which returns to the next instruction.
+    Call(u16),
+    Return,
+    /// Jumps to an address computed from a register.
+    Indirect,
+    /// Stops the program, `EXIT` or an illegal opcode.
+    Stop,
+}
+
+impl Flow {
+    pub fn of(instruction: &Instruction) -> Flow {
+        match *instruction {
+            Jump(address) => Flow::Jump(address),
+            Call(address) => Flow::Call(address),
+            Return => Flow::Return,
+            JumpV0Address(_) => Flow::Indirect,
+            Exit | Illegal(_) => Flow::Stop,
+            SkipIfConstantEqual(..)
+            | SkipIfConstantNotEqual(..)
+            | SkipIfEqual(..)
+            | SkipIfNotEqual(..)
+            | SkipIfPressed(_)
+            | SkipIfNotPressed(_) => Flow::Skip,
+            _ => Flow::Next,
+        }
+    }
+}
+
+/// The address an instruction refers to, if any: a jump or call target or
+/// what it points I at.
+pub fn target(instruction: &Instruction) -> Option<u16> {
+    match *instruction {
+        Jump(address)
+        | Call(address)
+        | JumpV0Address(address)
+        | SetAddress(address)
+        | SetLongAddress(address) => Some(address),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LabelKind {
+    Entry,
+    Subroutine,
+    Branch,
+    Data,
+}
+
+pub struct Disassembly {
+    memory: Vec<u8>,
+    origin: u16,
+    end: usize,
+    /// The reachable instructions by address.
+    pub code: BTreeMap<u16, Instruction>,
+    pub labels: BTreeMap<u16, LabelKind>,
+    /// The addresses of `JP V0, NNN` instructions whose targets are unknown.
+    pub unresolved: Vec<u16>,
+    /// The addresses of reachable illegal opcodes.
+    pub illegal: Vec<u16>,
+    covered: Vec<bool>,
+}
+
+impl Disassembly {
+    /// Disassembles `rom` as loaded at `origin`, starting from `origin`.
+    pub fn new(rom: &[u8], origin: u16) -> Disassembly {
+        Disassembly::with_entries(rom, origin, &[origin])
+    }
+
+    /// Disassembles `rom` as loaded at `origin`, following every path from
+    /// each of `entries`.
+    pub fn with_entries(rom: &[u8], origin: u16, entries: &[u16]) -> Disassembly {
+        let mut memory = vec![0u8; MEMORY_SIZE];
+        let end = (origin as usize + rom.len()).min(MEMORY_SIZE);
+        memory[origin as usize..end].copy_from_slice(&rom[..end - origin as usize]);
+
+        let mut disassembly = Disassembly {
+            memory,
+            origin,
+            end,
+            code: BTreeMap::new(),
+            labels: BTreeMap::new(),
+            unresolved: Vec::new(),
+            illegal: Vec::new(),
+            covered: vec![false; MEMORY_SIZE],
+        };
+        for &entry in entries {
+            disassembly.labels.insert(entry, LabelKind::Entry);
+        }
+        disassembly.walk(entries);
+        disassembly
+    }
+
+    fn in_rom(&self, address: u16) -> bool {
+        address >= self.origin && (address as usize) < self.end
+    }
+
+    fn label(&mut self, address: u16, kind: LabelKind) {
+        let label = self.labels.entry(address).or_insert(kind);
+        *label = (*label).min(kind);
+    }
+
+    fn walk(&mut self, entries: &[u16]) {
+        let mut pending: Vec<u16> = entries.to_vec();
+        let mut seen = BTreeSet::new();
+        while let Some(address) = pending.pop() {
+            if !self.in_rom(address) || !seen.insert(address) {
+                continue;
+            }
+
+            let instruction = Instruction::decode_at(&self.memory, address);
+            let next = address.wrapping_add(instruction.size());
+            self.code.insert(address, instruction);
+            for offset in 0..instruction.size() {
+                self.covered[address.wrapping_add(offset) as usize] = true;
+            }
+
+            match Flow::of(&instruction) {
+                Flow::Next => pending.push(next),
+                Flow::Skip => {
+                    let skipped = Instruction::decode_at(&self.memory, next);
+                    pending.push(next);
+                    pending.push(next.wrapping_add(skipped.size()));
+                }
+                Flow::Jump(target) => {
+                    self.label(target, LabelKind::Branch);
+                    pending.push(target);
+                }
+                Flow::Call(target) => {
+                    self.label(target, LabelKind::Subroutine);
+                    pending.push(target);
+                    pending.push(next);
+                }
+                Flow::Indirect => self.unresolved.push(address),
+                Flow::Return => (),
+                Flow::Stop => {
+                    if let Illegal(_) = instruction {
+                        self.illegal.push(address);
+                    }
+                }
+            }
+
+            match instruction {
+                SetAddress(target) | SetLongAddress(target) if self.in_rom(target) => {
+                    self.label(target, LabelKind::Data)
+                }
+                _ => (),
+            }
+        }
+        self.unresolved.sort();
+        self.illegal.sort();
+    }
+
+    /// Whether the byte at `address` is part of a reachable instruction.
+    pub fn is_code(&self, address: u16) -> bool {
+        self.covered[address as usize]
+    }
+
+    /// The name of the label at `address`, if there is one.
+    pub fn label_name(&self, address: u16) -> Option<String> {
+        self.labels.get(&address).map(|kind| match kind {
+            LabelKind::Entry if address == self.origin => "start".to_string(),
+            LabelKind::Entry => format!("entry_{:04X}", address),
+            LabelKind::Subroutine => format!("sub_{:04X}", address),
+            LabelKind::Branch => format!("label_{:04X}", address),
+            LabelKind::Data => format!("data_{:04X}", address),
+        })
+    }
+
+    /// The `Debug` mnemonic of `instruction` with its address operand
+    /// replaced by the label there, if there is one.
+    pub fn mnemonic(&self, instruction: &Instruction) -> String {
+        let text = format!("{:?}", instruction);
+        match target(instruction).and_then(|address| {
+            self.label_name(address)
+                .map(|label| (format!("{:#06X}", address), label))
+        }) {
+            Some((address, label)) => text.replacen(&address, &label, 1),
+            None => text,
+        }
+    }
+
+    /// The disassembly as source for `asm::assemble`, without the addresses
+    /// and opcodes of the listing. It assembles back into the same bytes.
+    pub fn plain(&self) -> Plain<'_> {
+        Plain(self)
+    }
+
+    fn write(&self, f: &mut fmt::Formatter, plain: bool) -> fmt::Result {
+        writeln!(
+            f,
+            "; {} bytes at {:#06X}, {} instructions reachable",
+            self.end - self.origin as usize,
+            self.origin,
+            self.code.len()
+        )?;
+        for address in &self.unresolved {
+            writeln!(
+                f,
+                "; {:#06X}: unresolved jump table, its targets are shown as data",
+                address
+            )?;
+        }
+        // Labels outside the program have no line of their own.
+        for &address in self.labels.keys().filter(|&&address| !self.in_rom(address)) {
+            writeln!(f, "{} = {:#06X}", self.label_name(address).unwrap(), address)?;
+        }
+        if plain && self.origin != ORIGIN {
+            writeln!(f, "org {:#06X}", self.origin)?;
+        }
+
+        let mut address = self.origin as usize;
+        while address < self.end {
+            if let Some(label) = self.label_name(address as u16) {
+                writeln!(f, "\n{}:", label)?;
+            }
+
+            match self.code.get(&(address as u16)) {
+                Some(instruction) => {
+                    let size = instruction.size() as usize;
+                    let bytes = &self.memory[address..(address + size).min(MEMORY_SIZE)];
+                    if !plain {
+                        let hex: String = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+                        write!(f, "    {:#06X}  {:<8}  {}", address, hex, self.mnemonic(instruction))?;
+                    } else if address + size > self.end || matches!(instruction, Illegal(_)) {
+                        // What does not assemble from a mnemonic is kept as
+                        // bytes.
+                        let end = (address + size).min(self.end);
+                        let data: Vec<String> = self.memory[address..end]
+                            .iter()
+                            .map(|byte| format!("{:#04X}", byte))
+                            .collect();
+                        write!(f, "    db {}  ; {}", data.join(", "), self.mnemonic(instruction))?;
+                    } else {
+                        write!(f, "    {}", self.mnemonic(instruction))?;
+                    }
+                    if Flow::of(instruction) == Flow::Indirect {
+                        write!(f, "  ; unresolved")?;
+                    }
+                    writeln!(f)?;
+                    // Labels on the operand bytes of an instruction, such as
+                    // self-modifying code, have no line of their own.
+                    for inside in address + 1..(address + size).min(self.end) {
+                        if let Some(label) = self.label_name(inside as u16) {
+                            writeln!(f, "{} = {:#06X}", label, inside)?;
+                        }
+                    }
+                    address += size;
+                }
+                None => {
+                    let byte = self.memory[address];
+                    let art: String = (0..8)
+                        .map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' })
+                        .collect();
+                    if plain {
+                        writeln!(f, "    db {:#04X}  ; {}", byte, art)?;
+                    } else {
+                        writeln!(f, "    {:#06X}  {:02X}        {}", address, byte, art)?;
+                    }
+                    address += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, false)
+    }
+}
+
+/// Displays a `Disassembly` as assembler source, see `Disassembly::plain`.
+pub struct Plain<'a>(&'a Disassembly);
+
+impl<'a> fmt::Display for Plain<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.write(f, true)
+    }
+}
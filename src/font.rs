@@ -0,0 +1,33 @@
+/// Where `FONT` is loaded.
+pub const FONT_ADDRESS: u16 = 0x000;
+
+/// Where `BIG_FONT` is loaded, right after `FONT`.
+pub const BIG_FONT_ADDRESS: u16 = 0x050;
+
+/// The built-in hexadecimal font, 16 glyphs of 5 bytes each.
+///
+/// It is loaded at `FONT_ADDRESS` and `LD F, Vx` points I at glyph `Vx`.
+pub const FONT: [u8; 80] = [
+    0xf0, 0x90, 0x90, 0x90, 0xf0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xf0, 0x10, 0xf0, 0x80, 0xf0, 0xf0,
+    0x10, 0xf0, 0x10, 0xf0, 0x90, 0x90, 0xf0, 0x10, 0x10, 0xf0, 0x80, 0xf0, 0x10, 0xf0, 0xf0, 0x80,
+    0xf0, 0x90, 0xf0, 0xf0, 0x10, 0x20, 0x40, 0x40, 0xf0, 0x90, 0xf0, 0x90, 0xf0, 0xf0, 0x90, 0xf0,
+    0x10, 0xf0, 0xf0, 0x90, 0xf0, 0x90, 0x90, 0xe0, 0x90, 0xe0, 0x90, 0xe0, 0xf0, 0x80, 0x80, 0x80,
+    0x80, 0xe0, 0x90, 0x90, 0x90, 0xe0, 0xf0, 0x80, 0xf0, 0x80, 0xf0, 0xf0, 0x80, 0xf0, 0x80, 0x80,
+];
+
+/// The SUPER-CHIP big hexadecimal font, 16 glyphs of 8x10 pixels.
+///
+/// It is loaded at `BIG_FONT_ADDRESS` and `LD HF, Vx` points I at glyph `Vx`.
+/// SUPER-CHIP 1.1 only had the digits, the letters are the ones Octo added.
+pub const BIG_FONT: [u8; 160] = [
+    0xff, 0xff, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xff, 0xff, 0x18, 0x78, 0x78, 0x18, 0x18, 0x18,
+    0x18, 0x18, 0xff, 0xff, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xff, 0xff,
+    0x03, 0x03, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff, 0xc3, 0xc3, 0xc3, 0xc3, 0xff, 0xff, 0x03, 0x03,
+    0x03, 0x03, 0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff, 0xff, 0xff, 0xc0, 0xc0,
+    0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, 0xff, 0xff, 0x03, 0x03, 0x06, 0x0c, 0x18, 0x18, 0x18, 0x18,
+    0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, 0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff,
+    0x03, 0x03, 0xff, 0xff, 0x7e, 0xff, 0xc3, 0xc3, 0xc3, 0xff, 0xff, 0xc3, 0xc3, 0xc3, 0xfc, 0xfc,
+    0xc3, 0xc3, 0xfc, 0xfc, 0xc3, 0xc3, 0xfc, 0xfc, 0x3c, 0xff, 0xc3, 0xc0, 0xc0, 0xc0, 0xc0, 0xc3,
+    0xff, 0x3c, 0xfc, 0xfe, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xfe, 0xfc, 0xff, 0xff, 0xc0, 0xc0,
+    0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xc0, 0xc0, 0xc0, 0xc0,
+];
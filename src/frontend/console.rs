@@ -0,0 +1,67 @@
+//! The debugger on the terminal the emulator was started from.
+
+use chip8::debugger::Debugger;
+use chip8::symbols::SymbolMap;
+use chip8::Cpu;
+
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+const PROMPT: &str = "(chip8) ";
+
+pub struct Console {
+    pub debugger: Debugger,
+    lines: Receiver<String>,
+}
+
+impl Console {
+    /// Starts reading commands from standard input. The program starts out
+    /// paused so breakpoints can be set before it runs.
+    pub fn new(symbols: Option<SymbolMap>) -> Console {
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            let stdin = io::stdin();
+            for line in stdin.lock().lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        println!("The program is paused, type help for the debugger commands.");
+        prompt();
+        Console {
+            debugger: Debugger::new(symbols),
+            lines,
+        }
+    }
+
+    /// Executes the commands typed since the last call.
+    pub fn poll(&mut self, cpu: &mut Cpu) {
+        while let Ok(line) = self.lines.try_recv() {
+            let output = self.debugger.command(&line, cpu);
+            if !output.is_empty() {
+                println!("{}", output);
+            }
+            prompt();
+        }
+    }
+
+    /// Runs a frame through the debugger, reporting where it stopped.
+    pub fn run_frame(&mut self, cpu: &mut Cpu) {
+        if let Some(stop) = self.debugger.run_frame(cpu) {
+            println!("\n{}", stop);
+            prompt();
+        }
+    }
+}
+
+fn prompt() {
+    print!("{}", PROMPT);
+    let _ = io::stdout().flush();
+}
@@ -0,0 +1,356 @@
+//! A second window with the memory as a hex and ASCII grid, where bytes can
+//! be changed while the game runs.
+//!
+//! The arrow keys or a click select a byte and typing two hex digits
+//! replaces it. Page Up and Page Down or the mouse wheel scroll, P and I jump
+//! to PC and I, and Escape closes the window.
+
+use chip8::cpu::MEMORY_SIZE;
+use chip8::font::{BIG_FONT, BIG_FONT_ADDRESS, FONT_ADDRESS};
+use chip8::{Cpu, Instruction};
+
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::WindowCanvas;
+use sdl2::VideoSubsystem;
+
+use super::text::{self, GLYPH_HEIGHT, GLYPH_WIDTH};
+
+const SCALE: u32 = 2;
+const MARGIN: i32 = 8;
+const COLUMN_WIDTH: i32 = ((GLYPH_WIDTH + 1) * SCALE) as i32;
+const LINE_HEIGHT: i32 = ((GLYPH_HEIGHT + 2) * SCALE) as i32;
+
+const BYTES_PER_ROW: usize = 16;
+const ROWS: usize = 32;
+/// The columns the hex and ASCII parts of a row start at.
+const HEX_COLUMN: i32 = 6;
+const ASCII_COLUMN: i32 = HEX_COLUMN + 3 * BYTES_PER_ROW as i32 + 1;
+/// The memory rows, then a line for the selected byte and one for the key.
+const LINES: i32 = ROWS as i32 + 2;
+
+const WIDTH: u32 = (2 * MARGIN + (ASCII_COLUMN + BYTES_PER_ROW as i32) * COLUMN_WIDTH) as u32;
+const HEIGHT: u32 = (2 * MARGIN + LINES * LINE_HEIGHT) as u32;
+
+const TEXT: Color = Color {
+    r: 200,
+    g: 200,
+    b: 200,
+    a: 255,
+};
+const DIM: Color = Color {
+    r: 96,
+    g: 96,
+    b: 96,
+    a: 255,
+};
+const FONT_TEXT: Color = Color {
+    r: 180,
+    g: 130,
+    b: 255,
+    a: 255,
+};
+const PC_BACKGROUND: Color = Color {
+    r: 128,
+    g: 96,
+    b: 0,
+    a: 255,
+};
+const I_BACKGROUND: Color = Color {
+    r: 0,
+    g: 64,
+    b: 160,
+    a: 255,
+};
+const STORE_BACKGROUND: Color = Color {
+    r: 0,
+    g: 112,
+    b: 48,
+    a: 255,
+};
+const CURSOR: Color = Color {
+    r: 255,
+    g: 255,
+    b: 255,
+    a: 255,
+};
+
+pub struct MemoryWindow {
+    canvas: WindowCanvas,
+    /// The address of the first byte shown.
+    top: usize,
+    cursor: usize,
+    /// The first digit typed over the byte at the cursor.
+    pending: Option<u8>,
+}
+
+impl MemoryWindow {
+    /// Opens the window scrolled to PC.
+    pub fn open(video: &VideoSubsystem, cpu: &Cpu) -> Result<MemoryWindow, String> {
+        let window = video
+            .window("CHIP-8 memory", WIDTH, HEIGHT)
+            .build()
+            .map_err(|err| err.to_string())?;
+        let canvas = window.into_canvas().build().map_err(|err| err.to_string())?;
+        let mut memory = MemoryWindow {
+            canvas,
+            top: 0,
+            cursor: 0,
+            pending: None,
+        };
+        memory.select(cpu.pc() as usize);
+        Ok(memory)
+    }
+
+    pub fn id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    /// Handles an event for this window. Returns false when the window
+    /// should be closed.
+    pub fn handle(&mut self, event: &Event, cpu: &mut Cpu) -> bool {
+        match *event {
+            Event::Window {
+                win_event: WindowEvent::Close,
+                ..
+            } => return false,
+            Event::KeyDown {
+                keycode: Some(keycode),
+                ..
+            } => match keycode {
+                Keycode::Escape => return false,
+                Keycode::Up => self.move_cursor(-(BYTES_PER_ROW as isize)),
+                Keycode::Down => self.move_cursor(BYTES_PER_ROW as isize),
+                Keycode::Left => self.move_cursor(-1),
+                Keycode::Right => self.move_cursor(1),
+                Keycode::PageUp => self.move_cursor(-((ROWS * BYTES_PER_ROW) as isize)),
+                Keycode::PageDown => self.move_cursor((ROWS * BYTES_PER_ROW) as isize),
+                Keycode::P => self.select(cpu.pc() as usize),
+                Keycode::I => self.select(cpu.i() as usize),
+                Keycode::Backspace => self.pending = None,
+                _ => {
+                    if let Some(digit) = hex_digit(keycode) {
+                        self.type_digit(digit, cpu);
+                    }
+                }
+            },
+            Event::MouseButtonDown { x, y, .. } => {
+                if let Some(address) = self.address_at(x, y) {
+                    self.select(address);
+                }
+            }
+            Event::MouseWheel { y, .. } => {
+                let rows = -y as isize * 3;
+                self.scroll(rows * BYTES_PER_ROW as isize);
+            }
+            _ => (),
+        }
+        true
+    }
+
+    fn type_digit(&mut self, digit: u8, cpu: &mut Cpu) {
+        match self.pending.take() {
+            None => self.pending = Some(digit),
+            Some(high) => {
+                cpu.write_memory(self.cursor as u16, &[high << 4 | digit]);
+                self.move_cursor(1);
+            }
+        }
+    }
+
+    fn move_cursor(&mut self, offset: isize) {
+        let cursor = (self.cursor as isize + offset).max(0).min(MEMORY_SIZE as isize - 1);
+        self.select(cursor as usize);
+    }
+
+    /// Moves the cursor to `address`, scrolling it into view.
+    fn select(&mut self, address: usize) {
+        self.cursor = address;
+        self.pending = None;
+        let row = address - address % BYTES_PER_ROW;
+        if row < self.top {
+            self.top = row;
+        } else if row >= self.top + ROWS * BYTES_PER_ROW {
+            self.top = row + BYTES_PER_ROW - ROWS * BYTES_PER_ROW;
+        }
+    }
+
+    fn scroll(&mut self, offset: isize) {
+        let last = (MEMORY_SIZE - ROWS * BYTES_PER_ROW) as isize;
+        self.top = (self.top as isize + offset).max(0).min(last) as usize;
+    }
+
+    /// The byte under the mouse, in either the hex or the ASCII part.
+    fn address_at(&self, x: i32, y: i32) -> Option<usize> {
+        let column = (x - MARGIN) / COLUMN_WIDTH;
+        let row = (y - MARGIN) / LINE_HEIGHT;
+        if x < MARGIN || y < MARGIN || row >= ROWS as i32 {
+            return None;
+        }
+        let offset = if (HEX_COLUMN..ASCII_COLUMN - 1).contains(&column) {
+            (column - HEX_COLUMN) / 3
+        } else if (ASCII_COLUMN..ASCII_COLUMN + BYTES_PER_ROW as i32).contains(&column) {
+            column - ASCII_COLUMN
+        } else {
+            return None;
+        };
+        Some(self.top + row as usize * BYTES_PER_ROW + offset as usize)
+    }
+
+    pub fn draw(&mut self, cpu: &Cpu) {
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.clear();
+
+        let pc = cpu.pc() as usize;
+        let pc_end = pc + Instruction::decode_at(cpu.memory(), cpu.pc()).size() as usize;
+        let store = match cpu.last_store() {
+            Some((start, length)) => start as usize..start as usize + length as usize,
+            None => 0..0,
+        };
+        let font = FONT_ADDRESS as usize..BIG_FONT_ADDRESS as usize + BIG_FONT.len();
+
+        for row in 0..ROWS {
+            let start = self.top + row * BYTES_PER_ROW;
+            self.text(0, row as i32, DIM, &format!("{:04X}", start));
+            for (n, &byte) in cpu.memory()[start..start + BYTES_PER_ROW].iter().enumerate() {
+                let address = start + n;
+                let background = if (pc..pc_end).contains(&address) {
+                    Some(PC_BACKGROUND)
+                } else if address == cpu.i() as usize {
+                    Some(I_BACKGROUND)
+                } else if store.contains(&address) {
+                    Some(STORE_BACKGROUND)
+                } else {
+                    None
+                };
+                let color = if font.contains(&address) { FONT_TEXT } else { TEXT };
+
+                let hex_column = HEX_COLUMN + 3 * n as i32;
+                let ascii_column = ASCII_COLUMN + n as i32;
+                if let Some(background) = background {
+                    self.fill_cells(hex_column, row as i32, 2, background);
+                    self.fill_cells(ascii_column, row as i32, 1, background);
+                }
+
+                let hex = match self.pending {
+                    Some(high) if address == self.cursor => format!("{:X}_", high),
+                    _ => format!("{:02X}", byte),
+                };
+                self.text(hex_column, row as i32, color, &hex);
+                let c = match byte {
+                    0x20..=0x7E => byte as char,
+                    _ => '.',
+                };
+                self.text(ascii_column, row as i32, color, &c.to_string());
+
+                if address == self.cursor {
+                    self.outline_cells(hex_column, row as i32, 2);
+                    self.outline_cells(ascii_column, row as i32, 1);
+                }
+            }
+        }
+
+        let value = cpu.memory()[self.cursor];
+        let mut status = format!(
+            "{:04X} = {:02X} ({})   PC {:04X}   I {:04X}",
+            self.cursor,
+            value,
+            value,
+            cpu.pc(),
+            cpu.i()
+        );
+        if let Some((start, length)) = cpu.last_store() {
+            status.push_str(&format!("   STORE {:04X}+{}", start, length));
+        }
+        self.text(0, ROWS as i32, TEXT, &status);
+
+        let mut column = 0;
+        for &(name, color) in &[
+            ("PC", PC_BACKGROUND),
+            ("I", I_BACKGROUND),
+            ("STORE", STORE_BACKGROUND),
+        ] {
+            self.fill_cells(column, ROWS as i32 + 1, name.len() as i32, color);
+            self.text(column, ROWS as i32 + 1, TEXT, name);
+            column += name.len() as i32 + 1;
+        }
+        self.text(column, ROWS as i32 + 1, FONT_TEXT, "FONT");
+        self.text(column + 5, ROWS as i32 + 1, DIM, "0-F edit, P/I go to PC/I, Esc closes");
+
+        self.canvas.present();
+    }
+
+    fn text(&mut self, column: i32, line: i32, color: Color, text: &str) {
+        self.canvas.set_draw_color(color);
+        text::draw(
+            &mut self.canvas,
+            MARGIN + column * COLUMN_WIDTH,
+            MARGIN + line * LINE_HEIGHT,
+            SCALE,
+            text,
+        );
+    }
+
+    /// The rectangle around `count` character cells, with a pixel of room.
+    fn cells(column: i32, line: i32, count: i32) -> Rect {
+        Rect::new(
+            MARGIN + column * COLUMN_WIDTH - 2,
+            MARGIN + line * LINE_HEIGHT - 2,
+            (count * COLUMN_WIDTH + 2) as u32,
+            (LINE_HEIGHT - 2) as u32,
+        )
+    }
+
+    fn fill_cells(&mut self, column: i32, line: i32, count: i32, color: Color) {
+        self.canvas.set_draw_color(color);
+        self.canvas
+            .fill_rect(MemoryWindow::cells(column, line, count))
+            .unwrap();
+    }
+
+    fn outline_cells(&mut self, column: i32, line: i32, count: i32) {
+        self.canvas.set_draw_color(CURSOR);
+        self.canvas
+            .draw_rect(MemoryWindow::cells(column, line, count))
+            .unwrap();
+    }
+}
+
+/// Which window an event is for, for the events that have one.
+pub fn window_id(event: &Event) -> Option<u32> {
+    match *event {
+        Event::Window { window_id, .. }
+        | Event::KeyDown { window_id, .. }
+        | Event::KeyUp { window_id, .. }
+        | Event::TextInput { window_id, .. }
+        | Event::MouseMotion { window_id, .. }
+        | Event::MouseButtonDown { window_id, .. }
+        | Event::MouseButtonUp { window_id, .. }
+        | Event::MouseWheel { window_id, .. } => Some(window_id),
+        _ => None,
+    }
+}
+
+fn hex_digit(keycode: Keycode) -> Option<u8> {
+    match keycode {
+        Keycode::Num0 | Keycode::Kp0 => Some(0x0),
+        Keycode::Num1 | Keycode::Kp1 => Some(0x1),
+        Keycode::Num2 | Keycode::Kp2 => Some(0x2),
+        Keycode::Num3 | Keycode::Kp3 => Some(0x3),
+        Keycode::Num4 | Keycode::Kp4 => Some(0x4),
+        Keycode::Num5 | Keycode::Kp5 => Some(0x5),
+        Keycode::Num6 | Keycode::Kp6 => Some(0x6),
+        Keycode::Num7 | Keycode::Kp7 => Some(0x7),
+        Keycode::Num8 | Keycode::Kp8 => Some(0x8),
+        Keycode::Num9 | Keycode::Kp9 => Some(0x9),
+        Keycode::A => Some(0xA),
+        Keycode::B => Some(0xB),
+        Keycode::C => Some(0xC),
+        Keycode::D => Some(0xD),
+        Keycode::E => Some(0xE),
+        Keycode::F => Some(0xF),
+        _ => None,
+    }
+}
@@ -0,0 +1,547 @@
+use chip8::movie::Movie;
+use chip8::profile::Profiler;
+use chip8::rewind::Rewind;
+use chip8::rom;
+use chip8::screen::PALETTE;
+use chip8::config::Config;
+use chip8::coverage::Coverage;
+use chip8::dap::DapServer;
+use chip8::gdb::GdbServer;
+use chip8::symbols::SymbolMap;
+use chip8::trace::Tracer;
+use chip8::Cpu;
+
+use sdl2;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{self, Keycode};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The length of one 60 Hz frame.
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// How many frames of history the rewind key can go back, three minutes.
+const REWIND_FRAMES: usize = 60 * 60 * 3;
+
+const WINDOW_WIDTH: u32 = 768;
+const WINDOW_HEIGHT: u32 = 384;
+
+mod console;
+mod memory;
+mod overlay;
+mod text;
+
+use self::console::Console;
+use self::memory::MemoryWindow;
+
+/// What runs the program in place of `Cpu::run_frame` while it is being
+/// debugged.
+enum Debugging {
+    Console(Console),
+    Gdb(GdbServer),
+    Dap(DapServer),
+}
+
+impl Debugging {
+    /// Handles the commands that came in since the last call.
+    fn poll(&mut self, cpu: &mut Cpu) -> io::Result<()> {
+        match self {
+            Debugging::Console(console) => {
+                console.poll(cpu);
+                Ok(())
+            }
+            Debugging::Gdb(gdb) => gdb.poll(cpu),
+            Debugging::Dap(dap) => dap.poll(cpu),
+        }
+    }
+
+    fn is_paused(&self) -> bool {
+        match self {
+            Debugging::Console(console) => console.debugger.is_paused(),
+            Debugging::Gdb(gdb) => gdb.is_paused(),
+            Debugging::Dap(dap) => dap.is_paused(),
+        }
+    }
+
+    fn run_frame(&mut self, cpu: &mut Cpu) {
+        match self {
+            Debugging::Console(console) => console.run_frame(cpu),
+            Debugging::Gdb(gdb) => gdb.run_frame(cpu),
+            Debugging::Dap(dap) => dap.run_frame(cpu),
+        }
+    }
+}
+
+/// What happens to the keys of each frame.
+enum MovieMode {
+    Recording { movie: Movie, path: PathBuf },
+    Playing { movie: Movie, frame: usize },
+}
+
+pub struct Chip8 {
+    cpu: Cpu,
+    canvas: sdl2::render::WindowCanvas,
+    events: sdl2::EventPump,
+    video: sdl2::VideoSubsystem,
+    game_path: Option<PathBuf>,
+    rewind: Rewind,
+    rewinding: bool,
+    movie: Option<MovieMode>,
+    /// The symbols of a program compiled from source.
+    symbols: Option<SymbolMap>,
+    debugging: Option<Debugging>,
+    /// Whether the debug overlay is drawn over the game.
+    overlay: bool,
+    memory: Option<MemoryWindow>,
+    /// Where F10 writes the profile, when profiling.
+    profile_path: Option<PathBuf>,
+    /// Where the coverage is written on exit, when it is tracked.
+    coverage_path: Option<PathBuf>,
+    /// The program as loaded, before it modified itself.
+    rom: Vec<u8>,
+}
+
+impl Chip8 {
+    pub fn new() -> Chip8 {
+        debug!("Creating SDL2 context");
+        let sdl_context = sdl2::init().unwrap();
+
+        debug!("Initializing SDL2 video subsystem");
+        let video = sdl_context.video().unwrap();
+
+        debug!("Creating a window");
+        let window = video
+            .window("CHIP-8", WINDOW_WIDTH, WINDOW_HEIGHT)
+            .position_centered()
+            .build()
+            .unwrap();
+
+        debug!("Creating a canvas");
+        let canvas = window.into_canvas().build().unwrap();
+
+        debug!("Initializing SDL2 event pump");
+        let events = sdl_context.event_pump().unwrap();
+
+        Chip8 {
+            cpu: Cpu::new(),
+            canvas,
+            events,
+            video,
+            game_path: None,
+            rewind: Rewind::new(REWIND_FRAMES),
+            rewinding: false,
+            movie: None,
+            symbols: None,
+            debugging: None,
+            overlay: false,
+            memory: None,
+            profile_path: None,
+            coverage_path: None,
+            rom: Vec::new(),
+        }
+    }
+
+    pub fn configure(&mut self, config: &Config) {
+        config.apply(&mut self.cpu);
+    }
+
+    /// Records the keys of every frame into a movie that is written to
+    /// `path` when the emulation stops. Call this after `load`.
+    pub fn record_movie<P: AsRef<Path>>(&mut self, path: P) -> Result<(), String> {
+        let movie = Movie::new(&self.cpu)
+            .ok_or("a custom random source cannot be recorded")?;
+        self.movie = Some(MovieMode::Recording {
+            movie,
+            path: path.as_ref().to_path_buf(),
+        });
+        Ok(())
+    }
+
+    /// Replays the movie at `path`, replacing the quirks, instruction rate
+    /// and random seed with the recorded ones. Call this after `load`.
+    pub fn play_movie<P: AsRef<Path>>(&mut self, path: P) -> Result<(), String> {
+        let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let movie = Movie::parse(&text).map_err(|err| err.to_string())?;
+        movie.configure(&mut self.cpu);
+        self.movie = Some(MovieMode::Playing { movie, frame: 0 });
+        Ok(())
+    }
+
+    /// Writes a trace record for every instruction to `path`, as JSON lines
+    /// for `.jsonl` files and in the binary format otherwise.
+    pub fn trace<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        self.cpu.set_tracer(Tracer::create(path)?);
+        Ok(())
+    }
+
+    /// Counts the instructions the program executes. The report is written
+    /// to `path` when F10 is pressed and when the emulation stops.
+    pub fn profile<P: AsRef<Path>>(&mut self, path: P) {
+        self.cpu.set_profiler(Profiler::new());
+        self.profile_path = Some(path.as_ref().to_path_buf());
+    }
+
+    /// Notes which memory the program executes, reads and writes, and
+    /// writes it to `path` when the emulation stops.
+    pub fn track_coverage<P: AsRef<Path>>(&mut self, path: P) {
+        self.cpu.set_coverage(Coverage::new());
+        self.coverage_path = Some(path.as_ref().to_path_buf());
+    }
+
+    /// Loads a ROM, or compiles and loads an Octo or assembly source.
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<usize, String> {
+        let rom = rom::read(&path)?;
+        self.game_path = Some(path.as_ref().to_path_buf());
+        self.symbols = rom.symbols;
+        self.rom = rom.data;
+        Ok(self.cpu.load_rom(&self.rom))
+    }
+
+    /// Runs the program under the debugger, taking commands from the
+    /// terminal. Call this after `load`.
+    pub fn debug(&mut self) {
+        self.debugging = Some(Debugging::Console(Console::new(self.symbols.clone())));
+    }
+
+    /// Waits for `gdb` or `lldb` to attach on `port` of the loopback
+    /// interface before running the program.
+    pub fn serve_gdb(&mut self, port: u16) -> io::Result<()> {
+        let gdb = GdbServer::bind(port)?;
+        info!("Waiting for a debugger on port {}", gdb.port()?);
+        self.debugging = Some(Debugging::Gdb(gdb));
+        Ok(())
+    }
+
+    /// Waits for an IDE to connect with the Debug Adapter Protocol on `port`
+    /// of the loopback interface before running the program. Call this
+    /// after `load`.
+    pub fn serve_dap(&mut self, port: u16) -> io::Result<()> {
+        let dap = DapServer::bind(port, self.symbols.clone())?;
+        info!("Waiting for an IDE on port {}", dap.port()?);
+        self.debugging = Some(Debugging::Dap(dap));
+        Ok(())
+    }
+
+    pub fn run(&mut self) {
+        let mut frame_instant = Instant::now();
+        let mut cpu_error = false;
+
+        debug!("Starting the emulation loop");
+        'outer: loop {
+            let events: Vec<Event> = self.events.poll_iter().collect();
+            for event in events {
+                if let Some(ref mut memory) = self.memory {
+                    if memory::window_id(&event) == Some(memory.id()) {
+                        if !memory.handle(&event, &mut self.cpu) {
+                            self.memory = None;
+                        }
+                        continue;
+                    }
+                }
+
+                match event {
+                    Event::Quit { .. }
+                    | Event::Window {
+                        win_event: WindowEvent::Close,
+                        ..
+                    }
+                    | Event::KeyDown {
+                        keycode: Some(Keycode::Escape),
+                        ..
+                    } => break 'outer,
+                    Event::KeyDown {
+                        keycode: Some(keycode),
+                        keymod,
+                        repeat: false,
+                        ..
+                    } if state_slot(keycode).is_some() =>
+                    {
+                        let slot = state_slot(keycode).unwrap();
+                        if keymod.intersects(keyboard::LSHIFTMOD | keyboard::RSHIFTMOD) {
+                            self.save_state(slot);
+                        } else {
+                            self.load_state(slot);
+                            cpu_error = false;
+                        }
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Backspace),
+                        repeat: false,
+                        ..
+                    } => {
+                        if self.movie.is_some() {
+                            warn!("Cannot rewind while a movie is recording or playing");
+                        } else {
+                            self.rewinding = true;
+                        }
+                    }
+                    Event::KeyUp {
+                        keycode: Some(Keycode::Backspace),
+                        ..
+                    } => self.rewinding = false,
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Tab),
+                        repeat: false,
+                        ..
+                    } => self.overlay = !self.overlay,
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F12),
+                        repeat: false,
+                        ..
+                    } => self.toggle_memory(),
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F10),
+                        repeat: false,
+                        ..
+                    } => self.write_profile(),
+                    Event::KeyDown { keycode, .. } => match keycode {
+                        Some(Keycode::Num1) => self.cpu.keys[0x1] = 1,
+                        Some(Keycode::Num2) => self.cpu.keys[0x2] = 1,
+                        Some(Keycode::Num3) => self.cpu.keys[0x3] = 1,
+                        Some(Keycode::Num4) => self.cpu.keys[0xC] = 1,
+                        Some(Keycode::Q) => self.cpu.keys[0x4] = 1,
+                        Some(Keycode::W) => self.cpu.keys[0x5] = 1,
+                        Some(Keycode::E) => self.cpu.keys[0x6] = 1,
+                        Some(Keycode::R) => self.cpu.keys[0xD] = 1,
+                        Some(Keycode::A) => self.cpu.keys[0x7] = 1,
+                        Some(Keycode::S) => self.cpu.keys[0x8] = 1,
+                        Some(Keycode::D) => self.cpu.keys[0x9] = 1,
+                        Some(Keycode::F) => self.cpu.keys[0xE] = 1,
+                        Some(Keycode::Z) => self.cpu.keys[0xA] = 1,
+                        Some(Keycode::X) => self.cpu.keys[0x0] = 1,
+                        Some(Keycode::C) => self.cpu.keys[0xB] = 1,
+                        Some(Keycode::V) => self.cpu.keys[0xF] = 1,
+                        _ => (),
+                    },
+                    Event::KeyUp { keycode, .. } => match keycode {
+                        Some(Keycode::Num1) => self.cpu.keys[0x1] = 0,
+                        Some(Keycode::Num2) => self.cpu.keys[0x2] = 0,
+                        Some(Keycode::Num3) => self.cpu.keys[0x3] = 0,
+                        Some(Keycode::Num4) => self.cpu.keys[0xC] = 0,
+                        Some(Keycode::Q) => self.cpu.keys[0x4] = 0,
+                        Some(Keycode::W) => self.cpu.keys[0x5] = 0,
+                        Some(Keycode::E) => self.cpu.keys[0x6] = 0,
+                        Some(Keycode::R) => self.cpu.keys[0xD] = 0,
+                        Some(Keycode::A) => self.cpu.keys[0x7] = 0,
+                        Some(Keycode::S) => self.cpu.keys[0x8] = 0,
+                        Some(Keycode::D) => self.cpu.keys[0x9] = 0,
+                        Some(Keycode::F) => self.cpu.keys[0xE] = 0,
+                        Some(Keycode::Z) => self.cpu.keys[0xA] = 0,
+                        Some(Keycode::X) => self.cpu.keys[0x0] = 0,
+                        Some(Keycode::C) => self.cpu.keys[0xB] = 0,
+                        Some(Keycode::V) => self.cpu.keys[0xF] = 0,
+                        _ => (),
+                    },
+                    _ => (),
+                }
+            }
+
+            if let Some(ref mut debugging) = self.debugging {
+                if let Err(err) = debugging.poll(&mut self.cpu) {
+                    error!("The debug server stopped: {}", err);
+                    self.debugging = None;
+                }
+            }
+
+            if frame_instant.elapsed() < FRAME {
+                thread::sleep(Duration::from_millis(1));
+                continue;
+            }
+            frame_instant += FRAME;
+
+            if self.rewinding {
+                if let Some(state) = self.rewind.step_back() {
+                    if let Err(err) = self.cpu.load_state(state) {
+                        error!("Could not rewind: {}", err);
+                    }
+                    cpu_error = false;
+                }
+            } else if let Some(ref mut debugging) = self.debugging {
+                // The debuggers report errors and stop on them themselves.
+                if !debugging.is_paused() {
+                    debugging.run_frame(&mut self.cpu);
+                    self.rewind.push(self.cpu.save_state());
+                }
+            } else if !cpu_error {
+                self.advance_movie();
+                if let Err(error) = self.cpu.run_frame() {
+                    error!("CPU encountered an error: {:?}", error);
+                    cpu_error = true;
+                }
+                self.rewind.push(self.cpu.save_state());
+
+                if self.cpu.exited() {
+                    info!("The program exited");
+                    break 'outer;
+                }
+            }
+
+            self.draw();
+        }
+
+        self.finish_movie();
+        self.write_profile();
+        self.write_coverage();
+        if let Some(tracer) = self.cpu.take_tracer() {
+            if let Err(err) = tracer.finish() {
+                error!("Could not write the trace: {}", err);
+            }
+        }
+    }
+
+    /// Records or replays the keys for the frame that is about to run.
+    fn advance_movie(&mut self) {
+        let ended = match self.movie {
+            Some(MovieMode::Recording { ref mut movie, .. }) => {
+                movie.record(&self.cpu);
+                false
+            }
+            Some(MovieMode::Playing {
+                ref movie,
+                ref mut frame,
+            }) => {
+                let playing = movie.play(*frame, &mut self.cpu);
+                *frame += 1;
+                !playing
+            }
+            None => false,
+        };
+        if ended {
+            info!("The movie has ended, the keyboard is live again");
+            self.movie = None;
+        }
+    }
+
+    fn finish_movie(&mut self) {
+        if let Some(MovieMode::Recording { ref movie, ref path }) = self.movie {
+            match fs::write(path, movie.to_string()) {
+                Ok(()) => info!("Wrote {} frames to {:?}", movie.frames.len(), path),
+                Err(err) => error!("Could not write the movie to {:?}: {}", path, err),
+            }
+        }
+    }
+
+    fn write_profile(&self) {
+        if let (Some(path), Some(profiler)) = (&self.profile_path, self.cpu.profiler()) {
+            let report = profiler.report(self.cpu.memory(), self.symbols.as_ref());
+            match fs::write(path, report) {
+                Ok(()) => info!("Wrote the profile of {} instructions to {:?}", profiler.cycles(), path),
+                Err(err) => error!("Could not write the profile to {:?}: {}", path, err),
+            }
+        }
+    }
+
+    fn write_coverage(&self) {
+        if let (Some(path), Some(coverage)) = (&self.coverage_path, self.cpu.coverage()) {
+            match coverage.save(path, &self.rom, self.symbols.as_ref()) {
+                Ok(()) => info!("Wrote the coverage to {:?}", path),
+                Err(err) => error!("Could not write the coverage to {:?}: {}", path, err),
+            }
+        }
+    }
+
+    /// The file save state `slot` is kept in, next to the game.
+    fn state_path(&self, slot: u8) -> PathBuf {
+        let mut path = self
+            .game_path
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("chip8"))
+            .into_os_string();
+        path.push(format!(".state{}", slot));
+        PathBuf::from(path)
+    }
+
+    fn save_state(&mut self, slot: u8) {
+        let path = self.state_path(slot);
+        match fs::write(&path, self.cpu.save_state()) {
+            Ok(()) => info!("Saved state {} to {:?}", slot, path),
+            Err(err) => error!("Could not save state to {:?}: {}", path, err),
+        }
+    }
+
+    fn load_state(&mut self, slot: u8) {
+        if self.movie.is_some() {
+            warn!("Cannot load a state while a movie is recording or playing");
+            return;
+        }
+        let path = self.state_path(slot);
+        let result = fs::read(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|data| self.cpu.load_state(&data).map_err(|err| err.to_string()));
+        match result {
+            Ok(()) => info!("Loaded state {} from {:?}", slot, path),
+            Err(err) => error!("Could not load state from {:?}: {}", path, err),
+        }
+    }
+
+    fn toggle_memory(&mut self) {
+        if self.memory.take().is_some() {
+            return;
+        }
+        match MemoryWindow::open(&self.video, &self.cpu) {
+            Ok(memory) => self.memory = Some(memory),
+            Err(err) => error!("Could not open the memory window: {}", err),
+        }
+    }
+
+    fn draw(&mut self) {
+        self.canvas.set_draw_color(color(0));
+        self.canvas.clear();
+
+        let scale = WINDOW_WIDTH / self.cpu.screen_width() as u32;
+        for y in 0..self.cpu.screen_height() {
+            for x in 0..self.cpu.screen_width() {
+                let pixel = self.cpu.pixel(x, y) as usize;
+                if pixel == 0 {
+                    continue;
+                }
+                self.canvas.set_draw_color(color(pixel));
+                self.canvas
+                    .fill_rect(Rect::new(
+                        x as i32 * scale as i32,
+                        y as i32 * scale as i32,
+                        scale,
+                        scale,
+                    ))
+                    .unwrap();
+            }
+        }
+
+        if self.overlay {
+            overlay::draw(&mut self.canvas, &self.cpu, self.symbols.as_ref());
+        }
+
+        self.canvas.present();
+
+        if let Some(ref mut memory) = self.memory {
+            memory.draw(&self.cpu);
+        }
+    }
+}
+
+/// F1 to F9 load save state slots 1 to 9, with shift held they save them.
+fn state_slot(keycode: Keycode) -> Option<u8> {
+    match keycode {
+        Keycode::F1 => Some(1),
+        Keycode::F2 => Some(2),
+        Keycode::F3 => Some(3),
+        Keycode::F4 => Some(4),
+        Keycode::F5 => Some(5),
+        Keycode::F6 => Some(6),
+        Keycode::F7 => Some(7),
+        Keycode::F8 => Some(8),
+        Keycode::F9 => Some(9),
+        _ => None,
+    }
+}
+
+fn color(pixel: usize) -> Color {
+    let [r, g, b] = PALETTE[pixel];
+    Color::RGB(r, g, b)
+}
@@ -0,0 +1,160 @@
+//! A panel drawn over the game with the registers, the call stack, the keys
+//! and a live disassembly around PC.
+
+use chip8::disasm;
+use chip8::symbols::SymbolMap;
+use chip8::{Cpu, Instruction};
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, WindowCanvas};
+
+use super::text::{self, GLYPH_HEIGHT, GLYPH_WIDTH};
+
+const SCALE: u32 = 2;
+const MARGIN: i32 = 8;
+const COLUMN_WIDTH: i32 = ((GLYPH_WIDTH + 1) * SCALE) as i32;
+const LINE_HEIGHT: i32 = ((GLYPH_HEIGHT + 2) * SCALE) as i32;
+
+/// The column the keypad is drawn at, right of the registers.
+const KEYPAD_COLUMN: i32 = 50;
+
+/// The keypad as it is laid out on the VIP.
+const KEYPAD: [[u8; 4]; 4] = [[0x1, 0x2, 0x3, 0xC], [0x4, 0x5, 0x6, 0xD], [0x7, 0x8, 0x9, 0xE], [0xA, 0x0, 0xB, 0xF]];
+
+/// How many instructions of the disassembly come before PC.
+const INSTRUCTIONS_BEFORE_PC: u16 = 4;
+
+const BACKGROUND: Color = Color {
+    r: 0,
+    g: 0,
+    b: 0,
+    a: 208,
+};
+const TEXT: Color = Color {
+    r: 200,
+    g: 200,
+    b: 200,
+    a: 255,
+};
+const HIGHLIGHT: Color = Color {
+    r: 255,
+    g: 204,
+    b: 0,
+    a: 255,
+};
+const DIM: Color = Color {
+    r: 96,
+    g: 96,
+    b: 96,
+    a: 255,
+};
+
+/// Draws the overlay over the whole window.
+pub fn draw(canvas: &mut WindowCanvas, cpu: &Cpu, symbols: Option<&SymbolMap>) {
+    let (width, height) = canvas.output_size().unwrap();
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(BACKGROUND);
+    canvas.fill_rect(Rect::new(0, 0, width, height)).unwrap();
+    canvas.set_blend_mode(BlendMode::None);
+
+    let columns = ((width as i32 - 2 * MARGIN) / COLUMN_WIDTH) as usize;
+    let lines = ((height as i32 - 2 * MARGIN) / LINE_HEIGHT) as usize;
+    let mut panel = Panel { canvas, columns };
+
+    let registers: Vec<String> = cpu.registers().iter().map(|value| format!("{:02X}", value)).collect();
+    panel.text(0, 0, TEXT, &format!("V0-7  {}", registers[..8].join(" ")));
+    panel.text(0, 1, TEXT, &format!("V8-F  {}", registers[8..].join(" ")));
+    panel.text(
+        0,
+        2,
+        TEXT,
+        &format!(
+            "I {:04X}  PC {:04X}  SP {:X}  DT {:02X}  ST {:02X}",
+            cpu.i(),
+            cpu.pc(),
+            cpu.sp(),
+            cpu.delay_timer(),
+            cpu.sound_timer()
+        ),
+    );
+    panel.text(0, 3, TEXT, &stack(cpu, KEYPAD_COLUMN as usize - 2));
+
+    let keys = cpu.key_mask();
+    for (row, keypad_row) in KEYPAD.iter().enumerate() {
+        for (column, &key) in keypad_row.iter().enumerate() {
+            let color = if keys >> key & 1 == 1 { HIGHLIGHT } else { DIM };
+            panel.text(KEYPAD_COLUMN as usize + column * 2, row, color, &format!("{:X}", key));
+        }
+    }
+    if cpu.waiting_for_key() {
+        panel.text(KEYPAD_COLUMN as usize - 2, 4, HIGHLIGHT, "waiting");
+    }
+
+    // Walking up to PC two bytes at a time keeps the listing in step with
+    // PC even when the instructions before it are not really instructions.
+    let mut address = cpu.pc().saturating_sub(2 * INSTRUCTIONS_BEFORE_PC);
+    let mut line = 6;
+    while line < lines {
+        if let Some(label) = symbols.and_then(|symbols| symbols.label_at(address)) {
+            panel.text(0, line, DIM, &format!("{}:", label));
+            line += 1;
+            if line == lines {
+                break;
+            }
+        }
+
+        let instruction = Instruction::decode_at(cpu.memory(), address);
+        let mut mnemonic = format!("{:?}", instruction);
+        if let Some(target) = disasm::target(&instruction) {
+            if let Some(label) = symbols.and_then(|symbols| symbols.label_at(target)) {
+                mnemonic = mnemonic.replacen(&format!("{:#06X}", target), label, 1);
+            }
+        }
+        let (marker, color) = if address == cpu.pc() { ("=>", HIGHLIGHT) } else { ("  ", TEXT) };
+        panel.text(0, line, color, &format!("{} {:04X}  {}", marker, address, mnemonic));
+        line += 1;
+
+        address = if address < cpu.pc() {
+            address + 2
+        } else {
+            address.wrapping_add(instruction.size())
+        };
+    }
+}
+
+/// The call stack, innermost return address last, cut on the left to fit
+/// in `width` columns.
+fn stack(cpu: &Cpu, width: usize) -> String {
+    let mut text = String::from("STACK");
+    let addresses: Vec<String> = cpu.stack().iter().map(|address| format!(" {:04X}", address)).collect();
+    let room = (width - text.len()) / 5;
+    if addresses.len() > room {
+        text.push_str(" ..");
+        text.push_str(&addresses[addresses.len() + 1 - room..].concat());
+    } else {
+        text.push_str(&addresses.concat());
+    }
+    text
+}
+
+/// Lays text out on a grid of character cells.
+struct Panel<'a> {
+    canvas: &'a mut WindowCanvas,
+    columns: usize,
+}
+
+impl<'a> Panel<'a> {
+    fn text(&mut self, column: usize, line: usize, color: Color, text: &str) {
+        let room = self.columns.saturating_sub(column);
+        let text: String = text.chars().take(room).collect();
+        self.canvas.set_draw_color(color);
+        text::draw(
+            self.canvas,
+            MARGIN + column as i32 * COLUMN_WIDTH,
+            MARGIN + line as i32 * LINE_HEIGHT,
+            SCALE,
+            &text,
+        );
+    }
+}
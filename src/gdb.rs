@@ -0,0 +1,459 @@
+//! A stub for the GDB remote serial protocol: a debugger attaches over TCP,
+//! sets breakpoints, single-steps and reads or writes registers and memory.
+//!
+//! The stub describes its registers in a target description: V0-VF, I, PC,
+//! SP, DT and ST, numbered 0 to 20 in that order and big-endian like
+//! everything else on the CHIP-8.
+//!
+//! A stock `gdb` cannot attach yet. GDB has no CHIP-8 architecture, so the
+//! description names no `<architecture>`, and gdb checks it against the
+//! architecture of its own host and refuses it. Attaching needs CHIP-8
+//! support in the client; `lldb` has not been tried. Until then the
+//! protocol is exercised by `tests/gdb.rs`.
+//!
+//! The server never blocks. A frontend calls `poll` to handle whatever the
+//! debugger sent and `run_frame` in place of `Cpu::run_frame`. The program
+//! stays paused until a debugger attaches and runs freely once it detaches.
+
+use cpu::{Cpu, MEMORY_SIZE};
+use debugger::{Breakpoint, Connection, Engine, Stop};
+
+use std::fmt::Write;
+use std::io;
+use std::net::{Ipv4Addr, TcpListener};
+
+/// The largest packet we accept or send, advertised in `qSupported`.
+const PACKET_SIZE: usize = 0x1000;
+
+const REGISTER_COUNT: usize = 21;
+const I: usize = 16;
+const PC: usize = 17;
+const SP: usize = 18;
+const DT: usize = 19;
+const ST: usize = 20;
+
+/// The signals in stop replies.
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+struct Client {
+    connection: Connection,
+    /// Cleared by `QStartNoAckMode`.
+    acks: bool,
+}
+
+impl Client {
+    fn send(&mut self, packet: &str) -> io::Result<()> {
+        let checksum = packet.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        self.connection.send(format!("${}#{:02x}", packet, checksum).as_bytes())
+    }
+
+    fn ack(&mut self, ok: bool) -> io::Result<()> {
+        if !self.acks {
+            return Ok(());
+        }
+        self.connection.send(if ok { b"+" } else { b"-" })
+    }
+
+    /// Takes the next complete packet or interrupt out of the input.
+    fn next_message(&mut self) -> Option<Message> {
+        loop {
+            match *self.connection.input.first()? {
+                0x03 => {
+                    self.connection.input.remove(0);
+                    return Some(Message::Interrupt);
+                }
+                b'$' => {
+                    let end = self.connection.input.iter().position(|&byte| byte == b'#')?;
+                    if self.connection.input.len() < end + 3 {
+                        return None;
+                    }
+                    let packet: Vec<u8> = self.connection.input.drain(..end + 3).collect();
+                    let data = &packet[1..end];
+                    let checksum = String::from_utf8_lossy(&packet[end + 1..]).into_owned();
+                    let sum = data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+                    return Some(match u8::from_str_radix(&checksum, 16) {
+                        Ok(checksum) if checksum == sum => {
+                            Message::Packet(String::from_utf8_lossy(data).into_owned())
+                        }
+                        _ => Message::Corrupt,
+                    });
+                }
+                // Acks and line noise.
+                _ => {
+                    self.connection.input.remove(0);
+                }
+            }
+        }
+    }
+}
+
+enum Message {
+    Packet(String),
+    Corrupt,
+    Interrupt,
+}
+
+/// What to do after answering a packet.
+enum Reply {
+    Send(String),
+    /// Continuing has no reply until the program stops.
+    Nothing,
+    Detach,
+}
+
+pub struct GdbServer {
+    listener: TcpListener,
+    client: Option<Client>,
+    engine: Engine,
+}
+
+impl GdbServer {
+    /// Listens on `port` of the loopback interface. Port 0 picks a free one,
+    /// see `port`.
+    pub fn bind(port: u16) -> io::Result<GdbServer> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        listener.set_nonblocking(true)?;
+        Ok(GdbServer {
+            listener,
+            client: None,
+            engine: Engine::new(),
+        })
+    }
+
+    pub fn port(&self) -> io::Result<u16> {
+        Ok(self.listener.local_addr()?.port())
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.client.is_some()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.engine.is_paused()
+    }
+
+    /// Accepts a debugger and handles the packets it sent since the last
+    /// call. A debugger that goes away is detached, only errors of the
+    /// listening socket are returned.
+    pub fn poll(&mut self, cpu: &mut Cpu) -> io::Result<()> {
+        if self.client.is_none() {
+            let connection = match Connection::accept(&self.listener)? {
+                Some(connection) => connection,
+                None => return Ok(()),
+            };
+            info!("A debugger attached");
+            self.client = Some(Client { connection, acks: true });
+            self.engine.pause();
+        }
+
+        if let Err(err) = self.receive(cpu) {
+            warn!("Lost the debugger: {}", err);
+            self.detach();
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self, cpu: &mut Cpu) -> io::Result<()> {
+        let mut client = match self.client.take() {
+            Some(client) => client,
+            None => return Ok(()),
+        };
+        if !client.connection.receive()? {
+            self.detach();
+            return Ok(());
+        }
+
+        while let Some(message) = client.next_message() {
+            let packet = match message {
+                Message::Packet(packet) => packet,
+                Message::Corrupt => {
+                    client.ack(false)?;
+                    continue;
+                }
+                Message::Interrupt => {
+                    if !self.engine.is_paused() {
+                        self.engine.pause();
+                        client.send(&stop_reply(SIGINT))?;
+                    }
+                    continue;
+                }
+            };
+            client.ack(true)?;
+
+            trace!("gdb: {}", packet);
+            match self.handle(&packet, cpu) {
+                Reply::Send(reply) => client.send(&reply)?,
+                Reply::Nothing => (),
+                Reply::Detach => {
+                    client.send("OK")?;
+                    self.detach();
+                    return Ok(());
+                }
+            }
+            if packet == "QStartNoAckMode" {
+                client.acks = false;
+            }
+        }
+        self.client = Some(client);
+        Ok(())
+    }
+
+    /// Lets the program run on its own.
+    fn detach(&mut self) {
+        info!("The debugger detached");
+        self.client = None;
+        self.engine.clear_breakpoints();
+        self.engine.resume();
+    }
+
+    /// Runs a frame through the engine and sends the stop reply when the
+    /// program stopped.
+    pub fn run_frame(&mut self, cpu: &mut Cpu) {
+        let reply = match self.engine.run_frame(cpu) {
+            Some(Stop::Exited) => "W00".to_string(),
+            Some(Stop::Error(_)) => stop_reply(SIGILL),
+            Some(Stop::Breakpoint(..)) | Some(Stop::Returned) => stop_reply(SIGTRAP),
+            None => return,
+        };
+        let sent = self.client.as_mut().map(|client| client.send(&reply));
+        if let Some(Err(err)) = sent {
+            warn!("Lost the debugger: {}", err);
+            self.detach();
+        }
+    }
+
+    fn handle(&mut self, packet: &str, cpu: &mut Cpu) -> Reply {
+        let reply = |text: &str| Reply::Send(text.to_string());
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+
+        match command {
+            "?" => Reply::Send(stop_reply(SIGTRAP)),
+            "g" => Reply::Send((0..REGISTER_COUNT).map(|n| register(cpu, n)).collect()),
+            "G" => {
+                let mut args = args;
+                for n in 0..REGISTER_COUNT {
+                    let width = register_size(n) * 2;
+                    match (args.get(..width).and_then(parse_hex), n) {
+                        // There is no way to rearrange the call stack.
+                        (Some(_), SP) => (),
+                        (Some(value), _) => set_register(cpu, n, value),
+                        (None, _) => return reply("E01"),
+                    }
+                    args = &args[width..];
+                }
+                reply("OK")
+            }
+            "p" => match parse_hex(args).map(|n| n as usize) {
+                Some(n) if n < REGISTER_COUNT => Reply::Send(register(cpu, n)),
+                _ => reply("E01"),
+            },
+            "P" => {
+                let mut parts = args.splitn(2, '=');
+                let n = parts.next().and_then(parse_hex).map(|n| n as usize);
+                match (n, parts.next().and_then(parse_hex)) {
+                    (Some(SP), _) => reply("E02"),
+                    (Some(n), Some(value)) if n < REGISTER_COUNT => {
+                        set_register(cpu, n, value);
+                        reply("OK")
+                    }
+                    _ => reply("E01"),
+                }
+            }
+            "m" => match parse_range(args) {
+                Some((address, length)) => {
+                    // Two hex digits a byte, and room for the framing.
+                    let length = length.min((PACKET_SIZE - 4) / 2);
+                    let end = (address + length).min(MEMORY_SIZE);
+                    Reply::Send(cpu.memory()[address..end].iter().map(|byte| format!("{:02x}", byte)).collect())
+                }
+                None => reply("E01"),
+            },
+            "M" => {
+                let mut parts = args.splitn(2, ':');
+                let range = parts.next().and_then(parse_range);
+                let data = parts.next().and_then(parse_bytes);
+                match (range, data) {
+                    (Some((address, length)), Some(ref data))
+                        if data.len() == length && address + length <= MEMORY_SIZE =>
+                    {
+                        cpu.write_memory(address as u16, data);
+                        reply("OK")
+                    }
+                    _ => reply("E01"),
+                }
+            }
+            "Z" | "z" => {
+                let fields: Vec<&str> = args.split(',').collect();
+                match (fields.as_slice(), parse_hex(fields.get(1).unwrap_or(&""))) {
+                    // Software and hardware breakpoints are the same to us.
+                    (["0", _, _], Some(address)) | (["1", _, _], Some(address))
+                        if (address as usize) < MEMORY_SIZE =>
+                    {
+                        let breakpoint = Breakpoint::Address(address as u16);
+                        self.engine.remove_breakpoints_like(breakpoint);
+                        if command == "Z" {
+                            self.engine.add_breakpoint(breakpoint);
+                        }
+                        reply("OK")
+                    }
+                    // Watchpoints are not supported.
+                    _ => reply(""),
+                }
+            }
+            "c" => self.resume(cpu, args),
+            "s" => self.step(cpu, args),
+            "D" | "k" => Reply::Detach,
+            "H" | "T" => reply("OK"),
+            _ => self.query(packet, cpu),
+        }
+    }
+
+    /// The packets with longer names.
+    fn query(&mut self, packet: &str, cpu: &mut Cpu) -> Reply {
+        let reply = |text: &str| Reply::Send(text.to_string());
+
+        if packet.starts_with("qSupported") {
+            return Reply::Send(format!(
+                "PacketSize={:x};qXfer:features:read+;QStartNoAckMode+;swbreak+;hwbreak+;vContSupported+",
+                PACKET_SIZE
+            ));
+        }
+        if let Some(args) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let xml = target_description();
+            return match parse_range(args) {
+                Some((offset, length)) => {
+                    let chunk = xml.get(offset.min(xml.len())..(offset + length).min(xml.len())).unwrap_or("");
+                    let more = offset + length < xml.len();
+                    Reply::Send(format!("{}{}", if more { "m" } else { "l" }, chunk))
+                }
+                None => reply("E01"),
+            };
+        }
+        if let Some(actions) = packet.strip_prefix("vCont;") {
+            // Only one thread, so the first action is the one for it.
+            let action = actions.split(';').next().unwrap_or("");
+            return match action.chars().next() {
+                Some('c') | Some('C') => self.resume(cpu, ""),
+                Some('s') | Some('S') => self.step(cpu, ""),
+                _ => reply("E01"),
+            };
+        }
+
+        match packet {
+            "QStartNoAckMode" => reply("OK"),
+            "vCont?" => reply("vCont;c;C;s;S"),
+            "qAttached" => reply("1"),
+            "qC" => reply("QC1"),
+            "qfThreadInfo" => reply("m1"),
+            "qsThreadInfo" => reply("l"),
+            // For lldb, which does not read the endianness from the target
+            // description.
+            "qHostInfo" | "qProcessInfo" => reply("endian:big;ptrsize:2;"),
+            _ => reply(""),
+        }
+    }
+
+    /// `c [ADDRESS]`
+    fn resume(&mut self, cpu: &mut Cpu, address: &str) -> Reply {
+        if let Some(address) = parse_hex(address) {
+            cpu.set_pc(address as u16);
+        }
+        self.engine.resume();
+        Reply::Nothing
+    }
+
+    /// `s [ADDRESS]`
+    fn step(&mut self, cpu: &mut Cpu, address: &str) -> Reply {
+        if let Some(address) = parse_hex(address) {
+            cpu.set_pc(address as u16);
+        }
+        Reply::Send(match self.engine.step(cpu) {
+            Ok(()) if cpu.exited() => "W00".to_string(),
+            Ok(()) => stop_reply(SIGTRAP),
+            Err(_) => stop_reply(SIGILL),
+        })
+    }
+}
+
+fn stop_reply(signal: u8) -> String {
+    format!("S{:02x}", signal)
+}
+
+fn target_description() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\n\
+         <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+         <target version=\"1.0\">\n\
+         <feature name=\"org.chip8.cpu\">\n",
+    );
+    for x in 0..16 {
+        let _ = writeln!(xml, "<reg name=\"v{:x}\" bitsize=\"8\" type=\"uint8\"/>", x);
+    }
+    xml.push_str(
+        "<reg name=\"i\" bitsize=\"16\" type=\"data_ptr\"/>\n\
+         <reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>\n\
+         <reg name=\"sp\" bitsize=\"8\" type=\"uint8\"/>\n\
+         <reg name=\"dt\" bitsize=\"8\" type=\"uint8\"/>\n\
+         <reg name=\"st\" bitsize=\"8\" type=\"uint8\"/>\n\
+         </feature>\n\
+         </target>\n",
+    );
+    xml
+}
+
+/// The size of register `n` in bytes.
+fn register_size(n: usize) -> usize {
+    match n {
+        I | PC => 2,
+        _ => 1,
+    }
+}
+
+/// Register `n` as big-endian hex.
+fn register(cpu: &Cpu, n: usize) -> String {
+    match n {
+        I => format!("{:04x}", cpu.i()),
+        PC => format!("{:04x}", cpu.pc()),
+        SP => format!("{:02x}", cpu.sp()),
+        DT => format!("{:02x}", cpu.delay_timer()),
+        ST => format!("{:02x}", cpu.sound_timer()),
+        _ => format!("{:02x}", cpu.registers()[n]),
+    }
+}
+
+fn set_register(cpu: &mut Cpu, n: usize, value: u32) {
+    match n {
+        I => cpu.set_i(value as u16),
+        PC => cpu.set_pc(value as u16),
+        SP => (),
+        DT => cpu.set_delay_timer(value as u8),
+        ST => cpu.set_sound_timer(value as u8),
+        _ => cpu.set_register(n, value as u8),
+    }
+}
+
+fn parse_hex(text: &str) -> Option<u32> {
+    u32::from_str_radix(text, 16).ok()
+}
+
+/// `ADDRESS,LENGTH` of something in memory.
+fn parse_range(text: &str) -> Option<(usize, usize)> {
+    let mut parts = text.splitn(2, ',');
+    let address = parse_hex(parts.next()?)? as usize;
+    let length = parse_hex(parts.next()?)? as usize;
+    if address < MEMORY_SIZE {
+        Some((address, length))
+    } else {
+        None
+    }
+}
+
+/// Hex pairs; an odd digit at the end makes it invalid.
+fn parse_bytes(text: &str) -> Option<Vec<u8>> {
+    (0..text.len())
+        .step_by(2)
+        .map(|n| text.get(n..n + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect()
+}
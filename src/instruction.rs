@@ -3,14 +3,24 @@ use std::fmt;
 
 pub use self::Instruction::*;
 
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Instruction {
     Clear,
     Return,
+    ScrollDown(u8),
+    ScrollUp(u8),
+    ScrollRight,
+    ScrollLeft,
+    Exit,
+    LowRes,
+    HighRes,
     Jump(u16),
     Call(u16),
     SkipIfConstantEqual(u8, u8),
     SkipIfConstantNotEqual(u8, u8),
     SkipIfEqual(u8, u8),
+    SaveRange(u8, u8),
+    LoadRange(u8, u8),
     LoadConstant(u8, u8),
     AddConstant(u8, u8),
     Load(u8, u8),
@@ -24,9 +34,13 @@ pub enum Instruction {
     ShiftLeft(u8, u8),
     SkipIfNotEqual(u8, u8),
     SetAddress(u16),
+    SetLongAddress(u16),
     JumpV0Address(u16),
     RandomAnd(u8, u8),
     Draw(u8, u8, u8),
+    SelectPlanes(u8),
+    LoadAudio,
+    SetPitch(u8),
     SkipIfPressed(u8),
     SkipIfNotPressed(u8),
     LoadDelay(u8),
@@ -35,9 +49,12 @@ pub enum Instruction {
     SetSound(u8),
     AddAddress(u8),
     SetFontLocation(u8),
+    SetBigFontLocation(u8),
     SetBCD(u8),
     DumpRegisters(u8),
     LoadRegisters(u8),
+    SaveFlags(u8),
+    LoadFlags(u8),
     Illegal(Opcode),
 }
 
@@ -46,11 +63,20 @@ impl fmt::Debug for Instruction {
         match self {
             Clear => write!(f, "CLS"),
             Return => write!(f, "RET"),
+            ScrollDown(n) => write!(f, "SCD {:#04X}", n),
+            ScrollUp(n) => write!(f, "SCU {:#04X}", n),
+            ScrollRight => write!(f, "SCR"),
+            ScrollLeft => write!(f, "SCL"),
+            Exit => write!(f, "EXIT"),
+            LowRes => write!(f, "LOW"),
+            HighRes => write!(f, "HIGH"),
             Jump(addr) => write!(f, "JP {:#06X}", addr),
             Call(addr) => write!(f, "CALL {:#06X}", addr),
             SkipIfConstantEqual(x, kk) => write!(f, "SE V[{:#04X}], {:#04X}", x, kk),
             SkipIfConstantNotEqual(x, kk) => write!(f, "SNE V[{:#04X}], {:#04X}", x, kk),
             SkipIfEqual(x, kk) => write!(f, "SE V[{:#04X}], V[{:#04X}]", x, kk),
+            SaveRange(x, y) => write!(f, "LD [I], V[{:#04X}...{:#04X}]", x, y),
+            LoadRange(x, y) => write!(f, "LD V[{:#04X}...{:#04X}], [I]", x, y),
             LoadConstant(x, kk) => write!(f, "LD V[{:#04X}], {:#04X}", x, kk),
             AddConstant(x, kk) => write!(f, "ADD V[{:#04X}], {:#04X}", x, kk),
             Load(x, y) => write!(f, "LD V[{:#04X}], V[{:#04X}]", x, y),
@@ -64,9 +90,13 @@ impl fmt::Debug for Instruction {
             ShiftLeft(x, y) => write!(f, "SHL V[{:#04X}], V[{:#04X}]", x, y),
             SkipIfNotEqual(x, y) => write!(f, "SNE V[{:#04X}], V[{:#04X}]", x, y),
             SetAddress(addr) => write!(f, "LD I, {:#06X}", addr),
+            SetLongAddress(addr) => write!(f, "LD I, LONG {:#06X}", addr),
             JumpV0Address(addr) => write!(f, "JP V0, {:#06X}", addr),
             RandomAnd(x, kk) => write!(f, "RND V[{:#04X}], {:#04X}", x, kk),
             Draw(x, y, n) => write!(f, "DRW V[{:#04X}], V[{:#04X}], {:#04X}", x, y, n),
+            SelectPlanes(n) => write!(f, "PLANE {:#04X}", n),
+            LoadAudio => write!(f, "AUDIO"),
+            SetPitch(x) => write!(f, "PITCH V[{:#04X}]", x),
             SkipIfPressed(x) => write!(f, "SKP v[{:#04X}]", x),
             SkipIfNotPressed(x) => write!(f, "SKNP v[{:#04X}]", x),
             LoadDelay(x) => write!(f, "LD V[{:#04X}], DT", x),
@@ -75,24 +105,186 @@ impl fmt::Debug for Instruction {
             SetSound(x) => write!(f, "LD ST, V[{:#04X}]", x),
             AddAddress(x) => write!(f, "ADD I, V[{:#04X}]", x),
             SetFontLocation(x) => write!(f, "LD F, V[{:#04X}]", x),
+            SetBigFontLocation(x) => write!(f, "LD HF, V[{:#04X}]", x),
             SetBCD(x) => write!(f, "LD B, V[{:#04X}]", x),
             DumpRegisters(x) => write!(f, "LD [I], V[0...{:#04X}]", x),
             LoadRegisters(x) => write!(f, "LD V[0...{:#04X}], [I]", x),
+            SaveFlags(x) => write!(f, "LD R, V[0...{:#04X}]", x),
+            LoadFlags(x) => write!(f, "LD V[0...{:#04X}], R", x),
             Illegal(opcode) => write!(f, "{:?}", opcode),
         }
     }
 }
 
 impl Instruction {
+    /// Decodes the instruction at `address`. Unlike `decode` this also
+    /// handles the XO-CHIP `F000 NNNN` long load, whose operand is the
+    /// following word.
+    pub fn decode_at(memory: &[u8], address: u16) -> Instruction {
+        let byte = |offset: u16| memory[address.wrapping_add(offset) as usize % memory.len()];
+        match (byte(0), byte(1)) {
+            (0xF0, 0x00) => SetLongAddress((byte(2) as u16) << 8 | byte(3) as u16),
+            (high, low) => Instruction::decode(Opcode(high, low)),
+        }
+    }
+
+    /// The name of the instruction's variant, without its operands.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Clear => "Clear",
+            Return => "Return",
+            ScrollDown(..) => "ScrollDown",
+            ScrollUp(..) => "ScrollUp",
+            ScrollRight => "ScrollRight",
+            ScrollLeft => "ScrollLeft",
+            Exit => "Exit",
+            LowRes => "LowRes",
+            HighRes => "HighRes",
+            Jump(..) => "Jump",
+            Call(..) => "Call",
+            SkipIfConstantEqual(..) => "SkipIfConstantEqual",
+            SkipIfConstantNotEqual(..) => "SkipIfConstantNotEqual",
+            SkipIfEqual(..) => "SkipIfEqual",
+            SaveRange(..) => "SaveRange",
+            LoadRange(..) => "LoadRange",
+            LoadConstant(..) => "LoadConstant",
+            AddConstant(..) => "AddConstant",
+            Load(..) => "Load",
+            Or(..) => "Or",
+            And(..) => "And",
+            Add(..) => "Add",
+            Xor(..) => "Xor",
+            Sub(..) => "Sub",
+            ShiftRight(..) => "ShiftRight",
+            SubReverse(..) => "SubReverse",
+            ShiftLeft(..) => "ShiftLeft",
+            SkipIfNotEqual(..) => "SkipIfNotEqual",
+            SetAddress(..) => "SetAddress",
+            SetLongAddress(..) => "SetLongAddress",
+            JumpV0Address(..) => "JumpV0Address",
+            RandomAnd(..) => "RandomAnd",
+            Draw(..) => "Draw",
+            SelectPlanes(..) => "SelectPlanes",
+            LoadAudio => "LoadAudio",
+            SetPitch(..) => "SetPitch",
+            SkipIfPressed(..) => "SkipIfPressed",
+            SkipIfNotPressed(..) => "SkipIfNotPressed",
+            LoadDelay(..) => "LoadDelay",
+            WaitForKey(..) => "WaitForKey",
+            SetDelay(..) => "SetDelay",
+            SetSound(..) => "SetSound",
+            AddAddress(..) => "AddAddress",
+            SetFontLocation(..) => "SetFontLocation",
+            SetBigFontLocation(..) => "SetBigFontLocation",
+            SetBCD(..) => "SetBCD",
+            DumpRegisters(..) => "DumpRegisters",
+            LoadRegisters(..) => "LoadRegisters",
+            SaveFlags(..) => "SaveFlags",
+            LoadFlags(..) => "LoadFlags",
+            Illegal(..) => "Illegal",
+        }
+    }
+
+    /// The number of bytes the instruction occupies in memory.
+    pub fn size(&self) -> u16 {
+        match self {
+            SetLongAddress(_) => 4,
+            _ => 2,
+        }
+    }
+
+    /// The bytes of the instruction as they appear in memory, the inverse of
+    /// `decode_at`. Addresses and operands are truncated to their fields.
+    pub fn encode(&self) -> Vec<u8> {
+        let op = |opcode: u16| vec![(opcode >> 8) as u8, opcode as u8];
+        let nnn = |high: u16, address: u16| op(high << 12 | address & 0x0FFF);
+        let xkk = |high: u16, x: u8, kk: u8| op(high << 12 | (x as u16 & 0xF) << 8 | kk as u16);
+        let xyn = |high: u16, x: u8, y: u8, n: u8| {
+            op(high << 12 | (x as u16 & 0xF) << 8 | (y as u16 & 0xF) << 4 | n as u16 & 0xF)
+        };
+        let fx = |x: u8, low: u8| xkk(0xF, x, low);
+
+        match *self {
+            Clear => op(0x00E0),
+            Return => op(0x00EE),
+            ScrollDown(n) => op(0x00C0 | n as u16 & 0xF),
+            ScrollUp(n) => op(0x00D0 | n as u16 & 0xF),
+            ScrollRight => op(0x00FB),
+            ScrollLeft => op(0x00FC),
+            Exit => op(0x00FD),
+            LowRes => op(0x00FE),
+            HighRes => op(0x00FF),
+            Jump(address) => nnn(0x1, address),
+            Call(address) => nnn(0x2, address),
+            SkipIfConstantEqual(x, kk) => xkk(0x3, x, kk),
+            SkipIfConstantNotEqual(x, kk) => xkk(0x4, x, kk),
+            SkipIfEqual(x, y) => xyn(0x5, x, y, 0x0),
+            SaveRange(x, y) => xyn(0x5, x, y, 0x2),
+            LoadRange(x, y) => xyn(0x5, x, y, 0x3),
+            LoadConstant(x, kk) => xkk(0x6, x, kk),
+            AddConstant(x, kk) => xkk(0x7, x, kk),
+            Load(x, y) => xyn(0x8, x, y, 0x0),
+            Or(x, y) => xyn(0x8, x, y, 0x1),
+            And(x, y) => xyn(0x8, x, y, 0x2),
+            Xor(x, y) => xyn(0x8, x, y, 0x3),
+            Add(x, y) => xyn(0x8, x, y, 0x4),
+            Sub(x, y) => xyn(0x8, x, y, 0x5),
+            ShiftRight(x, y) => xyn(0x8, x, y, 0x6),
+            SubReverse(x, y) => xyn(0x8, x, y, 0x7),
+            ShiftLeft(x, y) => xyn(0x8, x, y, 0xE),
+            SkipIfNotEqual(x, y) => xyn(0x9, x, y, 0x0),
+            SetAddress(address) => nnn(0xA, address),
+            SetLongAddress(address) => vec![0xF0, 0x00, (address >> 8) as u8, address as u8],
+            JumpV0Address(address) => nnn(0xB, address),
+            RandomAnd(x, kk) => xkk(0xC, x, kk),
+            Draw(x, y, n) => xyn(0xD, x, y, n),
+            SkipIfPressed(x) => xkk(0xE, x, 0x9E),
+            SkipIfNotPressed(x) => xkk(0xE, x, 0xA1),
+            SelectPlanes(n) => fx(n, 0x01),
+            LoadAudio => op(0xF002),
+            LoadDelay(x) => fx(x, 0x07),
+            WaitForKey(x) => fx(x, 0x0A),
+            SetDelay(x) => fx(x, 0x15),
+            SetSound(x) => fx(x, 0x18),
+            AddAddress(x) => fx(x, 0x1E),
+            SetFontLocation(x) => fx(x, 0x29),
+            SetBigFontLocation(x) => fx(x, 0x30),
+            SetBCD(x) => fx(x, 0x33),
+            SetPitch(x) => fx(x, 0x3A),
+            DumpRegisters(x) => fx(x, 0x55),
+            LoadRegisters(x) => fx(x, 0x65),
+            SaveFlags(x) => fx(x, 0x75),
+            LoadFlags(x) => fx(x, 0x85),
+            Illegal(Opcode(high, low)) => vec![high, low],
+        }
+    }
+
+    /// Decodes a single two byte opcode. `F000` is `Illegal` on its own
+    /// because its operand is in the next word, see `decode_at`.
     pub fn decode(Opcode(high, low): Opcode) -> Instruction {
         match (high & 0xF0, low) {
+            // Any other 0NNN calls machine code on the VIP, which isn't
+            // emulated.
+            (0x00, _) if high != 0x00 => Illegal(Opcode(high, low)),
             (0x00, 0xE0) => Clear,
             (0x00, 0xEE) => Return,
+            (0x00, 0xFB) => ScrollRight,
+            (0x00, 0xFC) => ScrollLeft,
+            (0x00, 0xFD) => Exit,
+            (0x00, 0xFE) => LowRes,
+            (0x00, 0xFF) => HighRes,
+            (0x00, _) if low & 0xF0 == 0xC0 => ScrollDown(low & 0x0F),
+            (0x00, _) if low & 0xF0 == 0xD0 => ScrollUp(low & 0x0F),
             (0x10, _) => Jump(((high & 0x0F) as u16) << 8 | low as u16),
             (0x20, _) => Call(((high & 0x0F) as u16) << 8 | low as u16),
             (0x30, _) => SkipIfConstantEqual(high & 0x0F, low),
             (0x40, _) => SkipIfConstantNotEqual(high & 0x0F, low),
-            (0x50, _) => SkipIfEqual(high & 0x0F, low >> 4),
+            (0x50, _) => match low & 0x0F {
+                0x00 => SkipIfEqual(high & 0x0F, low >> 4),
+                0x02 => SaveRange(high & 0x0F, low >> 4),
+                0x03 => LoadRange(high & 0x0F, low >> 4),
+                _ => Illegal(Opcode(high, low)),
+            },
             (0x60, _) => LoadConstant(high & 0x0F, low),
             (0x70, _) => AddConstant(high & 0x0F, low),
             (0x80, _) => match low & 0x0F {
@@ -117,15 +309,21 @@ impl Instruction {
             (0xD0, _) => Draw(high & 0x0F, low >> 4, low & 0x0F),
             (0xE0, 0x9E) => SkipIfPressed(high & 0xF),
             (0xE0, 0xA1) => SkipIfNotPressed(high & 0xF),
+            (0xF0, 0x01) => SelectPlanes(high & 0xF),
+            (0xF0, 0x02) if high == 0xF0 => LoadAudio,
             (0xF0, 0x07) => LoadDelay(high & 0xF),
             (0xF0, 0x0A) => WaitForKey(high & 0xF),
             (0xF0, 0x15) => SetDelay(high & 0xF),
             (0xF0, 0x18) => SetSound(high & 0xF),
             (0xF0, 0x1E) => AddAddress(high & 0xF),
             (0xF0, 0x29) => SetFontLocation(high & 0xF),
+            (0xF0, 0x30) => SetBigFontLocation(high & 0xF),
             (0xF0, 0x33) => SetBCD(high & 0xF),
+            (0xF0, 0x3A) => SetPitch(high & 0xF),
             (0xF0, 0x55) => DumpRegisters(high & 0xF),
             (0xF0, 0x65) => LoadRegisters(high & 0xF),
+            (0xF0, 0x75) => SaveFlags(high & 0xF),
+            (0xF0, 0x85) => LoadFlags(high & 0xF),
             _ => Illegal(Opcode(high, low)),
         }
     }
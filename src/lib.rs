@@ -0,0 +1,38 @@
+//! The CHIP-8 interpreter core.
+//!
+//! Everything in here is independent of SDL, so the interpreter can be driven
+//! from tools, tests and bots without opening a window. The `chip8` binary is
+//! a thin SDL frontend on top of this crate.
+
+#[macro_use]
+extern crate log;
+extern crate rand;
+
+pub mod asm;
+pub mod config;
+pub mod coverage;
+pub mod cpu;
+pub mod dap;
+pub mod debugger;
+pub mod disasm;
+pub mod font;
+pub mod gdb;
+pub mod instruction;
+pub mod json;
+pub mod lint;
+pub mod movie;
+pub mod octo;
+pub mod profile;
+pub mod quirks;
+pub mod rewind;
+pub mod rng;
+pub mod rom;
+pub mod screen;
+pub mod state;
+pub mod symbols;
+pub mod trace;
+
+pub use cpu::{Cpu, Error, Opcode};
+pub use font::FONT;
+pub use instruction::Instruction;
+pub use quirks::Quirks;
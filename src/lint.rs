@@ -0,0 +1,394 @@
+//! A static checker for common CHIP-8 bugs.
+//!
+//! The program is disassembled from its entry point and every subroutine is
+//! walked on its own, with calls stepped over. Along the way I is tracked
+//! while it holds a constant, which is enough to catch sprites read past the
+//! end of memory and stores that overwrite code. Anything that depends on a
+//! register at run time, such as `JP V0, NNN` targets, is not followed.
+
+use disasm::{Disassembly, Flow, ORIGIN};
+use instruction::Instruction::{self, *};
+use symbols::SymbolMap;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// How many return addresses the stack holds.
+const STACK_SIZE: usize = 16;
+
+/// The last address of CHIP-8 and SUPER-CHIP memory.
+const MEMORY_END: u16 = 0x0FFF;
+
+/// How far after `FX55`/`FX65` to look for a use of I.
+const MAX_I_DISTANCE: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    /// Something that is only a problem on some interpreters.
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Diagnostic {
+    pub address: u16,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// The diagnostic the way compilers print them: where it is, what it
+    /// is, and the instruction it is about. The place is a source line when
+    /// `symbols` has one for the address and the address in `path`
+    /// otherwise.
+    pub fn format(&self, path: &str, symbols: Option<&SymbolMap>, rom: &[u8]) -> String {
+        let place = match symbols.and_then(|symbols| symbols.location(self.address)) {
+            Some(location) => location.to_string(),
+            None => format!("{}:{:#06X}", path, self.address),
+        };
+        let offset = self.address.wrapping_sub(ORIGIN) as usize;
+        let instruction = Instruction::decode_at(rom, offset as u16);
+        let bytes: String = (0..instruction.size() as usize)
+            .map(|n| format!("{:02X}", rom[(offset + n) % rom.len()]))
+            .collect();
+        format!(
+            "{}: {}: {}\n    {:#06X}  {:<8}  {:?}",
+            place, self.severity, self.message, self.address, bytes, instruction
+        )
+    }
+}
+
+/// What is known about I at an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Index {
+    Known(u16),
+    Unknown,
+}
+
+impl Index {
+    fn merge(self, other: Index) -> Index {
+        if self == other {
+            self
+        } else {
+            Index::Unknown
+        }
+    }
+}
+
+struct Linter<'a> {
+    disassembly: &'a Disassembly,
+    diagnostics: BTreeSet<Diagnostic>,
+}
+
+/// Checks `rom`, loaded at `0x200`, and returns what it found ordered by
+/// address.
+pub fn lint(rom: &[u8]) -> Vec<Diagnostic> {
+    let disassembly = Disassembly::new(rom, ORIGIN);
+    let mut linter = Linter {
+        disassembly: &disassembly,
+        diagnostics: BTreeSet::new(),
+    };
+    linter.run();
+    linter.diagnostics.into_iter().collect()
+}
+
+impl<'a> Linter<'a> {
+    fn warn(&mut self, address: u16, message: String) {
+        self.report(address, Severity::Warning, message);
+    }
+
+    fn note(&mut self, address: u16, message: String) {
+        self.report(address, Severity::Note, message);
+    }
+
+    fn report(&mut self, address: u16, severity: Severity, message: String) {
+        self.diagnostics.insert(Diagnostic {
+            address,
+            severity,
+            message,
+        });
+    }
+
+    fn instruction(&self, address: u16) -> Option<Instruction> {
+        self.disassembly.code.get(&address).cloned()
+    }
+
+    /// Where execution goes after the instruction at `address` without
+    /// leaving the routine it is in. Calls come back to the next instruction.
+    fn successors(&self, address: u16) -> Vec<u16> {
+        let instruction = match self.instruction(address) {
+            Some(instruction) => instruction,
+            None => return Vec::new(),
+        };
+        let next = address.wrapping_add(instruction.size());
+        let successors = match Flow::of(&instruction) {
+            Flow::Next | Flow::Call(_) => vec![next],
+            Flow::Skip => {
+                let skipped = self.instruction(next).map_or(2, |instruction| instruction.size());
+                vec![next, next.wrapping_add(skipped)]
+            }
+            Flow::Jump(target) => vec![target],
+            Flow::Return | Flow::Indirect | Flow::Stop => Vec::new(),
+        };
+        successors
+            .into_iter()
+            .filter(|address| self.disassembly.code.contains_key(address))
+            .collect()
+    }
+
+    /// The instructions of the routine at `entry`, with what is known about
+    /// I at each of them.
+    fn walk(&self, entry: u16) -> BTreeMap<u16, Index> {
+        let mut states = BTreeMap::new();
+        let mut pending = vec![(entry, Index::Unknown)];
+        while let Some((address, index)) = pending.pop() {
+            let index = match states.get(&address) {
+                Some(&old) if old == index.merge(old) => continue,
+                Some(&old) => index.merge(old),
+                None => index,
+            };
+            states.insert(address, index);
+
+            let instruction = match self.instruction(address) {
+                Some(instruction) => instruction,
+                None => continue,
+            };
+            let after = match instruction {
+                SetAddress(address) => Index::Known(address),
+                SetLongAddress(_) | AddAddress(_) | SetFontLocation(_) | SetBigFontLocation(_) => Index::Unknown,
+                // Whether these move I is a quirk, and a subroutine can do
+                // anything to it.
+                DumpRegisters(_) | LoadRegisters(_) | Call(_) => Index::Unknown,
+                _ => index,
+            };
+            for successor in self.successors(address) {
+                pending.push((successor, after));
+            }
+        }
+        states
+    }
+
+    fn run(&mut self) {
+        let disassembly = self.disassembly;
+        for &address in &disassembly.illegal {
+            let message = match self.instruction(address) {
+                Some(Illegal(opcode)) => format!("illegal opcode {:?} can be reached", opcode),
+                _ => "illegal opcode can be reached".to_string(),
+            };
+            self.warn(address, message);
+        }
+
+        let mut routines = BTreeMap::new();
+        let mut pending = vec![ORIGIN];
+        while let Some(entry) = pending.pop() {
+            if routines.contains_key(&entry) || !self.disassembly.code.contains_key(&entry) {
+                continue;
+            }
+            let body = self.walk(entry);
+            for &address in body.keys() {
+                if let Some(Call(target)) = self.instruction(address) {
+                    pending.push(target);
+                }
+            }
+            routines.insert(entry, body);
+        }
+
+        for body in routines.values() {
+            for (&address, &index) in body {
+                self.check_instruction(address, index);
+            }
+        }
+
+        if let Some(main) = routines.get(&ORIGIN) {
+            for &address in main.keys() {
+                if let Some(Return) = self.instruction(address) {
+                    self.warn(address, "RET outside a subroutine, there is no call to return from".to_string());
+                }
+            }
+        }
+
+        self.check_stack(&routines);
+    }
+
+    fn check_instruction(&mut self, address: u16, index: Index) {
+        let instruction = match self.instruction(address) {
+            Some(instruction) => instruction,
+            None => return,
+        };
+
+        if let (Draw(_, _, height), Index::Known(i)) = (instruction, index) {
+            let size = if height == 0 { 32 } else { height as u16 };
+            let last = i as u32 + size as u32 - 1;
+            if last > MEMORY_END as u32 {
+                self.warn(
+                    address,
+                    format!(
+                        "the sprite is read from {:#06X} to {:#06X}, past the end of memory at {:#06X}",
+                        i, last, MEMORY_END
+                    ),
+                );
+            }
+        }
+
+        let written = match instruction {
+            DumpRegisters(x) => Some(x as u16 + 1),
+            SetBCD(_) => Some(3),
+            SaveRange(x, y) => Some((x as i16 - y as i16).unsigned_abs() + 1),
+            _ => None,
+        };
+        if let (Some(size), Index::Known(i)) = (written, index) {
+            if let Some(code) = (i..i.saturating_add(size)).find(|&byte| self.disassembly.is_code(byte)) {
+                self.warn(
+                    address,
+                    format!(
+                        "this writes {:#06X} to {:#06X}, over the instruction at {:#06X}",
+                        i,
+                        i.saturating_add(size - 1),
+                        code
+                    ),
+                );
+            }
+        }
+
+        match instruction {
+            ShiftRight(x, y) | ShiftLeft(x, y) if x != y => self.note(
+                address,
+                format!(
+                    "this shifts V{:X} into V{:X} with the shift quirk and V{:X} in place without it",
+                    y, x, x
+                ),
+            ),
+            DumpRegisters(_) | LoadRegisters(_) => {
+                if let Some(user) = self.next_use_of_i(address) {
+                    self.note(
+                        address,
+                        format!(
+                            "I is used at {:#06X}, where it has moved past the registers only with the load-store quirk",
+                            user
+                        ),
+                    );
+                }
+            }
+            JumpV0Address(target) if target & 0x0F00 != 0 => self.note(
+                address,
+                format!(
+                    "this jumps to {:#06X} + V0, or to {:#06X} + V{:X} with the jump quirk",
+                    target,
+                    target,
+                    target >> 8 & 0xF
+                ),
+            ),
+            _ => {}
+        }
+    }
+
+    /// The first instruction after the `FX55`/`FX65` at `address` that uses
+    /// I before something sets it again, if there is one close by.
+    fn next_use_of_i(&self, address: u16) -> Option<u16> {
+        let mut seen = BTreeSet::new();
+        let mut pending: Vec<(u16, usize)> = self.successors(address).into_iter().map(|next| (next, 1)).collect();
+        let mut first: Option<u16> = None;
+        while let Some((address, distance)) = pending.pop() {
+            if distance > MAX_I_DISTANCE || !seen.insert(address) {
+                continue;
+            }
+            let instruction = match self.instruction(address) {
+                Some(instruction) => instruction,
+                None => continue,
+            };
+            match instruction {
+                Draw(..) | DumpRegisters(_) | LoadRegisters(_) | SetBCD(_) | AddAddress(_) | SaveRange(..)
+                | LoadRange(..) | LoadAudio => {
+                    first = Some(first.map_or(address, |first| first.min(address)));
+                    continue;
+                }
+                SetAddress(_) | SetLongAddress(_) | SetFontLocation(_) | SetBigFontLocation(_) | Call(_) => continue,
+                _ => {}
+            }
+            pending.extend(self.successors(address).into_iter().map(|next| (next, distance + 1)));
+        }
+        first
+    }
+
+    /// Warns about calls that can nest deeper than the stack and about
+    /// recursion, which the stack cannot hold unless something bounds it.
+    fn check_stack(&mut self, routines: &BTreeMap<u16, BTreeMap<u16, Index>>) {
+        let calls: BTreeMap<u16, Vec<(u16, u16)>> = routines
+            .iter()
+            .map(|(&entry, body)| {
+                let calls = body
+                    .keys()
+                    .filter_map(|&address| match self.instruction(address) {
+                        Some(Call(target)) => Some((address, target)),
+                        _ => None,
+                    })
+                    .collect();
+                (entry, calls)
+            })
+            .collect();
+
+        // The deepest each routine can be entered at, found by walking every
+        // chain of calls from the entry point. Calls back into a routine that
+        // is already on the chain are recursion and end the chain.
+        let mut depths: BTreeMap<u16, usize> = BTreeMap::new();
+        let mut overflows = BTreeSet::new();
+        let mut recursions = BTreeSet::new();
+        let mut chain = vec![ORIGIN];
+        self.descend(&calls, &mut chain, &mut depths, &mut overflows, &mut recursions);
+
+        for (address, target) in recursions {
+            self.warn(
+                address,
+                format!(
+                    "this call is recursive, {:#06X} can call itself until the {} entry stack overflows",
+                    target, STACK_SIZE
+                ),
+            );
+        }
+        for (address, depth) in overflows {
+            self.warn(
+                address,
+                format!("calls can nest {} deep here, the stack holds {}", depth, STACK_SIZE),
+            );
+        }
+    }
+
+    fn descend(
+        &self,
+        calls: &BTreeMap<u16, Vec<(u16, u16)>>,
+        chain: &mut Vec<u16>,
+        depths: &mut BTreeMap<u16, usize>,
+        overflows: &mut BTreeSet<(u16, usize)>,
+        recursions: &mut BTreeSet<(u16, u16)>,
+    ) {
+        let routine = *chain.last().unwrap();
+        let depth = chain.len() - 1;
+        match depths.get(&routine) {
+            Some(&deepest) if deepest >= depth => return,
+            _ => depths.insert(routine, depth),
+        };
+
+        for &(address, target) in calls.get(&routine).map_or(&[][..], |calls| &calls[..]) {
+            if chain.contains(&target) {
+                recursions.insert((address, target));
+            } else if depth == STACK_SIZE {
+                // Routines are not followed any deeper, so only the call
+                // that first goes too deep is reported.
+                overflows.insert((address, depth + 1));
+            } else {
+                chain.push(target);
+                self.descend(calls, chain, depths, overflows, recursions);
+                chain.pop();
+            }
+        }
+    }
+}
@@ -1,16 +1,76 @@
+extern crate chip8;
 #[macro_use]
 extern crate log;
 extern crate env_logger;
-extern crate rand;
 extern crate sdl2;
 
+use chip8::config::{self, Config};
+
 use std::env;
+use std::process;
+
+mod frontend;
 
-mod chip8;
-mod cpu;
-mod instruction;
+use frontend::Chip8;
 
-use chip8::Chip8;
+struct Options {
+    game_path: Option<String>,
+    config: Config,
+    record: Option<String>,
+    play: Option<String>,
+    debug: bool,
+    gdb_port: Option<u16>,
+    dap_port: Option<u16>,
+    trace: Option<String>,
+    profile: Option<String>,
+    coverage: Option<String>,
+}
+
+fn parse_port(option: &str, port: Option<String>) -> Result<u16, String> {
+    let port = port.ok_or(format!("{} needs a port", option))?;
+    port.parse().map_err(|_| format!("invalid port {:?}", port))
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        game_path: None,
+        config: Config::default(),
+        record: None,
+        play: None,
+        debug: false,
+        gdb_port: None,
+        dap_port: None,
+        trace: None,
+        profile: None,
+        coverage: None,
+    };
+
+    let mut args = env::args().skip(1).peekable();
+    // `chip8 run game.8o` reads better in an edit-run loop, it is the same
+    // as `chip8 game.8o`.
+    if args.peek().map(String::as_str) == Some("run") {
+        args.next();
+    }
+    while let Some(arg) = args.next() {
+        if options.config.parse_option(&arg, &mut args)? {
+            continue;
+        }
+        match arg.as_str() {
+            "--record" => options.record = Some(args.next().ok_or("--record needs a movie file")?),
+            "--play" => options.play = Some(args.next().ok_or("--play needs a movie file")?),
+            "--debug" => options.debug = true,
+            "--gdb" => options.gdb_port = Some(parse_port(&arg, args.next())?),
+            "--dap" => options.dap_port = Some(parse_port(&arg, args.next())?),
+            "--trace" => options.trace = Some(args.next().ok_or("--trace needs a file")?),
+            "--profile" => options.profile = Some(args.next().ok_or("--profile needs a file")?),
+            "--coverage" => options.coverage = Some(args.next().ok_or("--coverage needs a file")?),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => options.game_path = Some(arg),
+        }
+    }
+
+    Ok(options)
+}
 
 fn main() {
     let mut builder = env_logger::Builder::new();
@@ -19,10 +79,80 @@ fn main() {
     }
     builder.init();
 
+    let options = parse_args().unwrap_or_else(|err| {
+        eprintln!("chip8: {}", err);
+        eprintln!(
+            "usage: chip8 [run] {} [--record MOVIE | --play MOVIE | --debug | --gdb PORT | --dap PORT] [--trace FILE] [--profile REPORT] [--coverage COVERAGE] [ROM]",
+            config::USAGE
+        );
+        eprintln!("  FILE is written as JSON lines for .jsonl, in the binary trace format otherwise");
+        eprintln!("  REPORT is written when F10 is pressed and on exit");
+        eprintln!("  COVERAGE is an lcov tracefile for .info and .lcov, an annotated disassembly otherwise");
+        eprintln!("  ROM can also be an Octo (.8o) or assembly (.asm) source");
+        eprintln!("{}", config::option_values());
+        process::exit(2);
+    });
+
     let mut chip8 = Chip8::new();
+    chip8.configure(&options.config);
+
+    if let Some(game_path) = options.game_path {
+        if let Err(err) = chip8.load(&game_path) {
+            error!("Could not load {:?}: {}", game_path, err);
+            process::exit(1);
+        }
+    }
+
+    let debuggers = [options.debug, options.gdb_port.is_some(), options.dap_port.is_some()];
+    let movie = options.record.is_some() || options.play.is_some();
+    if debuggers.iter().filter(|&&debugger| debugger).count() + movie as usize > 1 {
+        error!("A debugger cannot be used with a movie or another debugger");
+        process::exit(2);
+    }
+
+    let movie = match (options.record, options.play) {
+        (Some(path), None) => chip8.record_movie(&path).map_err(|err| (path, err)),
+        (None, Some(path)) => chip8.play_movie(&path).map_err(|err| (path, err)),
+        (None, None) => Ok(()),
+        (Some(_), Some(_)) => {
+            error!("A movie cannot be recorded and played at the same time");
+            process::exit(2);
+        }
+    };
+    if let Err((path, err)) = movie {
+        error!("Could not use the movie {:?}: {}", path, err);
+        process::exit(1);
+    }
+
+    if let Some(path) = options.trace {
+        if let Err(err) = chip8.trace(&path) {
+            error!("Could not trace to {:?}: {}", path, err);
+            process::exit(1);
+        }
+    }
 
-    if let Some(game_path) = env::args().nth(1) {
-        chip8.load(game_path);
+    if let Some(path) = options.profile {
+        chip8.profile(path);
+    }
+
+    if let Some(path) = options.coverage {
+        chip8.track_coverage(path);
+    }
+
+    if options.debug {
+        chip8.debug();
+    }
+    if let Some(port) = options.gdb_port {
+        if let Err(err) = chip8.serve_gdb(port) {
+            error!("Could not listen on port {}: {}", port, err);
+            process::exit(1);
+        }
+    }
+    if let Some(port) = options.dap_port {
+        if let Err(err) = chip8.serve_dap(port) {
+            error!("Could not listen on port {}: {}", port, err);
+            process::exit(1);
+        }
     }
 
     chip8.run();
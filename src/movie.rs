@@ -0,0 +1,182 @@
+//! Input movies: the keys held in every frame of a run, together with
+//! everything else needed to replay it exactly.
+//!
+//! Movies are plain text so they can be attached to bug reports and diffed:
+//!
+//! ```text
+//! chip8-movie 1
+//! rng xorshift 1234
+//! ipf 8
+//! quirk shift on
+//! ...
+//! frames
+//! 0000
+//! 0020
+//! ```
+//!
+//! Every line after `frames` is the `Cpu::key_mask` for one frame in hex.
+//! Replaying starts from a freshly loaded ROM.
+
+use cpu::Cpu;
+use quirks::{Quirks, QUIRK_NAMES};
+use rng::Generator;
+
+use std::error;
+use std::fmt;
+
+const HEADER: &str = "chip8-movie 1";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub generator: Generator,
+    pub seed: u64,
+    pub instructions_per_frame: u32,
+    pub quirks: Quirks,
+    pub frames: Vec<u16>,
+}
+
+#[derive(Debug)]
+pub struct MovieError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for MovieError {}
+
+impl Movie {
+    /// Starts an empty movie with the settings of `cpu`. Returns `None` if
+    /// the CPU uses a custom random source, which cannot be replayed.
+    pub fn new(cpu: &Cpu) -> Option<Movie> {
+        let (generator, seed) = cpu.rng_seed()?;
+        Some(Movie {
+            generator,
+            seed,
+            instructions_per_frame: cpu.instructions_per_frame(),
+            quirks: cpu.quirks(),
+            frames: Vec::new(),
+        })
+    }
+
+    /// Records the keys held in the frame that is about to run.
+    pub fn record(&mut self, cpu: &Cpu) {
+        self.frames.push(cpu.key_mask());
+    }
+
+    /// Gives `cpu` the settings the movie was recorded with. Call this
+    /// right after loading the ROM.
+    pub fn configure(&self, cpu: &mut Cpu) {
+        cpu.seed_rng(self.generator, self.seed);
+        cpu.set_instructions_per_frame(self.instructions_per_frame);
+        cpu.set_quirks(self.quirks);
+    }
+
+    /// Sets the keys for `frame` before it runs. Returns `false` once the
+    /// movie has ended.
+    pub fn play(&self, frame: usize, cpu: &mut Cpu) -> bool {
+        match self.frames.get(frame) {
+            Some(&mask) => {
+                cpu.set_key_mask(mask);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Movie, MovieError> {
+        let error = |line: usize, message: String| MovieError {
+            line: line + 1,
+            message,
+        };
+        let mut lines = text.lines().enumerate();
+
+        match lines.next() {
+            Some((_, HEADER)) => (),
+            _ => return Err(error(0, format!("expected {:?}", HEADER))),
+        }
+
+        let mut rng = None;
+        let mut instructions_per_frame = None;
+        let mut quirks = Quirks::default();
+        let mut frames = None;
+        for (n, line) in lines.by_ref() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => (),
+                ["rng", generator, seed] => {
+                    let generator: Generator = generator
+                        .parse()
+                        .map_err(|err| error(n, format!("{}", err)))?;
+                    let seed = seed
+                        .parse()
+                        .map_err(|_| error(n, format!("invalid seed {:?}", seed)))?;
+                    rng = Some((generator, seed));
+                }
+                ["ipf", value] => {
+                    instructions_per_frame = Some(value.parse().map_err(|_| {
+                        error(n, format!("invalid instructions per frame {:?}", value))
+                    })?);
+                }
+                ["quirk", name, state] => {
+                    let enabled = match *state {
+                        "on" => true,
+                        "off" => false,
+                        _ => return Err(error(n, format!("expected on or off, got {:?}", state))),
+                    };
+                    if !quirks.set(name, enabled) {
+                        return Err(error(n, format!("unknown quirk {:?}", name)));
+                    }
+                }
+                ["frames"] => {
+                    frames = Some(Vec::new());
+                    break;
+                }
+                _ => return Err(error(n, format!("unexpected {:?}", line))),
+            }
+        }
+
+        let mut frames = frames.ok_or_else(|| error(0, "no frames section".to_string()))?;
+        for (n, line) in lines {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mask = u16::from_str_radix(line, 16)
+                .map_err(|_| error(n, format!("invalid key mask {:?}", line)))?;
+            frames.push(mask);
+        }
+
+        let (generator, seed) = rng.ok_or_else(|| error(0, "no rng line".to_string()))?;
+        let instructions_per_frame =
+            instructions_per_frame.ok_or_else(|| error(0, "no ipf line".to_string()))?;
+        Ok(Movie {
+            generator,
+            seed,
+            instructions_per_frame,
+            quirks,
+            frames,
+        })
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "rng {} {}", self.generator.name(), self.seed)?;
+        writeln!(f, "ipf {}", self.instructions_per_frame)?;
+        for name in QUIRK_NAMES.iter() {
+            let enabled = self.quirks.get(name).unwrap_or(false);
+            writeln!(f, "quirk {} {}", name, if enabled { "on" } else { "off" })?;
+        }
+        writeln!(f, "frames")?;
+        for mask in &self.frames {
+            writeln!(f, "{:04x}", mask)?;
+        }
+        Ok(())
+    }
+}
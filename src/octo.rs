@@ -0,0 +1,908 @@
+//! A compiler for Octo, the structured assembly language most CHIP-8
+//! homebrew is written in.
+//!
+//! ```text
+//! :const SPEED 2
+//! :alias x v0
+//!
+//! : ball  0b01100000 0b11110000 0b11110000 0b01100000
+//!
+//! : main
+//!   x := 10
+//!   loop
+//!     i := ball
+//!     sprite x v1 4
+//!     x += SPEED
+//!     if x == 60 then x := 0
+//!   again
+//! ```
+//!
+//! The statements, the `if ... then` and `if ... begin ... else ... end`
+//! conditionals, `loop ... while ... again`, labels, bare numbers as data,
+//! `:const`, `:alias`, `:calc`, `:macro`, `:org`, `:byte`, `:pointer` and
+//! `:call` are supported, along with the SUPER-CHIP and XO-CHIP statements.
+//! `:breakpoint` and `:monitor` are accepted and ignored.
+//!
+//! Like Octo, the program starts with a jump to `: main`, labels can be used
+//! before they are defined wherever an address is expected, and `:calc`
+//! expressions have no operator precedence: they are evaluated right to
+//! left, so `{ 2 * 3 + 1 }` is 8.
+
+use asm::{AsmError, Program};
+use disasm::ORIGIN;
+use instruction::*;
+use symbols::{Location, SymbolMap};
+
+use std::collections::{HashMap, VecDeque};
+use std::f64::consts;
+use std::path::Path;
+
+const MEMORY_END: usize = 0x10000;
+
+/// How many macros a program can expand, which stops macros that expand
+/// themselves.
+const MAX_EXPANSIONS: usize = 1 << 16;
+
+/// Compiles the Octo program `source`.
+pub fn compile(source: &str) -> Result<Program, AsmError> {
+    compile_source(source, Path::new("<input>"))
+}
+
+/// Compiles `source`, read from `path`, which names it in errors.
+pub fn compile_source(source: &str, path: &Path) -> Result<Program, AsmError> {
+    let mut compiler = Compiler::new(path.display().to_string(), source);
+    match compiler.program() {
+        Ok(program) => Ok(program),
+        Err(message) => Err(AsmError {
+            location: Location {
+                file: compiler.file,
+                line: compiler.line,
+            },
+            message,
+        }),
+    }
+}
+
+#[derive(Clone)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+    for (n, line) in source.lines().enumerate() {
+        let code = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        tokens.extend(code.split_whitespace().map(|text| Token {
+            text: text.to_string(),
+            line: n + 1,
+        }));
+    }
+    tokens
+}
+
+#[derive(Clone)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+    calls: usize,
+}
+
+/// An open `loop` or `if ... begin` block and the jumps in it that still
+/// need their targets.
+enum Block {
+    Loop { start: u16, exits: Vec<u16> },
+    If(u16),
+    Else(u16),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Field {
+    /// The low 12 bits of the opcode at the address.
+    Address,
+    /// The two bytes at the address.
+    Long,
+}
+
+/// A label used before its definition.
+struct Reference {
+    address: u16,
+    field: Field,
+    name: String,
+    line: usize,
+}
+
+struct Compiler {
+    file: String,
+    tokens: VecDeque<Token>,
+    /// The line of the token being compiled, for errors.
+    line: usize,
+    /// The line of the statement being compiled, for the symbol map.
+    statement: usize,
+    memory: Vec<u8>,
+    here: usize,
+    end: usize,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    expansions: usize,
+    references: Vec<Reference>,
+    blocks: Vec<Block>,
+    symbols: SymbolMap,
+}
+
+impl Compiler {
+    fn new(file: String, source: &str) -> Compiler {
+        Compiler {
+            file,
+            tokens: tokenize(source),
+            line: 1,
+            statement: 1,
+            memory: vec![0; MEMORY_END],
+            here: ORIGIN as usize,
+            end: ORIGIN as usize,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            expansions: 0,
+            references: Vec::new(),
+            blocks: Vec::new(),
+            symbols: SymbolMap::default(),
+        }
+    }
+
+    fn program(&mut self) -> Result<Program, String> {
+        self.jump_to("main", Jump(0))?;
+        while let Some(token) = self.tokens.pop_front() {
+            self.line = token.line;
+            self.statement = token.line;
+            self.statement_for(&token.text)?;
+        }
+
+        match self.blocks.last() {
+            Some(Block::Loop { .. }) => return Err("a loop is missing its `again`".to_string()),
+            Some(_) => return Err("an `if ... begin` is missing its `end`".to_string()),
+            None => (),
+        }
+        if !self.labels.contains_key("main") {
+            self.line = 1;
+            return Err("the program has no `: main` label".to_string());
+        }
+
+        for reference in &self.references {
+            self.line = reference.line;
+            let address = *self
+                .labels
+                .get(&reference.name)
+                .ok_or_else(|| format!("undefined label {}", reference.name))?;
+            if !patch(&mut self.memory, reference.address, reference.field, address) {
+                let name = &reference.name;
+                return Err(format!("the label {} at {:#06X} is out of reach", name, address));
+            }
+        }
+
+        for (name, &address) in &self.labels {
+            self.symbols.labels.insert(name.clone(), address);
+        }
+        Ok(Program {
+            origin: ORIGIN,
+            rom: self.memory[ORIGIN as usize..self.end].to_vec(),
+            symbols: self.symbols.clone(),
+        })
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        let token = self
+            .tokens
+            .pop_front()
+            .ok_or("the program ends in the middle of a statement")?;
+        self.line = token.line;
+        Ok(token.text)
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), String> {
+        match self.next()? {
+            ref token if token == text => Ok(()),
+            token => Err(format!("expected `{}`, found `{}`", text, token)),
+        }
+    }
+
+    fn statement_for(&mut self, token: &str) -> Result<(), String> {
+        if let Some(x) = self.register_named(token) {
+            return self.assignment(x);
+        }
+
+        match token {
+            ":" => {
+                let name = self.name()?;
+                let here = self.here()?;
+                self.labels.insert(name, here);
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.next()?;
+                let value = self.constant(&value)?;
+                self.constants.insert(name, value);
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let register = self.register()?;
+                self.aliases.insert(name, register);
+            }
+            ":calc" => {
+                let name = self.name()?;
+                let value = self.calc()?;
+                self.constants.insert(name, value);
+            }
+            ":macro" => self.define_macro()?,
+            ":org" => {
+                let address = self.value_or_calc()? as i64;
+                if address < 0 || address >= MEMORY_END as i64 {
+                    return Err(format!("cannot place code at {:#X}", address));
+                }
+                self.here = address as usize;
+            }
+            ":byte" => {
+                let value = self.value_or_calc()?;
+                let byte = to_byte(value)?;
+                self.emit_bytes(&[byte])?;
+            }
+            ":pointer" => {
+                let name = self.next()?;
+                let address = self.address(&name, Field::Long)?;
+                self.emit_bytes(&[(address >> 8) as u8, address as u8])?;
+            }
+            ":call" => {
+                let name = self.next()?;
+                self.jump_to(&name, Call(0))?;
+            }
+            ":breakpoint" => {
+                self.next()?;
+            }
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            }
+            "return" | ";" => self.emit(Return)?,
+            "clear" => self.emit(Clear)?,
+            "hires" => self.emit(HighRes)?,
+            "lores" => self.emit(LowRes)?,
+            "scroll-left" => self.emit(ScrollLeft)?,
+            "scroll-right" => self.emit(ScrollRight)?,
+            "exit" => self.emit(Exit)?,
+            "audio" => self.emit(LoadAudio)?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(ScrollDown(n))?;
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(ScrollUp(n))?;
+            }
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(SelectPlanes(n))?;
+            }
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(SetBCD(x))?;
+            }
+            "save" | "load" => {
+                let x = self.register()?;
+                let save = token == "save";
+                let instruction = if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = self.register()?;
+                    if save {
+                        SaveRange(x, y)
+                    } else {
+                        LoadRange(x, y)
+                    }
+                } else if save {
+                    DumpRegisters(x)
+                } else {
+                    LoadRegisters(x)
+                };
+                self.emit(instruction)?;
+            }
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(SaveFlags(x))?;
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(LoadFlags(x))?;
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(Draw(x, y, n))?;
+            }
+            "jump" => {
+                let name = self.next()?;
+                self.jump_to(&name, Jump(0))?;
+            }
+            "jump0" => {
+                let name = self.next()?;
+                self.jump_to(&name, JumpV0Address(0))?;
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.emit(match token {
+                    "delay" => SetDelay(x),
+                    "buzzer" => SetSound(x),
+                    _ => SetPitch(x),
+                })?;
+            }
+            "i" => self.i_assignment()?,
+            "if" => {
+                // Compiled with the test inverted: `then` skips its statement
+                // when the condition is false, `begin` skips the jump past
+                // its block when it is true.
+                let condition = self.take_condition()?;
+                match self.next()?.as_str() {
+                    "then" => self.condition(&condition, false)?,
+                    "begin" => {
+                        self.condition(&condition, true)?;
+                        let jump = self.forward_jump()?;
+                        self.blocks.push(Block::If(jump));
+                    }
+                    token => return Err(format!("expected `then` or `begin`, found `{}`", token)),
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some(Block::If(jump)) => {
+                    let end = self.forward_jump()?;
+                    self.land(jump)?;
+                    self.blocks.push(Block::Else(end));
+                }
+                _ => return Err("`else` without `if ... begin`".to_string()),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If(jump)) | Some(Block::Else(jump)) => self.land(jump)?,
+                _ => return Err("`end` without `if ... begin`".to_string()),
+            },
+            "loop" => {
+                let start = self.here()?;
+                self.blocks.push(Block::Loop {
+                    start,
+                    exits: Vec::new(),
+                });
+            }
+            "while" => {
+                let condition = self.take_condition()?;
+                self.condition(&condition, true)?;
+                let exit = self.forward_jump()?;
+                match self.blocks.iter_mut().rev().find_map(|block| match block {
+                    Block::Loop { exits, .. } => Some(exits),
+                    _ => None,
+                }) {
+                    Some(exits) => exits.push(exit),
+                    None => return Err("`while` outside of a loop".to_string()),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, exits }) => {
+                    self.emit(Jump(start))?;
+                    for exit in exits {
+                        self.land(exit)?;
+                    }
+                }
+                _ => return Err("`again` without `loop`".to_string()),
+            },
+            _ if self.macros.contains_key(token) => self.expand(token)?,
+            _ => match self.constant(token) {
+                Ok(value) if !self.labels.contains_key(token) => {
+                    let byte = to_byte(value)?;
+                    self.emit_bytes(&[byte])?;
+                }
+                _ if is_name(token) => self.jump_to(token, Call(0))?,
+                _ => return Err(format!("unexpected `{}`", token)),
+            },
+        }
+        Ok(())
+    }
+
+    /// A statement that starts with the register `x`.
+    fn assignment(&mut self, x: u8) -> Result<(), String> {
+        let operator = self.next()?;
+        let operand = self.next()?;
+        let y = self.register_named(&operand);
+
+        let instruction = match (operator.as_str(), y) {
+            (":=", Some(y)) => Load(x, y),
+            (":=", None) if operand == "key" => WaitForKey(x),
+            (":=", None) if operand == "delay" => LoadDelay(x),
+            (":=", None) if operand == "random" => {
+                let mask = self.byte()?;
+                RandomAnd(x, mask)
+            }
+            (":=", None) => LoadConstant(x, to_byte(self.constant(&operand)?)?),
+            ("+=", Some(y)) => Add(x, y),
+            ("+=", None) => AddConstant(x, to_byte(self.constant(&operand)?)?),
+            ("-=", Some(y)) => Sub(x, y),
+            ("-=", None) => AddConstant(x, to_byte(-self.constant(&operand)?)?),
+            ("=-", Some(y)) => SubReverse(x, y),
+            ("|=", Some(y)) => Or(x, y),
+            ("&=", Some(y)) => And(x, y),
+            ("^=", Some(y)) => Xor(x, y),
+            (">>=", Some(y)) => ShiftRight(x, y),
+            ("<<=", Some(y)) => ShiftLeft(x, y),
+            (_, None) if ["=-", "|=", "&=", "^=", ">>=", "<<="].contains(&operator.as_str()) => {
+                return Err(format!("`{}` needs a register, found `{}`", operator, operand))
+            }
+            _ => return Err(format!("unknown operator `{}`", operator)),
+        };
+        self.emit(instruction)
+    }
+
+    /// `i := ...` and `i += vx`.
+    fn i_assignment(&mut self) -> Result<(), String> {
+        let operator = self.next()?;
+        let operand = self.next()?;
+        match (operator.as_str(), operand.as_str()) {
+            ("+=", _) => {
+                let x = self.register_of(&operand)?;
+                self.emit(AddAddress(x))
+            }
+            (":=", "hex") => {
+                let x = self.register()?;
+                self.emit(SetFontLocation(x))
+            }
+            (":=", "bighex") => {
+                let x = self.register()?;
+                self.emit(SetBigFontLocation(x))
+            }
+            (":=", "long") => {
+                let name = self.next()?;
+                // The address is the second half of the instruction.
+                self.here += 2;
+                let address = self.address(&name, Field::Long);
+                self.here -= 2;
+                self.emit(SetLongAddress(address?))
+            }
+            (":=", _) => self.jump_to(&operand, SetAddress(0)),
+            _ => Err(format!("unknown operator `{}`", operator)),
+        }
+    }
+
+    /// The tokens of a condition: a register, a test and maybe an operand.
+    fn take_condition(&mut self) -> Result<Vec<String>, String> {
+        let x = self.next()?;
+        let test = self.next()?;
+        let mut condition = vec![x, test];
+        if condition[1] != "key" && condition[1] != "-key" {
+            condition.push(self.next()?);
+        }
+        Ok(condition)
+    }
+
+    /// Emits the instructions that skip the next one when `condition` is
+    /// `skip_when`. The comparisons are computed in VF, like Octo does.
+    fn condition(&mut self, condition: &[String], skip_when: bool) -> Result<(), String> {
+        let x = self.register_of(&condition[0])?;
+        let test = condition[1].as_str();
+        let operand = condition.get(2).map(|operand| operand.as_str()).unwrap_or("");
+        let y = self.register_named(operand);
+
+        match test {
+            "key" | "-key" => {
+                let pressed = (test == "key") == skip_when;
+                self.emit(if pressed { SkipIfPressed(x) } else { SkipIfNotPressed(x) })
+            }
+            "==" | "!=" => {
+                let equal = (test == "==") == skip_when;
+                self.emit(match y {
+                    Some(y) if equal => SkipIfEqual(x, y),
+                    Some(y) => SkipIfNotEqual(x, y),
+                    None => {
+                        let kk = to_byte(self.constant(operand)?)?;
+                        if equal {
+                            SkipIfConstantEqual(x, kk)
+                        } else {
+                            SkipIfConstantNotEqual(x, kk)
+                        }
+                    }
+                })
+            }
+            "<" | ">" | "<=" | ">=" => {
+                // VF ends up 1 when `x >= operand` for `<` and `>=`, and when
+                // `operand >= x` for `>` and `<=`.
+                let at_least = test == "<" || test == ">=";
+                match y {
+                    Some(y) if at_least => {
+                        self.emit(Load(0xF, x))?;
+                        self.emit(Sub(0xF, y))?;
+                    }
+                    Some(y) => {
+                        self.emit(Load(0xF, y))?;
+                        self.emit(Sub(0xF, x))?;
+                    }
+                    None => {
+                        let kk = to_byte(self.constant(operand)?)?;
+                        self.emit(LoadConstant(0xF, kk))?;
+                        self.emit(if at_least { SubReverse(0xF, x) } else { Sub(0xF, x) })?;
+                    }
+                }
+                let true_when_set = test == ">=" || test == "<=";
+                self.emit(if true_when_set == skip_when {
+                    SkipIfConstantEqual(0xF, 1)
+                } else {
+                    SkipIfConstantNotEqual(0xF, 1)
+                })
+            }
+            _ => Err(format!("unknown test `{}`", test)),
+        }
+    }
+
+    /// Emits `instruction` with its address pointing at the label `name`,
+    /// which may not be defined yet.
+    fn jump_to(&mut self, name: &str, instruction: Instruction) -> Result<(), String> {
+        let address = self.address(name, Field::Address)?;
+        self.emit(match instruction {
+            Jump(_) => Jump(address),
+            Call(_) => Call(address),
+            JumpV0Address(_) => JumpV0Address(address),
+            _ => SetAddress(address),
+        })
+    }
+
+    /// Emits a jump to be pointed somewhere with `land` and returns its
+    /// address.
+    fn forward_jump(&mut self) -> Result<u16, String> {
+        let address = self.here()?;
+        self.emit(Jump(0))?;
+        Ok(address)
+    }
+
+    /// Points the jump at `jump` here.
+    fn land(&mut self, jump: u16) -> Result<(), String> {
+        let here = self.here()?;
+        if !patch(&mut self.memory, jump, Field::Address, here) {
+            return Err(format!("the jump at {:#06X} cannot reach {:#06X}", jump, here));
+        }
+        Ok(())
+    }
+
+    /// The value of `name` if it is known, or 0 after noting that the field
+    /// at `here` needs the label's address once it is defined.
+    fn address(&mut self, name: &str, field: Field) -> Result<u16, String> {
+        let max = if field == Field::Long { 0xFFFF } else { 0xFFF };
+        if let Ok(value) = self.constant(name) {
+            return match value as i64 {
+                address if (0..=max).contains(&address) => Ok(address as u16),
+                address => Err(format!("{:#X} is not a valid address", address)),
+            };
+        }
+        if !is_name(name) {
+            return Err(format!("expected an address, found `{}`", name));
+        }
+        let address = self.here()?;
+        self.references.push(Reference {
+            address,
+            field,
+            name: name.to_string(),
+            line: self.line,
+        });
+        Ok(0)
+    }
+
+    fn here(&self) -> Result<u16, String> {
+        if self.here >= MEMORY_END {
+            return Err("the program does not fit in memory".to_string());
+        }
+        Ok(self.here as u16)
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> Result<(), String> {
+        let location = Location {
+            file: self.file.clone(),
+            line: self.statement,
+        };
+        self.symbols.lines.insert(self.here()?, location);
+        self.emit_bytes(&instruction.encode())
+    }
+
+    fn emit_bytes(&mut self, bytes: &[u8]) -> Result<(), String> {
+        if self.here + bytes.len() > MEMORY_END {
+            return Err("the program does not fit in memory".to_string());
+        }
+        self.memory[self.here..self.here + bytes.len()].copy_from_slice(bytes);
+        self.here += bytes.len();
+        self.end = self.end.max(self.here);
+        Ok(())
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let name = self.next()?;
+        if !is_name(&name) || self.register_named(&name).is_some() {
+            return Err(format!("`{}` cannot be used as a name", name));
+        }
+        if self.labels.contains_key(&name) || self.constants.contains_key(&name) {
+            return Err(format!("{} is already defined", name));
+        }
+        Ok(name)
+    }
+
+    fn register_named(&self, name: &str) -> Option<u8> {
+        let lower = name.to_lowercase();
+        if lower.len() == 2 && lower.starts_with('v') {
+            if let Some(digit) = lower[1..].chars().next().and_then(|c| c.to_digit(16)) {
+                return Some(digit as u8);
+            }
+        }
+        self.aliases.get(name).cloned()
+    }
+
+    fn register_of(&self, name: &str) -> Result<u8, String> {
+        self.register_named(name)
+            .ok_or_else(|| format!("expected a register, found `{}`", name))
+    }
+
+    fn register(&mut self) -> Result<u8, String> {
+        let name = self.next()?;
+        self.register_of(&name)
+    }
+
+    /// The value of a number, constant or label defined so far.
+    fn constant(&self, token: &str) -> Result<f64, String> {
+        if let Some(value) = number(token) {
+            return Ok(value);
+        }
+        if let Some(&value) = self.constants.get(token) {
+            return Ok(value);
+        }
+        if let Some(&address) = self.labels.get(token) {
+            return Ok(address as f64);
+        }
+        Err(format!("`{}` is not a number or a defined constant", token))
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        let token = self.next()?;
+        to_byte(self.constant(&token)?)
+    }
+
+    fn nibble(&mut self) -> Result<u8, String> {
+        let token = self.next()?;
+        match self.constant(&token)? as i64 {
+            n if (0..=0xF).contains(&n) => Ok(n as u8),
+            n => Err(format!("{} does not fit in a nibble", n)),
+        }
+    }
+
+    /// A constant, or a `{ ... }` expression.
+    fn value_or_calc(&mut self) -> Result<f64, String> {
+        match self.peek() {
+            Some("{") => self.calc(),
+            _ => {
+                let token = self.next()?;
+                self.constant(&token)
+            }
+        }
+    }
+
+    /// The tokens up to the `}` matching the `{` that comes next.
+    fn braced(&mut self) -> Result<Vec<Token>, String> {
+        self.expect("{")?;
+        let mut tokens = Vec::new();
+        let mut depth = 0;
+        loop {
+            let token = self
+                .tokens
+                .pop_front()
+                .ok_or("the program ends before a `}`")?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => return Ok(tokens),
+                "}" => depth -= 1,
+                _ => (),
+            }
+            tokens.push(token);
+        }
+    }
+
+    fn define_macro(&mut self) -> Result<(), String> {
+        let name = self.name()?;
+        let mut params = Vec::new();
+        while !matches!(self.peek(), Some("{") | None) {
+            params.push(self.next()?);
+        }
+        let body = self.braced()?;
+        self.macros.insert(
+            name,
+            Macro {
+                params,
+                body,
+                calls: 0,
+            },
+        );
+        Ok(())
+    }
+
+    /// Replaces a use of the macro `name` and its arguments with its body.
+    fn expand(&mut self, name: &str) -> Result<(), String> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(format!("the macro {} expands itself forever", name));
+        }
+
+        let calls = {
+            let definition = self.macros.get_mut(name).expect("the macro is defined");
+            definition.calls += 1;
+            definition.calls - 1
+        };
+        let definition = self.macros[name].clone();
+        let mut args = HashMap::new();
+        for param in &definition.params {
+            args.insert(param.clone(), self.next()?);
+        }
+
+        for token in definition.body.iter().rev() {
+            let text = match args.get(&token.text) {
+                Some(arg) => arg.clone(),
+                None if token.text == "CALLS" => calls.to_string(),
+                None => token.text.clone(),
+            };
+            // Errors and the symbol map point at the use of the macro.
+            self.tokens.push_front(Token {
+                text,
+                line: self.line,
+            });
+        }
+        Ok(())
+    }
+
+    /// Evaluates a `{ ... }` expression.
+    fn calc(&mut self) -> Result<f64, String> {
+        let tokens: Vec<String> = self.braced()?.into_iter().map(|token| token.text).collect();
+        let mut pos = 0;
+        let value = self.expression(&tokens, &mut pos)?;
+        match tokens.get(pos) {
+            Some(token) => Err(format!("unexpected `{}` in the expression", token)),
+            None => Ok(value),
+        }
+    }
+
+    fn expression(&self, tokens: &[String], pos: &mut usize) -> Result<f64, String> {
+        let left = self.term(tokens, pos)?;
+        let operator = match tokens.get(*pos) {
+            Some(operator) if operator != ")" => operator.as_str(),
+            _ => return Ok(left),
+        };
+        *pos += 1;
+        let right = self.expression(tokens, pos)?;
+        Ok(match operator {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            "/" => left / right,
+            "%" => left % right,
+            "&" => (left as i64 & right as i64) as f64,
+            "|" => (left as i64 | right as i64) as f64,
+            "^" => (left as i64 ^ right as i64) as f64,
+            "<<" => ((left as i64) << (right as i64 & 63)) as f64,
+            ">>" => ((left as i64) >> (right as i64 & 63)) as f64,
+            "pow" => left.powf(right),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "<" => (left < right) as u8 as f64,
+            "<=" => (left <= right) as u8 as f64,
+            ">" => (left > right) as u8 as f64,
+            ">=" => (left >= right) as u8 as f64,
+            "==" => (left == right) as u8 as f64,
+            "!=" => (left != right) as u8 as f64,
+            _ => return Err(format!("unknown operator `{}`", operator)),
+        })
+    }
+
+    fn term(&self, tokens: &[String], pos: &mut usize) -> Result<f64, String> {
+        let token = tokens
+            .get(*pos)
+            .ok_or("the expression ends early")?
+            .as_str();
+        *pos += 1;
+
+        let unary: Option<fn(f64) -> f64> = match token {
+            "-" => Some(|x| -x),
+            "~" => Some(|x| !(x as i64) as f64),
+            "!" => Some(|x| (x == 0.0) as u8 as f64),
+            "sin" => Some(f64::sin),
+            "cos" => Some(f64::cos),
+            "tan" => Some(f64::tan),
+            "exp" => Some(f64::exp),
+            "log" => Some(f64::ln),
+            "abs" => Some(f64::abs),
+            "sqrt" => Some(f64::sqrt),
+            "sign" => Some(f64::signum),
+            "ceil" => Some(f64::ceil),
+            "floor" => Some(f64::floor),
+            _ => None,
+        };
+        if let Some(function) = unary {
+            return Ok(function(self.term(tokens, pos)?));
+        }
+
+        match token {
+            "(" => {
+                let value = self.expression(tokens, pos)?;
+                match tokens.get(*pos) {
+                    Some(token) if token == ")" => {
+                        *pos += 1;
+                        Ok(value)
+                    }
+                    _ => Err("missing `)` in the expression".to_string()),
+                }
+            }
+            "@" => {
+                let address = self.term(tokens, pos)? as i64;
+                match self.memory.get(address as usize) {
+                    Some(&byte) if address >= 0 => Ok(byte as f64),
+                    _ => Err(format!("cannot read memory at {:#X}", address)),
+                }
+            }
+            "HERE" => Ok(self.here as f64),
+            "PI" => Ok(consts::PI),
+            "E" => Ok(consts::E),
+            _ => self.constant(token),
+        }
+    }
+}
+
+/// Writes `address` into the field at `at`. Returns `false` if it does not
+/// fit.
+fn patch(memory: &mut [u8], at: u16, field: Field, address: u16) -> bool {
+    let at = at as usize;
+    match field {
+        Field::Address if address > 0xFFF => return false,
+        Field::Address => memory[at] = (memory[at] & 0xF0) | (address >> 8) as u8,
+        Field::Long => memory[at] = (address >> 8) as u8,
+    }
+    memory[at + 1] = address as u8;
+    true
+}
+
+fn to_byte(value: f64) -> Result<u8, String> {
+    match value as i64 {
+        byte if (-0x80..=0xFF).contains(&byte) => Ok(byte as u8),
+        byte => Err(format!("{} does not fit in a byte", byte)),
+    }
+}
+
+fn number(token: &str) -> Option<f64> {
+    let (negative, digits) = match token.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, token),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        return digits.parse::<f64>().ok().map(|value| if negative { -value } else { value });
+    } else {
+        return None;
+    };
+    Some(if negative { -value as f64 } else { value as f64 })
+}
+
+fn is_name(token: &str) -> bool {
+    let mut chars = token.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        }
+        _ => false,
+    }
+}
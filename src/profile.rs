@@ -0,0 +1,239 @@
+//! An execution profiler. Attached to a `Cpu` with `Cpu::set_profiler` it
+//! counts how often every address and every kind of instruction executes,
+//! follows `Call` and `Return` to add up the cycles spent in each
+//! subroutine, and notes the backward jumps that close loops. `report` ranks
+//! what it found, to see where a program that is too slow on real hardware
+//! spends its time.
+
+use cpu::Cpu;
+use instruction::Instruction::{self, *};
+use symbols::SymbolMap;
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// How many entries each table of the report lists.
+const TOP: usize = 20;
+
+/// A loop that reads the delay timer and is at most this many instructions
+/// long is taken to be waiting for the timer.
+const MAX_POLL_LENGTH: usize = 8;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Routine {
+    calls: u64,
+    /// The cycles from the `Call` to its `Return`, including the routines
+    /// it calls in turn.
+    cycles: u64,
+}
+
+pub struct Profiler {
+    cycles: u64,
+    hits: Vec<u64>,
+    instructions: HashMap<&'static str, u64>,
+    routines: HashMap<u16, Routine>,
+    /// The routines that have been called and have not returned yet, with
+    /// the cycle they were called at.
+    calls: Vec<(u16, u64)>,
+    /// How often the backward jump at the end of each loop was taken, by
+    /// the first and last address of the loop.
+    loops: HashMap<(u16, u16), u64>,
+}
+
+impl Default for Profiler {
+    fn default() -> Profiler {
+        Profiler::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            cycles: 0,
+            hits: vec![0; 0x10000],
+            instructions: HashMap::new(),
+            routines: HashMap::new(),
+            calls: Vec::new(),
+            loops: HashMap::new(),
+        }
+    }
+
+    /// The number of instructions executed so far.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// How often the instruction at `address` executed.
+    pub fn hits(&self, address: u16) -> u64 {
+        self.hits[address as usize]
+    }
+
+    /// Counts `instruction`, which `cpu` is about to execute.
+    pub fn record(&mut self, cpu: &Cpu, instruction: Instruction) {
+        let pc = cpu.pc();
+        self.cycles += 1;
+        self.hits[pc as usize] += 1;
+        *self.instructions.entry(instruction.name()).or_default() += 1;
+
+        // Loading a state or rewinding can pop calls behind our back.
+        self.calls.truncate(cpu.sp() as usize);
+        match instruction {
+            Call(address) => {
+                self.routines.entry(address).or_default().calls += 1;
+                self.calls.push((address, self.cycles));
+            }
+            Return => {
+                if let Some((address, start)) = self.calls.pop() {
+                    // A recursive routine is counted once, by its outermost
+                    // call.
+                    if self.calls.iter().all(|&(open, _)| open != address) {
+                        self.routines.entry(address).or_default().cycles +=
+                            self.cycles - start;
+                    }
+                }
+            }
+            Jump(address) if address <= pc => {
+                *self.loops.entry((address, pc)).or_default() += 1;
+            }
+            _ => {}
+        }
+    }
+
+    /// The cycles spent in the loop from `start` to `end`, counted at each
+    /// instruction in it.
+    fn loop_cycles(&self, memory: &[u8], start: u16, end: u16) -> u64 {
+        let mut cycles = 0;
+        let mut address = start;
+        while address <= end {
+            cycles += self.hits(address);
+            address = match address.checked_add(Instruction::decode_at(memory, address).size()) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+        cycles
+    }
+
+    /// Whether the loop from `start` to `end` does nothing but wait for the
+    /// delay timer to run down.
+    fn polls_delay_timer(memory: &[u8], start: u16, end: u16) -> bool {
+        let mut body = Vec::new();
+        let mut address = start;
+        while address <= end && body.len() <= MAX_POLL_LENGTH {
+            let instruction = Instruction::decode_at(memory, address);
+            body.push(instruction);
+            address = match address.checked_add(instruction.size()) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+        body.len() <= MAX_POLL_LENGTH
+            && body.iter().any(|instruction| matches!(instruction, LoadDelay(_)))
+            && !body.iter().any(|instruction| {
+                matches!(
+                    instruction,
+                    Draw(..) | Call(_) | WaitForKey(_) | SaveRange(..) | DumpRegisters(_) | SetBCD(_)
+                )
+            })
+    }
+
+    /// A ranked report of the hottest loops, the time spent waiting for
+    /// keys and the delay timer, the cycles spent in every routine and the
+    /// hottest instructions. `memory` is the program the profile was taken
+    /// of, `symbols` names its routines.
+    pub fn report(&self, memory: &[u8], symbols: Option<&SymbolMap>) -> String {
+        let total = self.cycles.max(1) as f64;
+        let percent = |cycles: u64| 100.0 * cycles as f64 / total;
+        let routine_of = |address: u16| match symbols.and_then(|symbols| symbols.routine(address)) {
+            Some((name, start)) if start == address => name.to_string(),
+            Some((name, start)) => format!("{}+{}", name, address - start),
+            None => String::new(),
+        };
+        let mut out = String::new();
+        writeln!(out, "{} instructions executed", self.cycles).unwrap();
+
+        let mut loops: Vec<(u16, u16, u64, u64)> = self
+            .loops
+            .iter()
+            .map(|(&(start, end), &iterations)| (start, end, iterations, self.loop_cycles(memory, start, end)))
+            .collect();
+        loops.sort_by(|a, b| b.3.cmp(&a.3).then(a.0.cmp(&b.0)));
+        writeln!(out, "\nhottest loops\n      cycles       %  iterations  addresses    routine").unwrap();
+        for &(start, end, iterations, cycles) in loops.iter().take(TOP) {
+            writeln!(
+                out,
+                "{:>12} {:>6.2}% {:>11}  {:04X}-{:04X}    {}",
+                cycles,
+                percent(cycles),
+                iterations,
+                start,
+                end,
+                routine_of(start)
+            )
+            .unwrap();
+        }
+
+        let key_wait = self.instructions.get("WaitForKey").cloned().unwrap_or(0);
+        let delay_wait: u64 = loops
+            .iter()
+            .filter(|&&(start, end, _, _)| Profiler::polls_delay_timer(memory, start, end))
+            .map(|&(_, _, _, cycles)| cycles)
+            .sum();
+        writeln!(out, "\nwaiting\n      cycles       %").unwrap();
+        writeln!(out, "{:>12} {:>6.2}%  for a key (FX0A)", key_wait, percent(key_wait)).unwrap();
+        writeln!(out, "{:>12} {:>6.2}%  polling the delay timer (FX07)", delay_wait, percent(delay_wait)).unwrap();
+
+        // Calls that are still running count up to now.
+        let mut routines = self.routines.clone();
+        for (n, &(address, start)) in self.calls.iter().enumerate() {
+            if self.calls[..n].iter().all(|&(open, _)| open != address) {
+                routines.entry(address).or_default().cycles += self.cycles - start;
+            }
+        }
+        let mut routines: Vec<(u16, Routine)> = routines.into_iter().collect();
+        routines.sort_by(|a, b| b.1.cycles.cmp(&a.1.cycles).then(a.0.cmp(&b.0)));
+        writeln!(out, "\nroutines\n      cycles       %       calls  address  name").unwrap();
+        for &(address, routine) in routines.iter().take(TOP) {
+            writeln!(
+                out,
+                "{:>12} {:>6.2}% {:>11}  {:04X}     {}",
+                routine.cycles,
+                percent(routine.cycles),
+                routine.calls,
+                address,
+                routine_of(address)
+            )
+            .unwrap();
+        }
+
+        let mut instructions: Vec<(&str, u64)> = self.instructions.iter().map(|(&name, &count)| (name, count)).collect();
+        instructions.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        writeln!(out, "\ninstructions\n       count       %  instruction").unwrap();
+        for &(name, count) in &instructions {
+            writeln!(out, "{:>12} {:>6.2}%  {}", count, percent(count), name).unwrap();
+        }
+
+        let mut addresses: Vec<(u16, u64)> = self
+            .hits
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(address, &count)| (address as u16, count))
+            .collect();
+        addresses.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        writeln!(out, "\nhottest addresses\n       count       %  address  instruction").unwrap();
+        for &(address, count) in addresses.iter().take(TOP) {
+            writeln!(
+                out,
+                "{:>12} {:>6.2}%  {:04X}     {:<24} {}",
+                count,
+                percent(count),
+                address,
+                format!("{:?}", Instruction::decode_at(memory, address)),
+                routine_of(address)
+            )
+            .unwrap();
+        }
+        out
+    }
+}
@@ -0,0 +1,165 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Selects between the behaviours that different interpreters gave to the
+/// ambiguous CHIP-8 opcodes.
+///
+/// The default matches what this interpreter has always done, which is also
+/// what modern XO-CHIP ROMs expect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// `8XY6`/`8XYE` shift Vy into Vx instead of shifting Vx in place.
+    pub shift_uses_vy: bool,
+    /// `FX55`/`FX65` leave I pointing just past the last register transferred.
+    pub load_store_increments_i: bool,
+    /// `8XY1`/`8XY2`/`8XY3` reset VF to zero.
+    pub logic_resets_vf: bool,
+    /// Sprites are clipped at the screen edges instead of wrapping around.
+    pub clip_sprites: bool,
+    /// `BNNN` is read as `BXNN` and jumps to XNN + Vx instead of NNN + V0.
+    pub jump_uses_vx: bool,
+    /// `DXYN` waits for the next vertical blank before drawing.
+    pub display_wait: bool,
+    /// `FX0A` completes when the key is released, as on the COSMAC VIP,
+    /// rather than as soon as it is pressed.
+    pub key_wait_release: bool,
+}
+
+/// The names accepted by `Quirks::set`, in the order they are documented.
+pub const QUIRK_NAMES: [&str; 7] = [
+    "shift",
+    "load-store",
+    "vf-reset",
+    "clip",
+    "jump",
+    "display-wait",
+    "key-release",
+];
+
+/// The profile names accepted by `Quirks::from_str`.
+pub const PROFILE_NAMES: [&str; 3] = ["vip", "schip", "xochip"];
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks::xochip()
+    }
+}
+
+impl Quirks {
+    /// The original COSMAC VIP interpreter.
+    pub fn vip() -> Quirks {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            logic_resets_vf: true,
+            clip_sprites: true,
+            jump_uses_vx: false,
+            display_wait: true,
+            key_wait_release: true,
+        }
+    }
+
+    /// SUPER-CHIP 1.1 on the HP 48.
+    pub fn schip() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            logic_resets_vf: false,
+            clip_sprites: true,
+            jump_uses_vx: true,
+            display_wait: false,
+            key_wait_release: true,
+        }
+    }
+
+    /// XO-CHIP as implemented by Octo.
+    pub fn xochip() -> Quirks {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            logic_resets_vf: false,
+            clip_sprites: false,
+            jump_uses_vx: false,
+            display_wait: false,
+            key_wait_release: true,
+        }
+    }
+
+    /// Turns the quirk called `name` (one of `QUIRK_NAMES`) on or off.
+    /// Returns `false` if there is no such quirk.
+    pub fn set(&mut self, name: &str, enabled: bool) -> bool {
+        match self.quirk_mut(name) {
+            Some(quirk) => {
+                *quirk = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the quirk called `name` is on, `None` if there is no such quirk.
+    pub fn get(&self, name: &str) -> Option<bool> {
+        let mut quirks = *self;
+        quirks.quirk_mut(name).map(|quirk| *quirk)
+    }
+
+    fn quirk_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "shift" => Some(&mut self.shift_uses_vy),
+            "load-store" => Some(&mut self.load_store_increments_i),
+            "vf-reset" => Some(&mut self.logic_resets_vf),
+            "clip" => Some(&mut self.clip_sprites),
+            "jump" => Some(&mut self.jump_uses_vx),
+            "display-wait" => Some(&mut self.display_wait),
+            "key-release" => Some(&mut self.key_wait_release),
+            _ => None,
+        }
+    }
+
+    /// Applies a `name=on` or `name=off` setting as given on the command line.
+    pub fn apply(&mut self, setting: &str) -> Result<(), String> {
+        let mut parts = setting.splitn(2, '=');
+        let name = parts.next().unwrap_or("");
+        let enabled = match parts.next() {
+            Some("on") => true,
+            Some("off") => false,
+            _ => return Err(format!("expected name=on or name=off, got {:?}", setting)),
+        };
+        if self.set(name, enabled) {
+            Ok(())
+        } else {
+            Err(format!(
+                "unknown quirk {:?}, expected one of {}",
+                name,
+                QUIRK_NAMES.join(", ")
+            ))
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownProfile(pub String);
+
+impl fmt::Display for UnknownProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unknown quirks profile {:?}, expected one of {}",
+            self.0,
+            PROFILE_NAMES.join(", ")
+        )
+    }
+}
+
+impl FromStr for Quirks {
+    type Err = UnknownProfile;
+
+    fn from_str(profile: &str) -> Result<Quirks, UnknownProfile> {
+        match profile {
+            "vip" => Ok(Quirks::vip()),
+            "schip" => Ok(Quirks::schip()),
+            "xochip" => Ok(Quirks::xochip()),
+            _ => Err(UnknownProfile(profile.to_string())),
+        }
+    }
+}
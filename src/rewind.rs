@@ -0,0 +1,151 @@
+//! A bounded history of save states for running the emulation backwards.
+//!
+//! Only the newest state is kept whole. Every older state is stored as the
+//! difference to the state after it: the two are XORed together, which leaves
+//! zeroes wherever nothing changed, and the zero runs are then run-length
+//! encoded. A frame of a typical game changes a few dozen bytes, so a frame
+//! of history costs tens of bytes instead of a full 70 KiB state.
+
+use std::collections::VecDeque;
+
+/// How a state differs from the one after it.
+enum Delta {
+    /// The run-length encoded XOR of the two states.
+    Xor(Vec<u8>),
+    /// The whole state, for when the two have different lengths.
+    Full(Vec<u8>),
+}
+
+impl Delta {
+    fn size(&self) -> usize {
+        match self {
+            Delta::Xor(data) | Delta::Full(data) => data.len(),
+        }
+    }
+}
+
+pub struct Rewind {
+    capacity: usize,
+    newest: Option<Vec<u8>>,
+    deltas: VecDeque<Delta>,
+    size: usize,
+}
+
+impl Rewind {
+    /// Creates a history that remembers up to `capacity` frames.
+    pub fn new(capacity: usize) -> Rewind {
+        Rewind {
+            capacity,
+            newest: None,
+            deltas: VecDeque::new(),
+            size: 0,
+        }
+    }
+
+    /// Records `state` as the newest frame, forgetting the oldest one if the
+    /// history is full.
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(previous) = self.newest.take() {
+            let delta = if previous.len() == state.len() {
+                Delta::Xor(encode(&previous, &state))
+            } else {
+                Delta::Full(previous)
+            };
+            self.size += delta.size();
+            self.deltas.push_back(delta);
+        }
+        self.newest = Some(state);
+
+        while self.deltas.len() > self.capacity {
+            if let Some(delta) = self.deltas.pop_front() {
+                self.size -= delta.size();
+            }
+        }
+    }
+
+    /// Steps one frame back and returns that frame's state, or `None` once
+    /// the oldest remembered frame has been reached.
+    pub fn step_back(&mut self) -> Option<&[u8]> {
+        let delta = self.deltas.pop_back()?;
+        self.size -= delta.size();
+        let newest = self.newest.as_mut()?;
+        match delta {
+            Delta::Xor(data) => decode(newest, &data),
+            Delta::Full(state) => *newest = state,
+        }
+        Some(newest)
+    }
+
+    /// The number of frames that can be stepped back.
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// The bytes used by the history, roughly.
+    pub fn memory_usage(&self) -> usize {
+        self.size + self.newest.as_ref().map_or(0, |state| state.len())
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.size = 0;
+    }
+}
+
+/// Encodes `a XOR b` as pairs of a zero run length and a literal run, both
+/// lengths as variable-length integers.
+fn encode(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    while pos < a.len() {
+        let zeroes = (pos..a.len()).take_while(|&i| a[i] == b[i]).count();
+        pos += zeroes;
+        let literals = (pos..a.len()).take_while(|&i| a[i] != b[i]).count();
+        write_varint(&mut out, zeroes);
+        write_varint(&mut out, literals);
+        out.extend((pos..pos + literals).map(|i| a[i] ^ b[i]));
+        pos += literals;
+    }
+    out
+}
+
+/// XORs the difference produced by `encode` back into `state`.
+fn decode(state: &mut [u8], mut data: &[u8]) {
+    let mut pos = 0;
+    while !data.is_empty() {
+        pos += read_varint(&mut data);
+        let literals = read_varint(&mut data);
+        for (byte, delta) in state[pos..pos + literals].iter_mut().zip(data) {
+            *byte ^= delta;
+        }
+        data = &data[literals..];
+        pos += literals;
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &mut &[u8]) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    while let Some((&byte, rest)) = data.split_first() {
+        *data = rest;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    value
+}
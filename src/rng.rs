@@ -0,0 +1,179 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Where `CXNN` gets its random bytes from.
+pub trait RandomSource {
+    /// Returns the next random byte.
+    fn next_byte(&mut self) -> u8;
+
+    /// Called once at the end of every 60 Hz frame.
+    fn frame(&mut self) {}
+
+    /// The generator's internal state, for save states.
+    fn save(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Restores a state returned by `save`. Returns `false` if `state` did
+    /// not come from this kind of generator.
+    fn restore(&mut self, state: &[u8]) -> bool {
+        state.is_empty()
+    }
+}
+
+/// A xorshift64* generator, small, fast and fully determined by its seed.
+pub struct Xorshift {
+    state: u64,
+}
+
+impl Xorshift {
+    pub fn new(seed: u64) -> Xorshift {
+        // The all-zero state is a fixed point, so nudge it away from there.
+        let state = seed ^ 0x9E37_79B9_7F4A_7C15;
+        Xorshift {
+            state: if state == 0 { 0x2545_F491_4F6C_DD1D } else { state },
+        }
+    }
+}
+
+impl RandomSource for Xorshift {
+    fn next_byte(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+
+    fn save(&self) -> Vec<u8> {
+        (0..8).map(|byte| (self.state >> (byte * 8)) as u8).collect()
+    }
+
+    fn restore(&mut self, state: &[u8]) -> bool {
+        if state.len() != 8 {
+            return false;
+        }
+        self.state = state
+            .iter()
+            .rev()
+            .fold(0, |value, &byte| value << 8 | byte as u64);
+        true
+    }
+}
+
+/// Modelled on the COSMAC VIP interpreter's `CXNN`. The VIP kept a byte
+/// counter that its 60 Hz interrupt advanced, and on every `CXNN` it bumped
+/// the counter again, used it to index a page of memory and added the byte
+/// found there into an accumulator. That page held the interpreter itself,
+/// a copy of it is in `VIP_PAGE`.
+pub struct VipRandom {
+    counter: u8,
+    accumulator: u8,
+}
+
+/// Page `0x01` of the COSMAC VIP, the second half of its CHIP-8 interpreter.
+const VIP_PAGE: [u8; 256] = [
+    0x00, 0x00, 0x00, 0x00, 0x45, 0xA3, 0x98, 0x56, 0xD4, 0xF8, 0x81, 0xBC, 0xF8, 0x95, 0xAC, 0x22,
+    0xDC, 0x12, 0x56, 0xD4, 0x06, 0xB8, 0xD4, 0x06, 0xA8, 0xD4, 0x64, 0x0A, 0x01, 0xE6, 0x8A, 0xF4,
+    0xAA, 0x3B, 0x28, 0x9A, 0xFC, 0x01, 0xBA, 0xD4, 0xF8, 0x81, 0xBA, 0x06, 0xFA, 0x0F, 0xAA, 0x0A,
+    0xAA, 0xD4, 0xE6, 0x06, 0xBF, 0x93, 0xBE, 0xF8, 0x1B, 0xAE, 0x2A, 0x1A, 0xF8, 0x00, 0x5A, 0x0E,
+    0xF5, 0x3B, 0x4B, 0x56, 0x0A, 0xFC, 0x01, 0x5A, 0x30, 0x40, 0x4E, 0xF6, 0x3B, 0x3C, 0x9F, 0x56,
+    0x2A, 0x2A, 0xD4, 0x00, 0x22, 0x86, 0x52, 0xF8, 0xF0, 0xA7, 0x07, 0x5A, 0x87, 0xF3, 0x17, 0x1A,
+    0x3A, 0x5B, 0x12, 0xD4, 0x22, 0x86, 0x52, 0xF8, 0xF0, 0xA7, 0x0A, 0x57, 0x87, 0xF3, 0x17, 0x1A,
+    0x3A, 0x6B, 0x12, 0xD4, 0x15, 0x85, 0x22, 0x73, 0x95, 0x52, 0x25, 0x45, 0xA5, 0x86, 0xFA, 0x0F,
+    0xB5, 0xD4, 0x45, 0xE6, 0xF3, 0x3A, 0x82, 0x15, 0x15, 0xD4, 0x45, 0xE6, 0xF3, 0x3A, 0x88, 0xD4,
+    0x45, 0x07, 0x30, 0x8C, 0x45, 0x07, 0x30, 0x84, 0xE6, 0x62, 0x26, 0x45, 0xA3, 0x36, 0x88, 0xD4,
+    0x3E, 0x88, 0xD4, 0xF8, 0xF0, 0xA7, 0xE7, 0x45, 0xF4, 0xA5, 0x86, 0xFA, 0x0F, 0x3B, 0xB2, 0xFC,
+    0x01, 0xB5, 0xD4, 0x45, 0x56, 0xD4, 0x45, 0xE6, 0xF4, 0x56, 0xD4, 0x45, 0xFA, 0x0F, 0x3A, 0xC4,
+    0x07, 0x56, 0xD4, 0xAF, 0x22, 0xF8, 0xD3, 0x73, 0x8F, 0xF9, 0xF0, 0x52, 0xE6, 0x07, 0xD2, 0x56,
+    0xF8, 0xFF, 0xA6, 0xF8, 0x00, 0x7E, 0x56, 0xD4, 0x19, 0x89, 0xAE, 0x93, 0xBE, 0x99, 0xEE, 0xF4,
+    0x56, 0x76, 0xE6, 0xF4, 0xB9, 0x56, 0x45, 0xF2, 0x56, 0xD4, 0x45, 0xAA, 0x86, 0xFA, 0x0F, 0xBA,
+    0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xE0,
+];
+
+impl VipRandom {
+    pub fn new(seed: u64) -> VipRandom {
+        VipRandom {
+            counter: seed as u8,
+            accumulator: (seed >> 8) as u8,
+        }
+    }
+}
+
+impl RandomSource for VipRandom {
+    fn next_byte(&mut self) -> u8 {
+        self.counter = self.counter.wrapping_add(1);
+        let byte = VIP_PAGE[self.counter as usize];
+        self.accumulator = self.accumulator.wrapping_add(byte).wrapping_add(self.counter);
+        self.accumulator
+    }
+
+    fn frame(&mut self) {
+        self.counter = self.counter.wrapping_add(1);
+    }
+
+    fn save(&self) -> Vec<u8> {
+        vec![self.counter, self.accumulator]
+    }
+
+    fn restore(&mut self, state: &[u8]) -> bool {
+        if state.len() != 2 {
+            return false;
+        }
+        self.counter = state[0];
+        self.accumulator = state[1];
+        true
+    }
+}
+
+/// The generators that can be picked by name on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Generator {
+    Xorshift,
+    Vip,
+}
+
+/// The names accepted by `Generator::from_str`.
+pub const GENERATOR_NAMES: [&str; 2] = ["xorshift", "vip"];
+
+impl Generator {
+    /// The name `from_str` accepts for this generator.
+    pub fn name(self) -> &'static str {
+        match self {
+            Generator::Xorshift => "xorshift",
+            Generator::Vip => "vip",
+        }
+    }
+
+    pub fn create(self, seed: u64) -> Box<dyn RandomSource> {
+        match self {
+            Generator::Xorshift => Box::new(Xorshift::new(seed)),
+            Generator::Vip => Box::new(VipRandom::new(seed)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownGenerator(pub String);
+
+impl fmt::Display for UnknownGenerator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unknown random generator {:?}, expected one of {}",
+            self.0,
+            GENERATOR_NAMES.join(", ")
+        )
+    }
+}
+
+impl FromStr for Generator {
+    type Err = UnknownGenerator;
+
+    fn from_str(name: &str) -> Result<Generator, UnknownGenerator> {
+        match name {
+            "xorshift" => Ok(Generator::Xorshift),
+            "vip" => Ok(Generator::Vip),
+            _ => Err(UnknownGenerator(name.to_string())),
+        }
+    }
+}
@@ -0,0 +1,41 @@
+//! Reading programs for the command-line frontends, which run sources as
+//! readily as ROM images.
+
+use asm;
+use octo;
+use symbols::SymbolMap;
+
+use std::fs;
+use std::path::Path;
+
+pub struct Rom {
+    pub data: Vec<u8>,
+    /// The symbols of a program compiled from source.
+    pub symbols: Option<SymbolMap>,
+}
+
+/// Reads the program at `path`. Octo (`.8o`) and assembly (`.asm`) sources
+/// are compiled, anything else is taken to be a ROM image.
+pub fn read<P: AsRef<Path>>(path: P) -> Result<Rom, String> {
+    let path = path.as_ref();
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+    if extension != "8o" && extension != "asm" {
+        let data = fs::read(path).map_err(|err| err.to_string())?;
+        return Ok(Rom { data, symbols: None });
+    }
+
+    let source = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let program = if extension == "8o" {
+        octo::compile_source(&source, path).map_err(|err| err.to_string())?
+    } else {
+        asm::assemble_source(&source, path).map_err(|errors| {
+            let errors: Vec<String> = errors.iter().map(|err| err.to_string()).collect();
+            errors.join("\n")
+        })?
+    };
+    info!("Compiled {:?} into {} bytes", path, program.rom.len());
+    Ok(Rom {
+        data: program.rom,
+        symbols: Some(program.symbols),
+    })
+}
@@ -0,0 +1,146 @@
+//! Encoders that turn the display into files, for screenshots and golden
+//! image tests.
+
+use cpu::Cpu;
+
+use std::path::Path;
+
+/// The colours of a pixel by the XO-CHIP bitplanes lit in it.
+pub const PALETTE: [[u8; 3]; 4] = [[0, 0, 0], [255, 255, 255], [255, 102, 0], [153, 102, 0]];
+
+/// The characters `ascii` draws a pixel with, by the bitplanes lit in it.
+const ASCII: [char; 4] = ['.', '#', '+', '@'];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Png,
+    Pbm,
+    Ascii,
+}
+
+impl Format {
+    /// Picks the format from the extension of `path`: `.png`, `.pbm`, or
+    /// anything else for ASCII art.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Format {
+        match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+            Some("png") => Format::Png,
+            Some("pbm") => Format::Pbm,
+            _ => Format::Ascii,
+        }
+    }
+
+    pub fn encode(self, cpu: &Cpu) -> Vec<u8> {
+        match self {
+            Format::Png => png(cpu),
+            Format::Pbm => pbm(cpu).into_bytes(),
+            Format::Ascii => ascii(cpu).into_bytes(),
+        }
+    }
+}
+
+/// One line of text per row, see `ASCII` for the characters.
+pub fn ascii(cpu: &Cpu) -> String {
+    let mut out = String::new();
+    for y in 0..cpu.screen_height() {
+        for x in 0..cpu.screen_width() {
+            out.push(ASCII[cpu.pixel(x, y) as usize & 0b11]);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// A plain (`P1`) portable bitmap. Pixels lit in any plane are black.
+pub fn pbm(cpu: &Cpu) -> String {
+    let mut out = format!("P1\n{} {}\n", cpu.screen_width(), cpu.screen_height());
+    for y in 0..cpu.screen_height() {
+        let row: Vec<&str> = (0..cpu.screen_width())
+            .map(|x| if cpu.pixel(x, y) != 0 { "1" } else { "0" })
+            .collect();
+        out.push_str(&row.join(" "));
+        out.push('\n');
+    }
+    out
+}
+
+/// An 8-bit indexed PNG using `PALETTE`, one image pixel per CHIP-8 pixel.
+/// The image data is stored uncompressed, which keeps the encoder tiny and
+/// the files are small anyway.
+pub fn png(cpu: &Cpu) -> Vec<u8> {
+    let (width, height) = (cpu.screen_width(), cpu.screen_height());
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per pixel, indexed colour, default compression, filter and no
+    // interlacing.
+    header.extend_from_slice(&[8, 3, 0, 0, 0]);
+
+    let palette: Vec<u8> = PALETTE.iter().flat_map(|rgb| rgb.iter().cloned()).collect();
+
+    let mut pixels = Vec::with_capacity((width + 1) * height);
+    for y in 0..height {
+        // Every scanline starts with its filter type, 0 for none.
+        pixels.push(0);
+        pixels.extend((0..width).map(|x| cpu.pixel(x, y) & 0b11));
+    }
+
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+    chunk(&mut out, b"IHDR", &header);
+    chunk(&mut out, b"PLTE", &palette);
+    chunk(&mut out, b"IDAT", &zlib_stored(&pixels));
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Wraps `data` in a zlib stream of uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = data.chunks(0xFFFF).collect();
+    for (n, block) in blocks.iter().enumerate() {
+        let last = n + 1 == blocks.len();
+        out.push(last as u8);
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    if blocks.is_empty() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
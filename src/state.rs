@@ -0,0 +1,161 @@
+//! The binary save-state format.
+//!
+//! A state starts with the `MAGIC` bytes and a little-endian `u16` version,
+//! followed by the machine in the order `Cpu::save_state` writes it. All
+//! multi-byte values are little-endian.
+
+use std::error;
+use std::fmt;
+
+pub const MAGIC: &[u8; 4] = b"C8ST";
+
+/// Bumped whenever the layout changes. Older versions are rejected.
+pub const VERSION: u16 = 1;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StateError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    Invalid(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "not a CHIP-8 save state"),
+            StateError::UnsupportedVersion(version) => write!(
+                f,
+                "save state version {} is not supported, expected {}",
+                version, VERSION
+            ),
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::Invalid(what) => write!(f, "save state has an invalid {}", what),
+        }
+    }
+}
+
+impl error::Error for StateError {}
+
+pub(crate) struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new() -> Writer {
+        let mut writer = Writer { data: Vec::new() };
+        writer.bytes(MAGIC);
+        writer.u16(VERSION);
+        writer
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes(&[value as u8, (value >> 8) as u8]);
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.u16(value as u16);
+        self.u16((value >> 16) as u16);
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.u32(value as u32);
+        self.u32((value >> 32) as u32);
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    /// Writes `bytes` prefixed with their length.
+    pub fn blob(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.bytes(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// Checks the header of `data` and returns a reader positioned after it.
+    pub fn new(data: &'a [u8]) -> Result<Reader<'a>, StateError> {
+        let mut reader = Reader { data };
+        if reader.bytes(MAGIC.len()).map_err(|_| StateError::BadMagic)? != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        Ok(reader)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Invalid("flag")),
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        let bytes = self.bytes(2)?;
+        Ok(bytes[0] as u16 | (bytes[1] as u16) << 8)
+    }
+
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(self.u16()? as u32 | (self.u16()? as u32) << 16)
+    }
+
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(self.u32()? as u64 | (self.u32()? as u64) << 32)
+    }
+
+    pub fn bytes(&mut self, n: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < n {
+            return Err(StateError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    /// Reads exactly `into.len()` bytes into `into`.
+    pub fn fill(&mut self, into: &mut [u8]) -> Result<(), StateError> {
+        into.copy_from_slice(self.bytes(into.len())?);
+        Ok(())
+    }
+
+    /// Reads bytes written by `Writer::blob`.
+    pub fn blob(&mut self) -> Result<&'a [u8], StateError> {
+        let n = self.u32()? as usize;
+        self.bytes(n)
+    }
+
+    /// Fails unless everything has been read.
+    pub fn finish(self) -> Result<(), StateError> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(StateError::Invalid("length"))
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Symbol maps tie the addresses of an assembled program back to its source:
+//! the address of every label and the source line every instruction came
+//! from. Debugging tools use them to show names instead of numbers.
+//!
+//! They are stored as plain text:
+//!
+//! ```text
+//! chip8-symbols 1
+//! label start 0x0200
+//! line 0x0200 3 game.asm
+//! ```
+//!
+//! A `line` entry is the address, the line number and the rest of the line
+//! is the file name.
+
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+
+const HEADER: &str = "chip8-symbols 1";
+
+/// A line in a source file.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Location {
+    pub file: String,
+    pub line: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolMap {
+    pub labels: BTreeMap<String, u16>,
+    /// The source line of the instruction at each address.
+    pub lines: BTreeMap<u16, Location>,
+}
+
+#[derive(Debug)]
+pub struct SymbolError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for SymbolError {}
+
+impl SymbolMap {
+    /// The first label at `address` in alphabetical order, if any.
+    pub fn label_at(&self, address: u16) -> Option<&str> {
+        self.labels
+            .iter()
+            .find(|&(_, &label)| label == address)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// The closest label at or before `address`, which is usually the
+    /// subroutine it is in.
+    pub fn routine(&self, address: u16) -> Option<(&str, u16)> {
+        self.labels
+            .iter()
+            .filter(|&(_, &label)| label <= address)
+            .max_by_key(|&(_, &label)| label)
+            .map(|(name, &label)| (name.as_str(), label))
+    }
+
+    /// The source line of the instruction at `address`.
+    pub fn location(&self, address: u16) -> Option<&Location> {
+        self.lines.get(&address)
+    }
+
+    pub fn parse(text: &str) -> Result<SymbolMap, SymbolError> {
+        let mut lines = text.lines().enumerate().map(|(n, line)| (n + 1, line));
+        let error = |line, message: &str| SymbolError {
+            line,
+            message: message.to_string(),
+        };
+
+        match lines.next() {
+            Some((_, header)) if header.trim() == HEADER => (),
+            _ => return Err(error(1, "not a symbol map")),
+        }
+
+        let mut map = SymbolMap::default();
+        for (n, line) in lines {
+            let fields: Vec<&str> = line.trim().splitn(4, ' ').collect();
+            match fields.as_slice() {
+                [] | [""] => (),
+                ["label", name, address] => {
+                    let address = parse_address(address).ok_or_else(|| error(n, "invalid address"))?;
+                    map.labels.insert(name.to_string(), address);
+                }
+                ["line", address, line, file] => {
+                    let address = parse_address(address).ok_or_else(|| error(n, "invalid address"))?;
+                    let line = line.parse().map_err(|_| error(n, "invalid line number"))?;
+                    map.lines.insert(
+                        address,
+                        Location {
+                            file: file.to_string(),
+                            line,
+                        },
+                    );
+                }
+                _ => return Err(error(n, "expected a label or line entry")),
+            }
+        }
+        Ok(map)
+    }
+}
+
+fn parse_address(text: &str) -> Option<u16> {
+    u16::from_str_radix(text.trim_start_matches("0x"), 16).ok()
+}
+
+impl fmt::Display for SymbolMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        for (name, address) in &self.labels {
+            writeln!(f, "label {} {:#06X}", name, address)?;
+        }
+        for (address, location) in &self.lines {
+            writeln!(f, "line {:#06X} {} {}", address, location.line, location.file)?;
+        }
+        Ok(())
+    }
+}
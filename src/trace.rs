@@ -0,0 +1,426 @@
+//! Instruction traces: a record of the machine before every step, written as
+//! JSON lines or in a compact binary format, and a diff that finds the first
+//! step where two traces disagree.
+//!
+//! A JSON record looks like this, with the instruction only for reading:
+//!
+//! ```text
+//! {"cycle":0,"pc":512,"opcode":"6005","instruction":"LD V[0x00], 0x05","v":[0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],"i":0,"sp":0,"dt":0,"st":0}
+//! ```
+//!
+//! A binary trace starts with the `MAGIC` bytes and a little-endian `u16`
+//! version. Every record is the cycle as a `u64`, PC as a `u16`, the two or
+//! four bytes of the instruction, V0-VF, I as a `u16`, SP, DT and ST, all
+//! little-endian.
+
+use cpu::Cpu;
+use instruction::Instruction;
+use json::{object, Value};
+
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+pub const MAGIC: &[u8; 4] = b"C8TR";
+
+pub const VERSION: u16 = 1;
+
+/// The machine before an instruction executes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record {
+    /// The number of instructions executed before this one.
+    pub cycle: u64,
+    pub pc: u16,
+    /// The bytes of the instruction. Only `F000 NNNN` uses all four, the
+    /// others leave the last two zero.
+    pub opcode: [u8; 4],
+    pub registers: [u8; 16],
+    pub i: u16,
+    pub sp: u8,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+impl Record {
+    pub fn new(cycle: u64, cpu: &Cpu) -> Record {
+        let instruction = Instruction::decode_at(cpu.memory(), cpu.pc());
+        let mut opcode = [0; 4];
+        for (offset, byte) in opcode.iter_mut().take(instruction.size() as usize).enumerate() {
+            *byte = cpu.memory()[cpu.pc().wrapping_add(offset as u16) as usize % cpu.memory().len()];
+        }
+        Record {
+            cycle,
+            pc: cpu.pc(),
+            opcode,
+            registers: *cpu.registers(),
+            i: cpu.i(),
+            sp: cpu.sp() as u8,
+            delay_timer: cpu.delay_timer(),
+            sound_timer: cpu.sound_timer(),
+        }
+    }
+
+    pub fn instruction(&self) -> Instruction {
+        Instruction::decode_at(&self.opcode, 0)
+    }
+
+    /// The bytes of the instruction that are used.
+    pub fn opcode_bytes(&self) -> &[u8] {
+        &self.opcode[..self.instruction().size() as usize]
+    }
+
+    pub fn to_json(&self) -> Value {
+        let opcode: String = self.opcode_bytes().iter().map(|byte| format!("{:02X}", byte)).collect();
+        let registers: Vec<Value> = self.registers.iter().map(|&value| value.into()).collect();
+        object(vec![
+            ("cycle", self.cycle.into()),
+            ("pc", self.pc.into()),
+            ("opcode", opcode.into()),
+            ("instruction", format!("{:?}", self.instruction()).into()),
+            ("v", registers.into()),
+            ("i", self.i.into()),
+            ("sp", self.sp.into()),
+            ("dt", self.delay_timer.into()),
+            ("st", self.sound_timer.into()),
+        ])
+    }
+
+    /// Reads a JSON record. The instruction is ignored, it follows from the
+    /// opcode, and a missing cycle is taken to be `cycle`.
+    pub fn from_json(value: &Value, cycle: u64) -> Result<Record, String> {
+        let number = |name: &str, max: u64| match value[name].as_u64() {
+            Some(number) if number <= max => Ok(number),
+            Some(_) => Err(format!("{} is out of range", name)),
+            None => Err(format!("{} is missing or not a number", name)),
+        };
+
+        let opcode_text = value["opcode"].as_str().ok_or("opcode is missing or not a string")?;
+        let mut opcode = [0; 4];
+        if opcode_text.len() != 4 && opcode_text.len() != 8 {
+            return Err("opcode must be 2 or 4 bytes of hex".to_string());
+        }
+        for (n, byte) in opcode.iter_mut().take(opcode_text.len() / 2).enumerate() {
+            *byte = opcode_text
+                .get(2 * n..2 * n + 2)
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .ok_or("opcode is not hex")?;
+        }
+
+        let mut registers = [0; 16];
+        let values = value["v"].as_array().ok_or("v is missing or not an array")?;
+        if values.len() != 16 {
+            return Err("v must have 16 registers".to_string());
+        }
+        for (register, value) in registers.iter_mut().zip(values) {
+            *register = match value.as_u64() {
+                Some(number) if number <= 0xFF => number as u8,
+                _ => return Err("v must hold bytes".to_string()),
+            };
+        }
+
+        Ok(Record {
+            cycle: match value.get("cycle") {
+                Some(_) => number("cycle", u64::MAX)?,
+                None => cycle,
+            },
+            pc: number("pc", 0xFFFF)? as u16,
+            opcode,
+            registers,
+            i: number("i", 0xFFFF)? as u16,
+            sp: number("sp", 0xFF)? as u8,
+            delay_timer: number("dt", 0xFF)? as u8,
+            sound_timer: number("st", 0xFF)? as u8,
+        })
+    }
+
+    fn write_binary(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.cycle.to_le_bytes());
+        out.extend_from_slice(&self.pc.to_le_bytes());
+        out.extend_from_slice(self.opcode_bytes());
+        out.extend_from_slice(&self.registers);
+        out.extend_from_slice(&self.i.to_le_bytes());
+        out.extend_from_slice(&[self.sp, self.delay_timer, self.sound_timer]);
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let opcode: String = self.opcode_bytes().iter().map(|byte| format!("{:02X}", byte)).collect();
+        write!(
+            f,
+            "cycle {} PC={:04X} {:<8} {:<20} I={:04X} SP={} DT={:02X} ST={:02X} V=",
+            self.cycle,
+            self.pc,
+            opcode,
+            format!("{:?}", self.instruction()),
+            self.i,
+            self.sp,
+            self.delay_timer,
+            self.sound_timer
+        )?;
+        for value in &self.registers {
+            write!(f, "{:02X}", value)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Binary,
+}
+
+impl Format {
+    /// JSON lines for `.jsonl` and `.json` files, binary otherwise.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Format {
+        match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+            Some("jsonl") | Some("json") => Format::Json,
+            _ => Format::Binary,
+        }
+    }
+}
+
+/// Writes a record for every step of a `Cpu` it is attached to with
+/// `Cpu::set_tracer`.
+pub struct Tracer {
+    out: Box<dyn Write>,
+    format: Format,
+    cycle: u64,
+    buffer: Vec<u8>,
+}
+
+impl Tracer {
+    pub fn new(mut out: Box<dyn Write>, format: Format) -> io::Result<Tracer> {
+        if format == Format::Binary {
+            out.write_all(MAGIC)?;
+            out.write_all(&VERSION.to_le_bytes())?;
+        }
+        Ok(Tracer {
+            out,
+            format,
+            cycle: 0,
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Creates `path` and traces into it in the format its extension asks
+    /// for.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Tracer> {
+        let file = File::create(&path)?;
+        Tracer::new(Box::new(BufWriter::new(file)), Format::from_path(path))
+    }
+
+    /// Records the machine before it executes its next instruction.
+    pub fn record(&mut self, cpu: &Cpu) -> io::Result<()> {
+        let record = Record::new(self.cycle, cpu);
+        self.cycle += 1;
+        self.buffer.clear();
+        match self.format {
+            Format::Json => {
+                self.buffer.extend_from_slice(record.to_json().to_string().as_bytes());
+                self.buffer.push(b'\n');
+            }
+            Format::Binary => record.write_binary(&mut self.buffer),
+        }
+        self.out.write_all(&self.buffer)
+    }
+
+    /// Flushes the records written so far.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[derive(Debug)]
+pub struct TraceError {
+    /// The index of the record that could not be read.
+    pub record: usize,
+    pub message: String,
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "record {}: {}", self.record, self.message)
+    }
+}
+
+impl error::Error for TraceError {}
+
+/// Reads a trace in either format, telling them apart by the magic bytes.
+pub fn read(data: &[u8]) -> Result<Vec<Record>, TraceError> {
+    if data.starts_with(MAGIC) {
+        read_binary(&data[MAGIC.len()..])
+    } else {
+        read_json(data)
+    }
+}
+
+fn read_json(data: &[u8]) -> Result<Vec<Record>, TraceError> {
+    let text = String::from_utf8_lossy(data);
+    let mut records = Vec::new();
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        let error = |message: String| TraceError {
+            record: records.len(),
+            message,
+        };
+        let value = Value::parse(line).map_err(|err| error(err.to_string()))?;
+        let record = Record::from_json(&value, records.len() as u64).map_err(error)?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Splits the first `n` bytes off `data`.
+fn take<'a>(data: &mut &'a [u8], n: usize, record: usize) -> Result<&'a [u8], TraceError> {
+    if data.len() < n {
+        return Err(TraceError {
+            record,
+            message: "truncated".to_string(),
+        });
+    }
+    let (bytes, rest) = data.split_at(n);
+    *data = rest;
+    Ok(bytes)
+}
+
+fn read_binary(mut data: &[u8]) -> Result<Vec<Record>, TraceError> {
+    let mut records = Vec::new();
+    if take(&mut data, 2, 0)? != VERSION.to_le_bytes() {
+        return Err(TraceError {
+            record: 0,
+            message: format!("unsupported version, expected {}", VERSION),
+        });
+    }
+    while !data.is_empty() {
+        let n = records.len();
+        let mut cycle = [0; 8];
+        cycle.copy_from_slice(take(&mut data, 8, n)?);
+        let pc = take(&mut data, 2, n)?;
+        let pc = pc[0] as u16 | (pc[1] as u16) << 8;
+
+        let mut opcode = [0; 4];
+        opcode[..2].copy_from_slice(take(&mut data, 2, n)?);
+        if opcode[..2] == [0xF0, 0x00] {
+            opcode[2..].copy_from_slice(take(&mut data, 2, n)?);
+        }
+
+        let mut registers = [0; 16];
+        registers.copy_from_slice(take(&mut data, 16, n)?);
+        let rest = take(&mut data, 5, n)?;
+        records.push(Record {
+            cycle: u64::from_le_bytes(cycle),
+            pc,
+            opcode,
+            registers,
+            i: rest[0] as u16 | (rest[1] as u16) << 8,
+            sp: rest[2],
+            delay_timer: rest[3],
+            sound_timer: rest[4],
+        });
+    }
+    Ok(records)
+}
+
+/// Where two traces first disagree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// The index of the first record that differs.
+    pub index: usize,
+    /// The names of the fields that differ, empty when one trace ended.
+    pub fields: Vec<&'static str>,
+    pub left: Option<Record>,
+    pub right: Option<Record>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.left, &self.right) {
+            (Some(left), Some(right)) => {
+                writeln!(f, "the traces diverge at record {}: {}", self.index, self.fields.join(", "))?;
+                writeln!(f, "< {}", left)?;
+                write!(f, "> {}", right)
+            }
+            (Some(left), None) => {
+                writeln!(f, "the second trace ends at record {}, the first goes on with", self.index)?;
+                write!(f, "< {}", left)
+            }
+            (None, Some(right)) => {
+                writeln!(f, "the first trace ends at record {}, the second goes on with", self.index)?;
+                write!(f, "> {}", right)
+            }
+            (None, None) => write!(f, "the traces are the same"),
+        }
+    }
+}
+
+/// The fields of two records that differ. The cycle is left out, emulators
+/// count from different points.
+fn differences(left: &Record, right: &Record) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    if left.pc != right.pc {
+        fields.push("pc");
+    }
+    if left.opcode_bytes() != right.opcode_bytes() {
+        fields.push("opcode");
+    }
+    for (x, name) in ["v0", "v1", "v2", "v3", "v4", "v5", "v6", "v7", "v8", "v9", "va", "vb", "vc", "vd", "ve", "vf"]
+        .iter()
+        .enumerate()
+    {
+        if left.registers[x] != right.registers[x] {
+            fields.push(name);
+        }
+    }
+    if left.i != right.i {
+        fields.push("i");
+    }
+    if left.sp != right.sp {
+        fields.push("sp");
+    }
+    if left.delay_timer != right.delay_timer {
+        fields.push("dt");
+    }
+    if left.sound_timer != right.sound_timer {
+        fields.push("st");
+    }
+    fields
+}
+
+/// Finds the first record where `left` and `right` differ, or where one of
+/// them ends before the other.
+pub fn diff<L, R>(left: L, right: R) -> Option<Divergence>
+where
+    L: IntoIterator<Item = Record>,
+    R: IntoIterator<Item = Record>,
+{
+    let (mut left, mut right) = (left.into_iter(), right.into_iter());
+    let mut index = 0;
+    loop {
+        match (left.next(), right.next()) {
+            (None, None) => return None,
+            (Some(l), Some(r)) => {
+                let fields = differences(&l, &r);
+                if !fields.is_empty() {
+                    return Some(Divergence {
+                        index,
+                        fields,
+                        left: Some(l),
+                        right: Some(r),
+                    });
+                }
+            }
+            (l, r) => {
+                return Some(Divergence {
+                    index,
+                    fields: Vec::new(),
+                    left: l,
+                    right: r,
+                })
+            }
+        }
+        index += 1;
+    }
+}
@@ -0,0 +1,69 @@
+extern crate chip8;
+
+use chip8::asm::{self, AsmError};
+use chip8::Instruction;
+
+fn assemble(source: &str) -> Vec<u8> {
+    match asm::assemble(source) {
+        Ok(program) => program.rom,
+        Err(errors) => panic!("{:?}", errors),
+    }
+}
+
+fn errors(source: &str) -> Vec<AsmError> {
+    match asm::assemble(source) {
+        Ok(_) => panic!("{:?} assembled", source),
+        Err(errors) => errors,
+    }
+}
+
+#[test]
+fn constants() {
+    let source = "SPEED = 2\nSIZE=3\nSTEP equ SPEED + SIZE\nLD V0, STEP\nADD V1, SPEED";
+    assert_eq!(assemble(source), [0x60, 0x05, 0x71, 0x02]);
+}
+
+#[test]
+fn equals_signs_in_operands_are_not_constants() {
+    assert_eq!(assemble("db \"a=b\""), b"a=b");
+    assert_eq!(assemble("x: db \"=\", 1"), [b'=', 1]);
+}
+
+#[test]
+fn invalid_constant_names_are_errors() {
+    let errors = errors("= 3\n1x = 3");
+    assert_eq!(errors.len(), 2);
+    assert!(errors[0].message.contains("invalid constant name"), "{}", errors[0]);
+    assert!(errors[1].message.contains("invalid constant name"), "{}", errors[1]);
+}
+
+#[test]
+fn every_mnemonic_assembles_to_the_bytes_it_was_disassembled_from() {
+    for opcode in 0..=0xFFFFu16 {
+        let memory = [(opcode >> 8) as u8, opcode as u8, 0x12, 0x34];
+        let instruction = Instruction::decode_at(&memory, 0);
+        if let Instruction::Illegal(_) = instruction {
+            continue;
+        }
+        let size = instruction.size() as usize;
+        assert_eq!(assemble(&format!("{:?}", instruction)), &memory[..size], "{:?}", instruction);
+    }
+}
+
+#[test]
+fn errors_name_their_line() {
+    let source = "start:\n    LD V0, 1\n    FOO V0\n\n    JP nowhere\n    LD V0, 0x100\nstart:\n    db";
+    let errors = errors(source);
+    let lines: Vec<usize> = errors.iter().map(|error| error.location.line).collect();
+    assert_eq!(lines, [3, 5, 6, 7, 8], "{:?}", errors);
+    assert!(errors.iter().all(|error| error.location.file == "<input>"));
+    assert_eq!(errors[0].to_string(), format!("<input>:3: {}", errors[0].message));
+}
+
+#[test]
+fn arithmetic_wraps_instead_of_overflowing() {
+    assert_eq!(assemble("LD V0, ((1 << 63) / -1) & 0xFF"), [0x60, 0x00]);
+    assert_eq!(assemble("LD V0, ((1 << 63) % -1) + 7"), [0x60, 0x07]);
+    assert_eq!(assemble("LD V0, ((1 << 63) * 2) + 1"), [0x60, 0x01]);
+    assert!(errors("LD V0, 1 / 0")[0].message.contains("division by zero"));
+}
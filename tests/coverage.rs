@@ -0,0 +1,106 @@
+extern crate chip8;
+
+use chip8::coverage::{Branch, Coverage, EXECUTED, READ, WRITTEN};
+use chip8::symbols::{Location, SymbolMap};
+use chip8::Cpu;
+
+/// main: V0 = 2, I = sprite, draw it; loop: V0 -= 1 until it is 0; store V0
+/// at 0x300, halt; sprite: 0x80
+const ROM: [u8; 19] = [
+    0x60, 0x02, 0xA2, 0x12, 0xD0, 0x01, 0x70, 0xFF, 0x30, 0x00, 0x12, 0x06, 0xA3, 0x00, 0xF0, 0x55,
+    0x12, 0x10, 0x80,
+];
+/// Runs into the halt twice.
+const STEPS: usize = 12;
+
+fn covered() -> Coverage {
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&ROM);
+    cpu.set_coverage(Coverage::new());
+    for _ in 0..STEPS {
+        cpu.step().unwrap();
+    }
+    cpu.take_coverage().unwrap()
+}
+
+/// Each instruction of `ROM` on its own line of game.8o.
+fn symbols() -> SymbolMap {
+    let mut symbols = SymbolMap::default();
+    symbols.labels.insert("main".to_string(), 0x200);
+    symbols.labels.insert("loop".to_string(), 0x206);
+    for line in 1..10 {
+        let location = Location {
+            file: "game.8o".to_string(),
+            line,
+        };
+        symbols.lines.insert(0x200 + 2 * (line as u16 - 1), location);
+    }
+    symbols
+}
+
+#[test]
+fn flags_and_counts() {
+    let coverage = covered();
+    assert_eq!(coverage.flags(0x200), EXECUTED);
+    assert_eq!(coverage.flags(0x201), EXECUTED);
+    assert_eq!(coverage.flags(0x212), READ);
+    assert_eq!(coverage.flags(0x213), 0);
+    assert_eq!(coverage.flags(0x300), WRITTEN);
+
+    assert_eq!(coverage.executions(0x200), 1);
+    assert_eq!(coverage.executions(0x206), 2);
+    assert_eq!(coverage.executions(0x210), 2);
+    assert_eq!(coverage.executions(0x212), 0);
+}
+
+#[test]
+fn skips_count_both_ways() {
+    let coverage = covered();
+    let branch = Branch {
+        taken: 1,
+        not_taken: 1,
+    };
+    assert_eq!(coverage.branch_at(0x208), Some(branch));
+    assert_eq!(coverage.branch_at(0x206), None);
+
+    let listing = coverage.disassembly(&ROM, None);
+    assert!(listing.contains("; 2 of 2 skip outcomes taken"), "{}", listing);
+    assert!(listing.contains("; skipped 1, fell through 1"), "{}", listing);
+}
+
+#[test]
+fn lcov_lines_functions_and_branches() {
+    let lcov = covered().lcov(&ROM, &symbols());
+    let lines: Vec<&str> = lcov.lines().collect();
+    assert_eq!(lines[..2], ["TN:", "SF:game.8o"]);
+    for line in &[
+        "FN:1,main",
+        "FN:4,loop",
+        "FNDA:1,main",
+        "FNDA:2,loop",
+        "FNF:2",
+        "FNH:2",
+        "BRDA:5,0,0,1",
+        "BRDA:5,0,1,1",
+        "BRF:2",
+        "BRH:2",
+        "DA:1,1",
+        "DA:4,2",
+        "DA:5,2",
+        "DA:6,1",
+        "DA:9,2",
+        "LF:9",
+        "LH:9",
+    ] {
+        assert!(lines.contains(line), "no {} in\n{}", line, lcov);
+    }
+    assert_eq!(lines.last(), Some(&"end_of_record"));
+}
+
+#[test]
+fn labels_inside_instructions_are_listed() {
+    // I = 0x205, the operand of the second instruction; V0 = 0; V1 = 7; loop
+    let rom = [0xA2, 0x05, 0x60, 0x00, 0x61, 0x07, 0x12, 0x06];
+    let listing = Coverage::new().disassembly(&rom, None);
+    assert!(listing.contains("\ndata_0205 = 0x0205\n"), "{}", listing);
+}
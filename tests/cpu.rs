@@ -0,0 +1,247 @@
+extern crate chip8;
+
+use chip8::{Cpu, Error};
+
+/// Loads `program` at 0x200 and executes it up to its end.
+fn run(program: &[u8]) -> Cpu {
+    let mut cpu = Cpu::new();
+    cpu.load_rom(program);
+    while (cpu.pc() as usize) < 0x200 + program.len() {
+        cpu.step().unwrap();
+    }
+    cpu
+}
+
+/// Loads `program` at 0x200 to be stepped through by the test.
+fn load(program: &[u8]) -> Cpu {
+    let mut cpu = Cpu::new();
+    cpu.load_rom(program);
+    cpu
+}
+
+fn step(cpu: &mut Cpu, steps: usize) {
+    for _ in 0..steps {
+        cpu.step().unwrap();
+    }
+}
+
+#[test]
+fn add_writes_the_carry_after_the_sum() {
+    // VF = 0x01, V0 = 0xFF, VF += V0
+    let cpu = run(&[0x6F, 0x01, 0x60, 0xFF, 0x8F, 0x04]);
+    assert_eq!(cpu.registers()[0xF], 1);
+
+    // V0 = 0x80, V1 = 0x7F, V0 += V1
+    let cpu = run(&[0x60, 0x80, 0x61, 0x7F, 0x80, 0x14]);
+    assert_eq!(cpu.registers()[0], 0xFF);
+    assert_eq!(cpu.registers()[0xF], 0);
+}
+
+#[test]
+fn sub_writes_the_flag_after_the_difference() {
+    // VF = 0x03, V0 = 0x05, VF -= V0
+    let cpu = run(&[0x6F, 0x03, 0x60, 0x05, 0x8F, 0x05]);
+    assert_eq!(cpu.registers()[0xF], 0);
+
+    // VF = 0x03, V0 = 0x05, VF = V0 - VF
+    let cpu = run(&[0x6F, 0x03, 0x60, 0x05, 0x8F, 0x07]);
+    assert_eq!(cpu.registers()[0xF], 1);
+}
+
+#[test]
+fn sub_of_equal_operands_does_not_borrow() {
+    // V0 = V1 = 0x05, V0 -= V1
+    let cpu = run(&[0x60, 0x05, 0x61, 0x05, 0x80, 0x15]);
+    assert_eq!(cpu.registers()[0], 0);
+    assert_eq!(cpu.registers()[0xF], 1);
+
+    // V0 = V1 = 0x05, V0 = V1 - V0
+    let cpu = run(&[0x60, 0x05, 0x61, 0x05, 0x80, 0x17]);
+    assert_eq!(cpu.registers()[0], 0);
+    assert_eq!(cpu.registers()[0xF], 1);
+}
+
+#[test]
+fn add_address_wraps_around_the_address_space() {
+    // I = 0xFFFF, V0 = 0x02, I += V0
+    let cpu = run(&[0xF0, 0x00, 0xFF, 0xFF, 0x60, 0x02, 0xF0, 0x1E]);
+    assert_eq!(cpu.i(), 0x0001);
+}
+
+#[test]
+fn high_resolution_switches_the_screen_size() {
+    let cpu = run(&[0x00, 0xFF]);
+    assert_eq!((cpu.screen_width(), cpu.screen_height()), (128, 64));
+
+    let cpu = run(&[0x00, 0xFF, 0x00, 0xFE]);
+    assert_eq!((cpu.screen_width(), cpu.screen_height()), (64, 32));
+}
+
+#[test]
+fn scrolls_move_the_screen() {
+    // I = 0x20C, draw a pixel at 0, 0, scroll down 2, right, left, loop
+    let mut cpu = load(&[0xA2, 0x0C, 0xD0, 0x01, 0x00, 0xC2, 0x00, 0xFB, 0x00, 0xFC, 0x12, 0x0A, 0x80]);
+    step(&mut cpu, 3);
+    assert_eq!(cpu.pixel(0, 0), 0);
+    assert_eq!(cpu.pixel(0, 2), 1);
+    step(&mut cpu, 1);
+    assert_eq!(cpu.pixel(0, 2), 0);
+    assert_eq!(cpu.pixel(4, 2), 1);
+    step(&mut cpu, 1);
+    assert_eq!(cpu.pixel(4, 2), 0);
+    assert_eq!(cpu.pixel(0, 2), 1);
+}
+
+#[test]
+fn draw_of_height_zero_is_a_16x16_sprite() {
+    // high resolution, I = 0x20A, draw the 16x16 sprite at 0, 0 twice, loop
+    let mut program = vec![0x00, 0xFF, 0xA2, 0x0A, 0xD0, 0x00, 0xD0, 0x00, 0x12, 0x08];
+    program.extend_from_slice(&[0xFF; 32]);
+    let mut cpu = load(&program);
+    step(&mut cpu, 3);
+    assert_eq!(cpu.pixel(15, 15), 1);
+    assert_eq!(cpu.pixel(16, 0), 0);
+    assert_eq!(cpu.pixel(0, 16), 0);
+    assert_eq!(cpu.registers()[0xF], 0);
+
+    step(&mut cpu, 1);
+    assert_eq!(cpu.pixel(15, 15), 0);
+    assert_eq!(cpu.registers()[0xF], 1);
+}
+
+#[test]
+fn flags_save_and_restore_registers() {
+    // V0 = 1, V1 = 2, V2 = 3, save V0-V2, clear them, load V0-V2
+    let cpu = run(&[
+        0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0xF2, 0x75, 0x60, 0x00, 0x61, 0x00, 0x62, 0x00, 0xF2, 0x85,
+    ]);
+    assert_eq!(cpu.registers()[..3], [1, 2, 3]);
+}
+
+#[test]
+fn skips_step_over_the_whole_long_load() {
+    // V0 = 0, skip if V0 == 0, I = 0x1234, V1 = 1
+    let mut cpu = load(&[0x60, 0x00, 0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x61, 0x01]);
+    step(&mut cpu, 2);
+    assert_eq!(cpu.pc(), 0x208);
+    step(&mut cpu, 1);
+    assert_eq!(cpu.i(), 0);
+    assert_eq!(cpu.registers()[1], 1);
+}
+
+#[test]
+fn register_ranges_save_and_load_in_either_order() {
+    // V1 = 1, V2 = 2, V3 = 3, I = 0x300, save V1-V3, load V3-V1
+    let cpu = run(&[
+        0x61, 0x01, 0x62, 0x02, 0x63, 0x03, 0xA3, 0x00, 0x51, 0x32, 0x53, 0x13,
+    ]);
+    assert_eq!(cpu.memory()[0x300..0x303], [1, 2, 3]);
+    assert_eq!(cpu.registers()[1..4], [3, 2, 1]);
+    assert_eq!(cpu.i(), 0x300);
+}
+
+#[test]
+fn draw_and_clear_use_the_selected_planes() {
+    // I = 0x212, plane 2, draw, clear, both planes, draw, plane 1, clear, loop
+    let mut cpu = load(&[
+        0xA2, 0x12, 0xF2, 0x01, 0xD0, 0x01, 0xF3, 0x01, 0xD0, 0x01, 0xF1, 0x01, 0x00, 0xE0, 0x12, 0x0E,
+        0x00, 0x00, 0x80, 0x80,
+    ]);
+    step(&mut cpu, 3);
+    assert_eq!(cpu.pixel(0, 0), 0b10);
+    step(&mut cpu, 2);
+    // The second plane's row follows the first's and turns its pixel off.
+    assert_eq!(cpu.pixel(0, 0), 0b01);
+    step(&mut cpu, 2);
+    assert_eq!(cpu.pixel(0, 0), 0);
+}
+
+#[test]
+fn audio_pattern_and_pitch_are_loaded() {
+    // I = 0x208, load the pattern, V0 = 0x70, pitch = V0, pattern
+    let mut program = vec![0xA2, 0x08, 0xF0, 0x02, 0x60, 0x70, 0xF0, 0x3A];
+    program.extend(0..16);
+    let mut cpu = load(&program);
+    step(&mut cpu, 4);
+    let pattern: Vec<u8> = (0..16).collect();
+    assert_eq!(cpu.audio_pattern()[..], pattern[..]);
+    assert_eq!(cpu.pitch(), 0x70);
+}
+
+#[test]
+fn stack_errors_leave_the_machine_unchanged() {
+    // return
+    let mut cpu = load(&[0x00, 0xEE]);
+    assert!(matches!(cpu.step(), Err(Error::StackUnderflow)));
+    assert_eq!((cpu.pc(), cpu.sp()), (0x200, 0));
+
+    // call itself
+    let mut cpu = load(&[0x22, 0x00]);
+    step(&mut cpu, 16);
+    assert!(matches!(cpu.step(), Err(Error::StackOverflow)));
+    assert_eq!((cpu.pc(), cpu.sp()), (0x200, 16));
+}
+
+#[test]
+fn run_frame_counts_the_instructions_it_ran() {
+    // V0 += 1, loop
+    let mut cpu = load(&[0x70, 0x01, 0x12, 0x00]);
+    cpu.set_instructions_per_frame(10);
+    assert_eq!(cpu.run_frame().unwrap(), 10);
+    assert_eq!(cpu.run_frame_limited(3).unwrap(), 3);
+    assert_eq!(cpu.run_frame_limited(50).unwrap(), 10);
+    assert_eq!(cpu.registers()[0], 12);
+}
+
+// The decode cache is on by default, so the tests below run code that has
+// been decoded before it is overwritten.
+
+#[test]
+fn bcd_over_the_next_instruction_is_executed() {
+    // V0 = 123, I = 0x209, call 0x208, store the digits of V0 at I; at
+    // 0x208: VE += 0, return. The digits turn it into VE += 1 and 0203.
+    let mut cpu = load(&[0x60, 0x7B, 0xA2, 0x09, 0x22, 0x08, 0xF0, 0x33, 0x7E, 0x00, 0x00, 0xEE]);
+    step(&mut cpu, 7);
+    assert_eq!((cpu.pc(), cpu.registers()[0xE]), (0x20A, 1));
+    assert_eq!(cpu.step().unwrap_err().to_string(), "illegal opcode 0x0203");
+}
+
+/// V0 = 0x7E, V1 = 0x01, I = 0x20A, call 0x20A, `store`; at 0x20A: return,
+/// which the store turns into VE += 1; loop
+fn store_over_the_next_instruction(store: [u8; 2]) -> Cpu {
+    let mut cpu = load(&[
+        0x60, 0x7E, 0x61, 0x01, 0xA2, 0x0A, 0x22, 0x0A, store[0], store[1], 0x00, 0xEE, 0x12, 0x0C,
+    ]);
+    step(&mut cpu, 7);
+    cpu
+}
+
+#[test]
+fn register_store_over_the_next_instruction_is_executed() {
+    // FX55
+    let cpu = store_over_the_next_instruction([0xF1, 0x55]);
+    assert_eq!((cpu.pc(), cpu.registers()[0xE]), (0x20C, 1));
+    // 5XY2
+    let cpu = store_over_the_next_instruction([0x50, 0x12]);
+    assert_eq!((cpu.pc(), cpu.registers()[0xE]), (0x20C, 1));
+}
+
+#[test]
+fn write_memory_over_the_next_instruction_is_executed() {
+    // VE += 1, loop
+    let mut cpu = load(&[0x7E, 0x01, 0x12, 0x00]);
+    step(&mut cpu, 2);
+    cpu.write_memory(0x200, &[0x7E, 0x05]);
+    step(&mut cpu, 1);
+    assert_eq!(cpu.registers()[0xE], 6);
+
+    // I = 0x1234 with the long load, loop
+    let mut cpu = load(&[0xF0, 0x00, 0x12, 0x34, 0x12, 0x00]);
+    step(&mut cpu, 2);
+    cpu.write_memory(0x202, &[0x0A, 0xBC]);
+    step(&mut cpu, 1);
+    assert_eq!(cpu.i(), 0x0ABC);
+    cpu.write_memory(0x203, &[0xCD]);
+    step(&mut cpu, 2);
+    assert_eq!(cpu.i(), 0x0ACD);
+}
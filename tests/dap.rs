@@ -0,0 +1,176 @@
+extern crate chip8;
+
+use chip8::dap::DapServer;
+use chip8::json::{object, Value};
+use chip8::symbols::{Location, SymbolMap};
+use chip8::Cpu;
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+/// main: V0 = 1, call sub, loop; sub: V0 += 1, return
+const ROM: [u8; 12] = [0x60, 0x01, 0x22, 0x08, 0x12, 0x04, 0x00, 0x00, 0x70, 0x01, 0x00, 0xEE];
+
+/// Each instruction of `ROM` on its own line of game.8o.
+fn symbols() -> SymbolMap {
+    let mut symbols = SymbolMap::default();
+    symbols.labels.insert("main".to_string(), 0x200);
+    symbols.labels.insert("sub".to_string(), 0x208);
+    for (line, &address) in [0x200, 0x202, 0x204, 0x208, 0x20A].iter().enumerate() {
+        let location = Location {
+            file: "game.8o".to_string(),
+            line: line + 1,
+        };
+        symbols.lines.insert(address, location);
+    }
+    symbols
+}
+
+struct Session {
+    server: DapServer,
+    cpu: Cpu,
+    stream: TcpStream,
+    input: Vec<u8>,
+    seq: u64,
+    /// Messages that arrived and have not been looked at yet.
+    messages: Vec<Value>,
+}
+
+impl Session {
+    fn start() -> Session {
+        let mut cpu = Cpu::new();
+        cpu.load_rom(&ROM);
+        let server = DapServer::bind(0, Some(symbols())).unwrap();
+        let stream = TcpStream::connect(("127.0.0.1", server.port().unwrap())).unwrap();
+        stream.set_read_timeout(Some(Duration::from_millis(1))).unwrap();
+        Session {
+            server,
+            cpu,
+            stream,
+            input: Vec::new(),
+            seq: 1,
+            messages: Vec::new(),
+        }
+    }
+
+    /// Sends a request and returns the body of its successful response.
+    fn request(&mut self, command: &str, arguments: Value) -> Value {
+        let seq = self.seq;
+        self.seq += 1;
+        let body = object(vec![
+            ("seq", seq.into()),
+            ("type", "request".into()),
+            ("command", command.into()),
+            ("arguments", arguments),
+        ])
+        .to_string();
+        write!(self.stream, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+
+        let response = self.receive(|message| message["request_seq"].as_u64() == Some(seq));
+        assert_eq!(response["success"].as_bool(), Some(true), "{}", response);
+        response["body"].clone()
+    }
+
+    /// Waits for the event called `name` and returns its body.
+    fn event(&mut self, name: &str) -> Value {
+        self.receive(|message| message["event"].as_str() == Some(name))["body"].clone()
+    }
+
+    /// Runs the server until a message `wanted` arrives.
+    fn receive<F: Fn(&Value) -> bool>(&mut self, wanted: F) -> Value {
+        for _ in 0..1000 {
+            if let Some(n) = self.messages.iter().position(&wanted) {
+                return self.messages.remove(n);
+            }
+            self.server.poll(&mut self.cpu).unwrap();
+            self.server.run_frame(&mut self.cpu);
+
+            let mut buffer = [0; 4096];
+            match self.stream.read(&mut buffer) {
+                Ok(n) => self.input.extend_from_slice(&buffer[..n]),
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut => (),
+                Err(err) => panic!("{}", err),
+            }
+            while let Some(message) = self.next_message() {
+                self.messages.push(message);
+            }
+        }
+        panic!("no such message in {:?}", self.messages.iter().map(Value::to_string).collect::<Vec<_>>());
+    }
+
+    fn next_message(&mut self) -> Option<Value> {
+        let text = String::from_utf8_lossy(&self.input).into_owned();
+        let end = text.find("\r\n\r\n")?;
+        let length: usize = text[..end].trim_start_matches("Content-Length: ").parse().unwrap();
+        let start = end + 4;
+        if text.len() < start + length {
+            return None;
+        }
+        self.input.drain(..start + length);
+        Some(Value::parse(&text[start..start + length]).unwrap())
+    }
+}
+
+fn frame_addresses(trace: &Value) -> Vec<String> {
+    trace["stackFrames"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|frame| frame["instructionPointerReference"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn session() {
+    let mut session = Session::start();
+    let capabilities = session.request("initialize", object(vec![("adapterID", "chip8".into())]));
+    assert_eq!(capabilities["supportsReadMemoryRequest"].as_bool(), Some(true));
+    session.event("initialized");
+
+    session.request("launch", object(vec![("stopOnEntry", true.into())]));
+    let source = object(vec![("path", "/home/someone/game.8o".into())]);
+    let breakpoints = vec![object(vec![("line", 4.into())]), object(vec![("line", 9.into())])];
+    let set = session.request(
+        "setBreakpoints",
+        object(vec![("source", source), ("breakpoints", breakpoints.into())]),
+    );
+    let set = set["breakpoints"].as_array().unwrap();
+    assert_eq!(set[0]["verified"].as_bool(), Some(true));
+    assert_eq!(set[0]["line"].as_u64(), Some(4));
+    assert_eq!(set[1]["verified"].as_bool(), Some(false));
+
+    session.request("configurationDone", object(vec![]));
+    assert_eq!(session.event("stopped")["reason"].as_str(), Some("entry"));
+
+    session.request("continue", object(vec![("threadId", 1.into())]));
+    assert_eq!(session.event("stopped")["reason"].as_str(), Some("breakpoint"));
+    assert_eq!(session.cpu.pc(), 0x208);
+
+    // The caller's frame is at the CALL.
+    let trace = session.request("stackTrace", object(vec![("threadId", 1.into())]));
+    assert_eq!(frame_addresses(&trace), ["0x0208", "0x0202"]);
+    let frames = trace["stackFrames"].as_array().unwrap();
+    assert_eq!(frames[0]["name"].as_str(), Some("sub"));
+    assert_eq!(frames[1]["name"].as_str(), Some("main+2"));
+    assert_eq!(frames[1]["line"].as_u64(), Some(2));
+
+    let variables = session.request("variables", object(vec![("variablesReference", 1.into())]));
+    let v0 = &variables["variables"].as_array().unwrap()[0];
+    assert_eq!(v0["name"].as_str(), Some("V0"));
+    assert_eq!(v0["value"].as_str(), Some("0x01 (1)"));
+
+    let written = session.request(
+        "writeMemory",
+        object(vec![("memoryReference", "0x300".into()), ("data", "AQL/".into())]),
+    );
+    assert_eq!(written["bytesWritten"].as_u64(), Some(3));
+    assert_eq!(session.cpu.memory()[0x300..0x303], [1, 2, 0xFF]);
+    let read = session.request(
+        "readMemory",
+        object(vec![("memoryReference", "0x300".into()), ("count", 4.into())]),
+    );
+    assert_eq!(read["data"].as_str(), Some("AQL/AA=="));
+
+    session.request("disconnect", object(vec![]));
+}
@@ -0,0 +1,81 @@
+extern crate chip8;
+
+use chip8::debugger::Debugger;
+use chip8::Cpu;
+
+/// V0 = 1, call 0x20A, V1 = 2, loop; at 0x20A: V0 += 1, return
+fn machine() -> (Debugger, Cpu) {
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&[0x60, 0x01, 0x22, 0x0A, 0x61, 0x02, 0x12, 0x06, 0x00, 0x00, 0x70, 0x01, 0x00, 0xEE]);
+    (Debugger::new(None), cpu)
+}
+
+/// Runs frames until the debugger stops the program.
+fn run(debugger: &mut Debugger, cpu: &mut Cpu) -> String {
+    for _ in 0..100 {
+        if let Some(stop) = debugger.run_frame(cpu) {
+            return stop;
+        }
+    }
+    panic!("the program did not stop");
+}
+
+#[test]
+fn breakpoint_on_an_opcode_pattern() {
+    let (mut debugger, mut cpu) = machine();
+    assert!(debugger.command("break op 7XKK", &mut cpu).starts_with("breakpoint 1 "));
+    assert_eq!(debugger.command("continue", &mut cpu), "running");
+    assert_eq!(debugger.command("step", &mut cpu), "the program is running, pause it first");
+
+    let stop = run(&mut debugger, &mut cpu);
+    assert!(stop.starts_with("breakpoint 1 "), "{}", stop);
+    assert_eq!(cpu.pc(), 0x20A);
+    assert!(debugger.is_paused());
+}
+
+#[test]
+fn next_runs_a_call_until_it_returns() {
+    let (mut debugger, mut cpu) = machine();
+    debugger.command("step", &mut cpu);
+    assert_eq!(cpu.pc(), 0x202);
+
+    assert_eq!(debugger.command("next", &mut cpu), "running");
+    let stop = run(&mut debugger, &mut cpu);
+    assert!(stop.starts_with("=> 0x0204"), "{}", stop);
+    assert_eq!(cpu.registers()[0], 2);
+
+    // An empty line repeats the step over, which just steps now.
+    debugger.command("", &mut cpu);
+    assert_eq!(cpu.pc(), 0x206);
+}
+
+#[test]
+fn finish_runs_until_the_subroutine_returns() {
+    let (mut debugger, mut cpu) = machine();
+    assert_eq!(debugger.command("finish", &mut cpu), "not in a subroutine");
+    debugger.command("step 2", &mut cpu);
+    assert_eq!((cpu.pc(), cpu.sp()), (0x20A, 1));
+
+    assert_eq!(debugger.command("finish", &mut cpu), "running");
+    run(&mut debugger, &mut cpu);
+    assert_eq!((cpu.pc(), cpu.sp()), (0x204, 0));
+}
+
+#[test]
+fn set_and_poke_change_the_machine() {
+    let (mut debugger, mut cpu) = machine();
+    assert_eq!(debugger.command("set V3 0x12", &mut cpu), "V3 = 0x12");
+    assert_eq!(cpu.registers()[3], 0x12);
+    assert_eq!(debugger.command("set V3 0x100", &mut cpu), "V3 needs a byte");
+    debugger.command("set I $300", &mut cpu);
+    assert_eq!(cpu.i(), 0x300);
+
+    assert_eq!(debugger.command("poke 0x300 1 2 0xFF", &mut cpu), "wrote 3 bytes at 0x0300");
+    assert_eq!(cpu.memory()[0x300..0x303], [1, 2, 0xFF]);
+    assert!(debugger.command("mem 0x300 3", &mut cpu).contains("01 02 FF"));
+
+    // A return outside of a subroutine stops with an error.
+    debugger.command("set PC 0x20C", &mut cpu);
+    assert_eq!(debugger.command("step", &mut cpu), "return without a call");
+    assert_eq!(cpu.pc(), 0x20C);
+}
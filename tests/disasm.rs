@@ -0,0 +1,52 @@
+extern crate chip8;
+
+use chip8::asm;
+use chip8::disasm::Disassembly;
+
+#[test]
+fn labels_inside_instructions_are_listed() {
+    // I = 0x205, the operand of the second instruction; V0 = 0; V1 = 7; loop
+    let rom = [0xA2, 0x05, 0x60, 0x00, 0x61, 0x07, 0x12, 0x06];
+    let listing = Disassembly::new(&rom, 0x200).to_string();
+    assert!(listing.contains("LD I, data_0205"), "{}", listing);
+    assert!(listing.contains("\ndata_0205 = 0x0205\n"), "{}", listing);
+}
+
+/// Disassembles `rom` as assembler source and assembles that again.
+fn round_trip(rom: &[u8]) -> Vec<u8> {
+    let source = Disassembly::new(rom, 0x200).plain().to_string();
+    match asm::assemble(&source) {
+        Ok(program) => program.rom,
+        Err(errors) => panic!("{}\n{:?}", source, errors),
+    }
+}
+
+#[test]
+fn plain_output_assembles_into_the_same_rom() {
+    let rom = [
+        0x00, 0xE0, // CLS
+        0xA2, 0x10, // LD I, data
+        0xF0, 0x00, 0x02, 0x15, // LD I, LONG into the data
+        0x22, 0x0C, // CALL sub
+        0xB2, 0x0E, // JP V0, past the end of the code
+        0xF2, 0x55, // sub: LD [I], V[0...2]
+        0x00, 0xEE, // RET
+        0x12, 0x00, // JP start, only reached through the jump table
+        0xF0, 0x90, 0x90, 0xF0, 0x12, 0x34, 0xFF, // data
+    ];
+    assert_eq!(round_trip(&rom), rom.to_vec());
+}
+
+#[test]
+fn plain_output_keeps_illegal_and_cut_off_instructions() {
+    // an illegal opcode, then a long load missing its operand
+    let rom = [0x50, 0x01, 0xF0, 0x00, 0x12];
+    assert_eq!(round_trip(&rom), rom.to_vec());
+}
+
+#[test]
+fn plain_output_defines_labels_inside_instructions_and_outside_the_rom() {
+    // I = 0x205, V0 = 0, V1 = 7, jump to 0x300 past the end
+    let rom = [0xA2, 0x05, 0x60, 0x00, 0x61, 0x07, 0x13, 0x00];
+    assert_eq!(round_trip(&rom), rom.to_vec());
+}
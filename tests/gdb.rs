@@ -0,0 +1,75 @@
+extern crate chip8;
+
+use chip8::gdb::GdbServer;
+use chip8::Cpu;
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+/// Sends `packet` and runs the server until its reply is complete.
+fn exchange(server: &mut GdbServer, cpu: &mut Cpu, stream: &mut TcpStream, packet: &str) -> String {
+    let checksum = packet.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+    write!(stream, "${}#{:02x}", packet, checksum).unwrap();
+
+    let mut input = Vec::new();
+    for _ in 0..1000 {
+        server.poll(cpu).unwrap();
+        server.run_frame(cpu);
+
+        let mut buffer = [0; 4096];
+        match stream.read(&mut buffer) {
+            Ok(n) => input.extend_from_slice(&buffer[..n]),
+            Err(ref err) if err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut => (),
+            Err(err) => panic!("{}", err),
+        }
+        // Skip the acks.
+        let text = String::from_utf8_lossy(&input).trim_start_matches('+').to_string();
+        if let Some(end) = text.find('#') {
+            if text.len() >= end + 3 {
+                assert!(text.starts_with('$'), "{}", text);
+                return text[1..end].to_string();
+            }
+        }
+    }
+    panic!("no reply to {}", packet);
+}
+
+#[test]
+fn session() {
+    // V0 = 5, V0 += 1, V0 += 2, loop
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&[0x60, 0x05, 0x70, 0x01, 0x70, 0x02, 0x12, 0x06]);
+    let mut server = GdbServer::bind(0).unwrap();
+    let mut stream = TcpStream::connect(("127.0.0.1", server.port().unwrap())).unwrap();
+    stream.set_read_timeout(Some(Duration::from_millis(1))).unwrap();
+    let mut send = |packet: &str| exchange(&mut server, &mut cpu, &mut stream, packet);
+
+    assert!(send("qSupported:swbreak+").contains("qXfer:features:read+"));
+    // Unknown packets, even ones that are not ASCII, get an empty reply.
+    assert_eq!(send("\u{e9}"), "");
+    assert_eq!(send(""), "");
+    let xml = send("qXfer:features:read:target.xml:0,fff");
+    assert!(xml.starts_with("l<?xml"), "{}", xml);
+    assert!(xml.contains("<reg name=\"pc\" bitsize=\"16\""));
+
+    // V0-VF, then I, PC, SP, DT and ST.
+    let registers = send("g");
+    assert_eq!(registers.len(), 46);
+    assert_eq!(&registers[36..40], "0200");
+
+    assert_eq!(send("M300,2:abcd"), "OK");
+    assert_eq!(send("m300,2"), "abcd");
+    assert_eq!(send("mffff,4"), "00");
+
+    assert_eq!(send("Z0,204,2"), "OK");
+    assert_eq!(send("c"), "S05");
+    assert_eq!(send("p11"), "0204");
+    assert_eq!(send("p0"), "06");
+
+    assert_eq!(send("s"), "S05");
+    assert_eq!(send("p11"), "0206");
+    assert_eq!(send("p0"), "08");
+
+    assert_eq!(send("D"), "OK");
+}
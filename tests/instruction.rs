@@ -0,0 +1,24 @@
+extern crate chip8;
+
+use chip8::Instruction;
+
+use std::collections::HashSet;
+
+/// Every opcode, followed by the operand word of the long load.
+fn every_opcode() -> impl Iterator<Item = [u8; 4]> {
+    (0..=0xFFFFu16).map(|opcode| [(opcode >> 8) as u8, opcode as u8, 0x12, 0x34])
+}
+
+#[test]
+fn every_instruction_encodes_to_the_bytes_it_decodes_from() {
+    let mut names = HashSet::new();
+    for memory in every_opcode() {
+        let instruction = Instruction::decode_at(&memory, 0);
+        let size = instruction.size() as usize;
+        assert_eq!(instruction.encode(), &memory[..size], "{:?}", instruction);
+        assert_eq!(Instruction::decode_at(&instruction.encode(), 0), instruction);
+        names.insert(instruction.name());
+    }
+    // Every variant decodes from some opcode.
+    assert_eq!(names.len(), 51);
+}
@@ -0,0 +1,106 @@
+extern crate chip8;
+
+use chip8::lint::{lint, Severity};
+
+/// What `lint` finds in `rom` as address, severity and message.
+fn diagnostics(rom: &[u8]) -> Vec<(u16, Severity, String)> {
+    lint(rom)
+        .into_iter()
+        .map(|diagnostic| (diagnostic.address, diagnostic.severity, diagnostic.message))
+        .collect()
+}
+
+fn warning(address: u16, message: &str) -> (u16, Severity, String) {
+    (address, Severity::Warning, message.to_string())
+}
+
+fn note(address: u16, message: &str) -> (u16, Severity, String) {
+    (address, Severity::Note, message.to_string())
+}
+
+#[test]
+fn reachable_illegal_opcode() {
+    // skip if V0 is 0, the illegal 5001, loop
+    let rom = [0x30, 0x00, 0x50, 0x01, 0x12, 0x04];
+    assert_eq!(diagnostics(&rom), [warning(0x202, "illegal opcode 0x5001 can be reached")]);
+}
+
+#[test]
+fn calls_nested_deeper_than_the_stack() {
+    // main calls the first of 17 routines and loops; each routine calls the
+    // next one and returns, the last one sets V0 instead
+    let mut rom = vec![0x22, 0x04, 0x12, 0x02];
+    for routine in 0..17u16 {
+        let next = 0x208 + 4 * routine;
+        let call = if routine < 16 { [0x20 | (next >> 8) as u8, next as u8] } else { [0x60, 0x00] };
+        rom.extend_from_slice(&[call[0], call[1], 0x00, 0xEE]);
+    }
+    assert_eq!(
+        diagnostics(&rom),
+        [warning(0x240, "calls can nest 17 deep here, the stack holds 16")]
+    );
+}
+
+#[test]
+fn recursive_call() {
+    // main calls 0x204 and loops; at 0x204: unless V0 is 0, call 0x204, return
+    let rom = [0x22, 0x04, 0x12, 0x02, 0x30, 0x00, 0x22, 0x04, 0x00, 0xEE];
+    assert_eq!(
+        diagnostics(&rom),
+        [warning(
+            0x206,
+            "this call is recursive, 0x0204 can call itself until the 16 entry stack overflows"
+        )]
+    );
+}
+
+#[test]
+fn return_in_main() {
+    // V0 = 1, return
+    let rom = [0x60, 0x01, 0x00, 0xEE];
+    assert_eq!(
+        diagnostics(&rom),
+        [warning(0x202, "RET outside a subroutine, there is no call to return from")]
+    );
+}
+
+#[test]
+fn sprite_past_the_end_of_memory() {
+    // I = 0xFFC, draw 8 rows, loop
+    let rom = [0xAF, 0xFC, 0xD0, 0x18, 0x12, 0x04];
+    assert_eq!(
+        diagnostics(&rom),
+        [warning(
+            0x202,
+            "the sprite is read from 0x0FFC to 0x1003, past the end of memory at 0x0FFF"
+        )]
+    );
+}
+
+#[test]
+fn store_over_code() {
+    // I = 0x206, store V0 and V1 there, V0 = 0, loop
+    let rom = [0xA2, 0x06, 0xF1, 0x55, 0x60, 0x00, 0x12, 0x06];
+    assert_eq!(
+        diagnostics(&rom),
+        [warning(0x202, "this writes 0x0206 to 0x0207, over the instruction at 0x0206")]
+    );
+}
+
+#[test]
+fn quirk_notes() {
+    // V0 = V1 >> 1, I = 0x300, load V0 and V1, draw with I, jump to
+    // 0x300 + V0
+    let rom = [0x80, 0x16, 0xA3, 0x00, 0xF1, 0x65, 0xD0, 0x11, 0xB3, 0x00];
+    assert_eq!(
+        diagnostics(&rom),
+        [
+            note(0x200, "this shifts V1 into V0 with the shift quirk and V0 in place without it"),
+            note(
+                0x204,
+                "I is used at 0x0206, where it has moved past the registers only with the load-store quirk"
+            ),
+            note(0x208, "this jumps to 0x0300 + V0, or to 0x0300 + V3 with the jump quirk"),
+        ]
+    );
+}
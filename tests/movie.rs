@@ -0,0 +1,65 @@
+extern crate chip8;
+
+use chip8::movie::Movie;
+use chip8::rng::Generator;
+use chip8::{Cpu, Quirks};
+
+/// Waits for a key and draws a pixel at the key's column and a random row.
+const ROM: [u8; 11] = [0xF0, 0x0A, 0xC1, 0x1F, 0xA2, 0x0A, 0xD0, 0x11, 0x12, 0x00, 0x80];
+
+fn machine() -> Cpu {
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&ROM);
+    cpu
+}
+
+#[test]
+fn a_recorded_movie_replays_identically() {
+    let mut cpu = machine();
+    cpu.seed_rng(Generator::Vip, 42);
+    cpu.set_instructions_per_frame(11);
+    cpu.set_quirks(Quirks::vip());
+    let mut movie = Movie::new(&cpu).unwrap();
+    for frame in 0..120u16 {
+        // Press a different key every few frames and let go in between.
+        cpu.set_key_mask(if frame % 6 < 3 { 1 << (frame / 6 % 16) } else { 0 });
+        movie.record(&cpu);
+        cpu.run_frame().unwrap();
+    }
+
+    let parsed = Movie::parse(&movie.to_string()).unwrap();
+    assert_eq!(parsed, movie);
+
+    let mut replay = machine();
+    parsed.configure(&mut replay);
+    let mut frame = 0;
+    while parsed.play(frame, &mut replay) {
+        replay.run_frame().unwrap();
+        frame += 1;
+    }
+    assert_eq!(frame, 120);
+    assert_eq!(replay.save_state(), cpu.save_state());
+}
+
+#[test]
+fn parse_errors_name_the_line() {
+    let error = |text: &str| {
+        let err = Movie::parse(text).unwrap_err();
+        (err.line, err.message)
+    };
+    let header = "chip8-movie 1\nrng xorshift 1\nipf 8\n";
+
+    assert_eq!(error("chip8-movie 2\n").0, 1);
+    assert_eq!(
+        error(&format!("{}quirk shift maybe\nframes\n", header)),
+        (4, "expected on or off, got \"maybe\"".to_string())
+    );
+    assert_eq!(
+        error(&format!("{}quirk wobble on\nframes\n", header)),
+        (4, "unknown quirk \"wobble\"".to_string())
+    );
+    assert_eq!(error(&format!("{}frames\n0000\nzz\n", header)), (6, "invalid key mask \"zz\"".to_string()));
+    assert_eq!(error(header).1, "no frames section");
+    assert_eq!(error("chip8-movie 1\nipf 8\nframes\n").1, "no rng line");
+    assert_eq!(error("chip8-movie 1\nrng xorshift x\n"), (2, "invalid seed \"x\"".to_string()));
+}
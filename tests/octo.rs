@@ -0,0 +1,106 @@
+extern crate chip8;
+
+use chip8::{octo, Cpu};
+
+/// Compiles and runs `source` until it exits.
+fn run(source: &str) -> Cpu {
+    let program = octo::compile(source).unwrap_or_else(|err| panic!("{}\n{}", err, source));
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&program.rom);
+    for _ in 0..100_000 {
+        if cpu.exited() {
+            return cpu;
+        }
+        cpu.step().unwrap();
+    }
+    panic!("{} did not exit", source);
+}
+
+const VALUES: [u8; 7] = [0, 1, 5, 0x7F, 0x80, 0xFE, 0xFF];
+
+type Comparison = fn(u8, u8) -> bool;
+
+#[test]
+fn comparisons() {
+    let tests: [(&str, Comparison); 6] = [
+        ("==", |a, b| a == b),
+        ("!=", |a, b| a != b),
+        ("<", |a, b| a < b),
+        (">", |a, b| a > b),
+        ("<=", |a, b| a <= b),
+        (">=", |a, b| a >= b),
+    ];
+    for &(test, expected) in &tests {
+        for &a in &VALUES {
+            for &b in &VALUES {
+                // against a register and against a constant
+                for operand in &["v1".to_string(), b.to_string()] {
+                    let source = format!(
+                        ": main\n  v0 := {}\n  v1 := {}\n  v2 := 0\n  if v0 {} {} then v2 := 1\n  exit",
+                        a, b, test, operand
+                    );
+                    let cpu = run(&source);
+                    assert_eq!(cpu.registers()[2] == 1, expected(a, b), "{}", source);
+                    assert_eq!(cpu.registers()[0], a, "{}", source);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn if_begin_else_end() {
+    let source = ": main
+        v0 := 3
+        if v0 > 2 begin v1 := 1 else v1 := 2 end
+        if v0 > 3 begin v2 := 1 else v2 := 2 end
+        exit";
+    let cpu = run(source);
+    assert_eq!(cpu.registers()[1], 1);
+    assert_eq!(cpu.registers()[2], 2);
+}
+
+#[test]
+fn loops() {
+    let source = ": main
+        v0 := 0
+        v1 := 0
+        loop
+          v0 += 1
+          v2 := 0
+          loop
+            while v2 != 3
+            v2 += 1
+            v1 += 1
+          again
+          while v0 < 10
+        again
+        exit";
+    let cpu = run(source);
+    assert_eq!(cpu.registers()[0], 10);
+    assert_eq!(cpu.registers()[1], 30);
+}
+
+#[test]
+fn calc_evaluates_right_to_left() {
+    let source = ":calc A { 2 * 3 + 1 }
+        :calc B { A - 1 }
+        :calc C { ( 2 * 3 ) + 1 }
+        :calc D { 0xFF & ~ 1 }
+        : main
+        v0 := A
+        v1 := B
+        v2 := C
+        v3 := D
+        exit";
+    let cpu = run(source);
+    assert_eq!(&cpu.registers()[..4], &[8, 7, 7, 0xFE]);
+}
+
+#[test]
+fn errors_name_their_line() {
+    let err = octo::compile(": main\n  v0 := 1\n  loop\n  v0 += 1\n  exit").err().unwrap();
+    assert!(err.message.contains("loop"), "{}", err);
+    let err = octo::compile(": main\n  v0 := 1\n\n  v0 frobnicate 3").err().unwrap();
+    assert_eq!(err.location.line, 4, "{}", err);
+}
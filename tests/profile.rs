@@ -0,0 +1,57 @@
+extern crate chip8;
+
+use chip8::profile::Profiler;
+use chip8::Cpu;
+
+/// main: V0 = 3, call rec, DT = 5, wait for DT to reach 0, halt
+/// rec at 0x210: V0 -= 1, call rec again unless V0 is 0, return
+const ROM: [u8; 24] = [
+    0x60, 0x03, 0x22, 0x10, 0x61, 0x05, 0xF1, 0x15, 0xF2, 0x07, 0x32, 0x00, 0x12, 0x08, 0x12, 0x0E,
+    0x70, 0xFF, 0x30, 0x00, 0x22, 0x10, 0x00, 0xEE,
+];
+
+fn profile() -> (Profiler, Cpu) {
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&ROM);
+    cpu.set_profiler(Profiler::new());
+    for _ in 0..10 {
+        cpu.run_frame().unwrap();
+    }
+    (cpu.take_profiler().unwrap(), cpu)
+}
+
+/// The columns of the report line that contains `text`.
+fn columns<'a>(report: &'a str, text: &str) -> Vec<&'a str> {
+    let line = report.lines().find(|line| line.contains(text));
+    line.unwrap_or_else(|| panic!("no {} in\n{}", text, report)).split_whitespace().collect()
+}
+
+#[test]
+fn hits_routines_and_loops() {
+    let (profiler, cpu) = profile();
+    assert_eq!(profiler.cycles(), 80);
+    assert_eq!(profiler.hits(0x200), 1);
+    assert_eq!(profiler.hits(0x210), 3);
+    assert_eq!(profiler.hits(0x214), 2);
+    assert_eq!(profiler.hits(0x216), 3);
+    assert_eq!(cpu.delay_timer(), 0);
+
+    let report = profiler.report(cpu.memory(), None);
+    // The recursion is counted once, from the first call at cycle 2 to the
+    // last return at cycle 13.
+    assert_eq!(columns(&report, " 0210 ")[..3], ["11", "13.75%", "3"]);
+
+    // The jump back to the poll is taken every time but the last.
+    let iterations = profiler.hits(0x20C);
+    assert_eq!(profiler.hits(0x208), iterations + 1);
+    let poll = columns(&report, "0208-020C");
+    assert_eq!(poll[2], iterations.to_string());
+    let poll_cycles = profiler.hits(0x208) + profiler.hits(0x20A) + profiler.hits(0x20C);
+    assert_eq!(poll[0], poll_cycles.to_string());
+
+    let halt = columns(&report, "020E-020E");
+    assert_eq!(halt[2], profiler.hits(0x20E).to_string());
+
+    // Only the poll waits for the delay timer, not the halt loop.
+    assert_eq!(columns(&report, "polling the delay timer")[0], poll_cycles.to_string());
+}
@@ -0,0 +1,153 @@
+extern crate chip8;
+
+use chip8::{Cpu, Quirks};
+
+/// A CPU running `program` from 0x200 with the quirk called `name` turned
+/// on or off and the others as in the default profile.
+fn load(name: &str, enabled: bool, program: &[u8]) -> Cpu {
+    let mut quirks = Quirks::default();
+    assert!(quirks.set(name, enabled));
+    let mut cpu = Cpu::new();
+    cpu.set_quirks(quirks);
+    cpu.load_rom(program);
+    cpu
+}
+
+fn run(name: &str, enabled: bool, program: &[u8], steps: usize) -> Cpu {
+    let mut cpu = load(name, enabled, program);
+    for _ in 0..steps {
+        cpu.step().unwrap();
+    }
+    cpu
+}
+
+#[test]
+fn shift() {
+    // V0 = 0x01, V1 = 0x80, V0 >>= V1
+    let program = [0x60, 0x01, 0x61, 0x80, 0x80, 0x16];
+
+    let cpu = run("shift", true, &program, 3);
+    assert_eq!(cpu.registers()[0], 0x40);
+    assert_eq!(cpu.registers()[0xF], 0);
+
+    let cpu = run("shift", false, &program, 3);
+    assert_eq!(cpu.registers()[0], 0x00);
+    assert_eq!(cpu.registers()[0xF], 1);
+}
+
+#[test]
+fn load_store() {
+    // I = 0x300, save V0-V1, load V0-V1
+    let program = [0xA3, 0x00, 0xF1, 0x55, 0xF1, 0x65];
+
+    let cpu = run("load-store", true, &program, 2);
+    assert_eq!(cpu.i(), 0x302);
+    let cpu = run("load-store", true, &program, 3);
+    assert_eq!(cpu.i(), 0x304);
+
+    let cpu = run("load-store", false, &program, 3);
+    assert_eq!(cpu.i(), 0x300);
+}
+
+#[test]
+fn vf_reset() {
+    // VF = 0x05, V0 = 0x01, V1 = 0x02, V0 |= V1
+    let program = [0x6F, 0x05, 0x60, 0x01, 0x61, 0x02, 0x80, 0x11];
+
+    let cpu = run("vf-reset", true, &program, 4);
+    assert_eq!(cpu.registers()[0], 0x03);
+    assert_eq!(cpu.registers()[0xF], 0);
+
+    let cpu = run("vf-reset", false, &program, 4);
+    assert_eq!(cpu.registers()[0], 0x03);
+    assert_eq!(cpu.registers()[0xF], 5);
+}
+
+#[test]
+fn clip() {
+    // V0 = 60, V1 = 0, I = 0x208, draw the 8 pixel wide row at 0x208
+    let program = [0x60, 60, 0x61, 0x00, 0xA2, 0x08, 0xD0, 0x11, 0xFF, 0x00];
+
+    let cpu = run("clip", true, &program, 4);
+    assert_eq!(cpu.pixel(63, 0), 1);
+    assert_eq!(cpu.pixel(0, 0), 0);
+
+    let cpu = run("clip", false, &program, 4);
+    assert_eq!(cpu.pixel(63, 0), 1);
+    assert_eq!(cpu.pixel(0, 0), 1);
+}
+
+#[test]
+fn jump() {
+    // V0 = 0x04, V2 = 0x08, jump to 0x204 + V0 or, as BXNN, + V2
+    let program = [0x60, 0x04, 0x62, 0x08, 0xB2, 0x04];
+
+    let cpu = run("jump", true, &program, 3);
+    assert_eq!(cpu.pc(), 0x20C);
+
+    let cpu = run("jump", false, &program, 3);
+    assert_eq!(cpu.pc(), 0x208);
+}
+
+#[test]
+fn display_wait() {
+    // I = 0x204, draw the one pixel sprite at 0x204
+    let program = [0xA2, 0x04, 0xD0, 0x01, 0x80, 0x00];
+
+    let mut cpu = run("display-wait", true, &program, 2);
+    assert!(cpu.waiting_for_vblank());
+    assert_eq!(cpu.pc(), 0x202);
+    assert_eq!(cpu.pixel(0, 0), 0);
+    cpu.end_frame();
+    cpu.step().unwrap();
+    assert_eq!(cpu.pc(), 0x204);
+    assert_eq!(cpu.pixel(0, 0), 1);
+
+    let cpu = run("display-wait", false, &program, 2);
+    assert!(!cpu.waiting_for_vblank());
+    assert_eq!(cpu.pc(), 0x204);
+    assert_eq!(cpu.pixel(0, 0), 1);
+}
+
+#[test]
+fn key_release() {
+    // wait for a key into V0
+    let program = [0xF0, 0x0A];
+
+    let mut cpu = load("key-release", true, &program);
+    cpu.step().unwrap();
+    cpu.set_key_mask(1 << 5);
+    cpu.step().unwrap();
+    assert!(cpu.waiting_for_key());
+    assert_eq!(cpu.pc(), 0x200);
+    cpu.set_key_mask(0);
+    cpu.step().unwrap();
+    assert_eq!(cpu.registers()[0], 5);
+    assert_eq!(cpu.pc(), 0x202);
+
+    let mut cpu = load("key-release", false, &program);
+    cpu.step().unwrap();
+    cpu.set_key_mask(1 << 5);
+    cpu.step().unwrap();
+    assert_eq!(cpu.registers()[0], 5);
+    assert_eq!(cpu.pc(), 0x202);
+}
+
+#[test]
+fn profiles() {
+    let names = |quirks: Quirks| -> Vec<&str> {
+        chip8::quirks::QUIRK_NAMES
+            .iter()
+            .cloned()
+            .filter(|&name| quirks.get(name).unwrap())
+            .collect()
+    };
+    assert_eq!(
+        names(Quirks::vip()),
+        ["shift", "load-store", "vf-reset", "clip", "display-wait", "key-release"]
+    );
+    assert_eq!(names(Quirks::schip()), ["clip", "jump", "key-release"]);
+    assert_eq!(names(Quirks::xochip()), ["shift", "load-store", "key-release"]);
+    assert_eq!("schip".parse::<Quirks>().unwrap(), Quirks::schip());
+    assert!("chip48".parse::<Quirks>().is_err());
+}
@@ -0,0 +1,59 @@
+extern crate chip8;
+
+use chip8::rewind::Rewind;
+
+/// A state of `len` bytes where frame `n` changed a few scattered bytes and
+/// a run long enough to need a multi-byte length.
+fn state(n: usize, len: usize) -> Vec<u8> {
+    let mut state: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+    for i in (n..len).step_by(97) {
+        state[i] = n as u8;
+    }
+    for byte in state.iter_mut().skip(1000).take(200 + n) {
+        *byte = !*byte;
+    }
+    state
+}
+
+#[test]
+fn steps_back_through_changing_states() {
+    let states: Vec<Vec<u8>> = (0..10).map(|n| state(n, 4096)).collect();
+    let mut rewind = Rewind::new(100);
+    for state in &states {
+        rewind.push(state.clone());
+    }
+    assert_eq!(rewind.len(), 9);
+    assert!(rewind.memory_usage() < 4 * 4096);
+
+    for expected in states.iter().rev().skip(1) {
+        assert_eq!(rewind.step_back(), Some(&expected[..]));
+    }
+    assert_eq!(rewind.step_back(), None);
+    assert!(rewind.is_empty());
+}
+
+#[test]
+fn a_state_of_another_length_is_kept_whole() {
+    let mut rewind = Rewind::new(10);
+    rewind.push(state(1, 100));
+    rewind.push(state(2, 4096));
+    rewind.push(state(3, 4096));
+
+    assert_eq!(rewind.step_back(), Some(&state(2, 4096)[..]));
+    assert_eq!(rewind.step_back(), Some(&state(1, 100)[..]));
+    assert_eq!(rewind.step_back(), None);
+}
+
+#[test]
+fn the_oldest_frames_are_forgotten() {
+    let mut rewind = Rewind::new(3);
+    for n in 0..6 {
+        rewind.push(state(n, 2048));
+    }
+    assert_eq!(rewind.len(), 3);
+
+    for n in (2..5).rev() {
+        assert_eq!(rewind.step_back(), Some(&state(n, 2048)[..]));
+    }
+    assert_eq!(rewind.step_back(), None);
+}
@@ -0,0 +1,44 @@
+extern crate chip8;
+
+use chip8::rng::{RandomSource, VipRandom, Xorshift};
+
+fn bytes(source: &mut dyn RandomSource) -> Vec<u8> {
+    (0..256).map(|_| source.next_byte()).collect()
+}
+
+/// Fails for a generator stuck on one value.
+fn assert_varies(bytes: &[u8]) {
+    assert!(bytes.iter().any(|&byte| byte != bytes[0]), "{:?}", bytes);
+}
+
+#[test]
+fn xorshift_is_not_stuck_for_any_seed() {
+    for &seed in &[0, 1, 0x9E37_79B9_7F4A_7C15, !0] {
+        assert_varies(&bytes(&mut Xorshift::new(seed)));
+    }
+}
+
+#[test]
+fn xorshift_is_determined_by_its_seed() {
+    assert_eq!(bytes(&mut Xorshift::new(42)), bytes(&mut Xorshift::new(42)));
+    assert_ne!(bytes(&mut Xorshift::new(42)), bytes(&mut Xorshift::new(43)));
+}
+
+#[test]
+fn vip_random_reads_the_interpreter() {
+    for &seed in &[0, 0x1234] {
+        assert_varies(&bytes(&mut VipRandom::new(seed)));
+    }
+}
+
+#[test]
+fn vip_random_restores_its_state() {
+    let mut source = VipRandom::new(7);
+    source.next_byte();
+    source.frame();
+    let state = source.save();
+    let expected = bytes(&mut source);
+    let mut restored = VipRandom::new(0);
+    assert!(restored.restore(&state));
+    assert_eq!(bytes(&mut restored), expected);
+}
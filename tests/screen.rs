@@ -0,0 +1,57 @@
+extern crate chip8;
+
+use chip8::screen;
+use chip8::Cpu;
+
+/// The font's 0 at the top left in the first plane and a pixel at 5, 0 in
+/// the second.
+fn screen() -> Cpu {
+    // V0 = 0, I = font 0, draw, plane 2, V1 = 5, I = 0x210, draw, loop
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&[
+        0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0xF2, 0x01, 0x61, 0x05, 0xA2, 0x10, 0xD1, 0x01, 0x12, 0x0E,
+        0x80,
+    ]);
+    for _ in 0..7 {
+        cpu.step().unwrap();
+    }
+    cpu
+}
+
+/// The rows of the screen with `rows` at the top, padded with unlit pixels.
+fn golden(rows: &[&str], unlit: &str, separator: &str) -> Vec<String> {
+    (0..32)
+        .map(|y| {
+            let row = rows.get(y).cloned().unwrap_or("");
+            let mut pixels: Vec<String> = row.chars().map(|c| c.to_string()).collect();
+            pixels.resize(64, unlit.to_string());
+            pixels.join(separator)
+        })
+        .collect()
+}
+
+#[test]
+fn ascii() {
+    let rows = golden(&["####.+", "#..#", "#..#", "#..#", "####"], ".", "");
+    assert_eq!(screen::ascii(&screen()), rows.join("\n") + "\n");
+}
+
+#[test]
+fn pbm() {
+    let rows = golden(&["111101", "1001", "1001", "1001", "1111"], "0", " ");
+    assert_eq!(screen::pbm(&screen()), format!("P1\n64 32\n{}\n", rows.join("\n")));
+}
+
+#[test]
+fn png_header() {
+    let png = screen::png(&screen());
+    assert_eq!(png[..8], *b"\x89PNG\r\n\x1a\n");
+
+    // Length, type, 64x32, 8-bit indexed, and the CRC of type and data.
+    let ihdr = &png[8..33];
+    assert_eq!(ihdr[..8], *b"\0\0\0\x0dIHDR");
+    assert_eq!(ihdr[8..21], [0, 0, 0, 64, 0, 0, 0, 32, 8, 3, 0, 0, 0]);
+    assert_eq!(ihdr[21..], 0x9543_8EB6u32.to_be_bytes());
+
+    assert_eq!(png[png.len() - 12..], *b"\0\0\0\0IEND\xae\x42\x60\x82");
+}
@@ -0,0 +1,74 @@
+extern crate chip8;
+
+use chip8::rng::Generator;
+use chip8::state::{StateError, VERSION};
+use chip8::Cpu;
+
+/// Where the stack pointer is in a state: after the magic, the version,
+/// the registers, the stack, I and PC.
+const SP_OFFSET: usize = 4 + 2 + 16 + 32 + 2 + 2;
+
+/// Draws a pixel at random coordinates forever.
+fn machine() -> Cpu {
+    // I = 0x20A, V0 = random, V1 = random, draw, loop
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&[0xA2, 0x0A, 0xC0, 0x3F, 0xC1, 0x1F, 0xD0, 0x11, 0x12, 0x02, 0x80]);
+    cpu.seed_rng(Generator::Xorshift, 7);
+    cpu
+}
+
+fn run_frames(cpu: &mut Cpu, frames: usize) {
+    for _ in 0..frames {
+        cpu.run_frame().unwrap();
+    }
+}
+
+#[test]
+fn save_load_save_is_identical() {
+    let mut cpu = machine();
+    run_frames(&mut cpu, 10);
+    let state = cpu.save_state();
+
+    let mut restored = Cpu::new();
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.save_state(), state);
+}
+
+#[test]
+fn a_reloaded_machine_runs_the_same() {
+    let mut cpu = machine();
+    run_frames(&mut cpu, 10);
+    let state = cpu.save_state();
+    run_frames(&mut cpu, 50);
+
+    let mut restored = Cpu::new();
+    restored.load_state(&state).unwrap();
+    run_frames(&mut restored, 50);
+    assert_eq!(restored.save_state(), cpu.save_state());
+    assert_eq!(restored.vram[..], cpu.vram[..]);
+}
+
+#[test]
+fn invalid_states_are_rejected() {
+    let state = machine().save_state();
+    let mut cpu = machine();
+    let before = cpu.save_state();
+
+    let mut bad_magic = state.clone();
+    bad_magic[0] = b'X';
+    assert_eq!(cpu.load_state(&bad_magic), Err(StateError::BadMagic));
+
+    let mut newer = state.clone();
+    newer[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+    assert_eq!(cpu.load_state(&newer), Err(StateError::UnsupportedVersion(VERSION + 1)));
+
+    assert_eq!(cpu.load_state(&state[..state.len() - 1]), Err(StateError::Truncated));
+    assert_eq!(cpu.load_state(&state[..3]), Err(StateError::BadMagic));
+
+    let mut bad_sp = state.clone();
+    bad_sp[SP_OFFSET..SP_OFFSET + 2].copy_from_slice(&17u16.to_le_bytes());
+    assert_eq!(cpu.load_state(&bad_sp), Err(StateError::Invalid("stack pointer")));
+
+    // Nothing was changed by the failed loads.
+    assert_eq!(cpu.save_state(), before);
+}
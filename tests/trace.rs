@@ -0,0 +1,104 @@
+extern crate chip8;
+
+use chip8::trace::{self, Format, Record, Tracer};
+use chip8::Cpu;
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// V0 = 5, I = 0x1234 with the long load, V0 += 1, loop
+const ROM: [u8; 10] = [0x60, 0x05, 0xF0, 0x00, 0x12, 0x34, 0x70, 0x01, 0x12, 0x08];
+const STEPS: usize = 5;
+
+/// Keeps what a `Tracer` writes where the test can still read it.
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(data)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn machine() -> Cpu {
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&ROM);
+    cpu
+}
+
+/// The records of the run, taken by hand.
+fn expected() -> Vec<Record> {
+    let mut cpu = machine();
+    (0..STEPS as u64)
+        .map(|cycle| {
+            let record = Record::new(cycle, &cpu);
+            cpu.step().unwrap();
+            record
+        })
+        .collect()
+}
+
+fn traced(format: Format) -> Vec<u8> {
+    let out = Shared::default();
+    let mut cpu = machine();
+    cpu.set_tracer(Tracer::new(Box::new(out.clone()), format).unwrap());
+    for _ in 0..STEPS {
+        cpu.step().unwrap();
+    }
+    cpu.take_tracer().unwrap().finish().unwrap();
+    let data = out.0.borrow().clone();
+    data
+}
+
+#[test]
+fn both_formats_read_back() {
+    let expected = expected();
+    assert_eq!(expected[1].opcode, [0xF0, 0x00, 0x12, 0x34]);
+    assert_eq!(expected[2].i, 0x1234);
+
+    assert_eq!(trace::read(&traced(Format::Json)).unwrap(), expected);
+    assert_eq!(trace::read(&traced(Format::Binary)).unwrap(), expected);
+}
+
+#[test]
+fn diff_finds_the_first_differing_fields() {
+    let left = expected();
+    assert_eq!(trace::diff(left.clone(), left.clone()), None);
+
+    let mut right = left.clone();
+    right[3].registers[0] = 0x42;
+    right[3].i = 0;
+    right[4].pc = 0;
+    let divergence = trace::diff(left.clone(), right.clone()).unwrap();
+    assert_eq!(divergence.index, 3);
+    assert_eq!(divergence.fields, ["v0", "i"]);
+    assert_eq!(divergence.left, Some(left[3]));
+    assert_eq!(divergence.right, Some(right[3]));
+}
+
+#[test]
+fn diff_reports_a_trace_that_ends_early() {
+    let left = expected();
+    let divergence = trace::diff(left.clone(), left[..3].to_vec()).unwrap();
+    assert_eq!(divergence.index, 3);
+    assert!(divergence.fields.is_empty());
+    assert_eq!(divergence.left, Some(left[3]));
+    assert_eq!(divergence.right, None);
+}
+
+#[test]
+fn truncated_binary_traces_are_errors() {
+    let data = traced(Format::Binary);
+    let err = trace::read(&data[..data.len() - 1]).unwrap_err();
+    assert_eq!((err.record, err.message.as_str()), (STEPS - 1, "truncated"));
+
+    // Cut inside the second half of the long load's opcode.
+    let record = 8 + 2 + 2 + 16 + 5;
+    let err = trace::read(&data[..6 + record + 8 + 2 + 3]).unwrap_err();
+    assert_eq!((err.record, err.message.as_str()), (1, "truncated"));
+}